@@ -0,0 +1,482 @@
+//! Run the arbitrage detector over recorded frames and report the opportunities it would have published.
+//!
+//! ```bash
+//! cargo run --release --example backtest -- recordings \
+//!     --fee 0.001 --fee coinbase=0.004 --enter 0.1 --exit 0.05 --min-ticks 2 \
+//!     --sweep 0,0.05,0.1,0.25,0.5 --output report.json
+//! ```
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::sync::broadcast::{error::TryRecvError, Receiver};
+
+use arbitrage_detector::{
+    config::Config,
+    detector::{ArbitrageDetector, FeeSchedule},
+    logger,
+    models::{OpportunityEventKind, StreamEvent, TrackedOpportunity},
+    socket::{socket_consumer::SocketConsumer, socket_container::replay_container::Replayer},
+    AppState,
+};
+
+const USAGE: &str = "Usage: backtest <recording file or directory> [options]
+
+Options:
+  --fee <rate>                 Taker fee of every exchange, as a fraction (default: public base tier fees)
+  --fee <exchange>=<rate>      Taker fee of one exchange
+  --enter <percentage>         Net profit percentage a window opens at (default: 0)
+  --exit <percentage>          Net profit percentage a window closes below (default: 0)
+  --min-ticks <count>          Ticks above the enter threshold before a window opens (default: 1)
+  --min-persistence-ms <ms>    Time above the enter threshold before a window opens (default: 0)
+  --max-quantity <quantity>    Cap on the base quantity sized per opportunity
+  --sweep <p1,p2,...>          Enter thresholds to sweep (default: 0,0.05,0.1,0.25,0.5,1)
+  --output <file>              Also write the report as JSON";
+
+#[derive(Debug, Clone, Serialize)]
+struct BacktestConfig {
+    default_fee: Option<f64>,
+    fees: BTreeMap<String, f64>,
+    enter_threshold: f64,
+    exit_threshold: f64,
+    min_ticks: u64,
+    min_persistence_ms: u64,
+    max_quantity: Option<f64>,
+}
+
+impl BacktestConfig {
+    fn fee_schedule(&self) -> FeeSchedule {
+        let base = match self.default_fee {
+            Some(fee) => FeeSchedule::new(fee),
+            None => FeeSchedule::default(),
+        };
+        self.fees.iter().fold(base, |schedule, (exchange, fee)| schedule.with_fee(exchange, *fee))
+    }
+
+    /// Same settings with another enter threshold, the exit threshold never exceeding it
+    fn with_enter_threshold(&self, enter_threshold: f64) -> Self {
+        BacktestConfig {
+            enter_threshold,
+            exit_threshold: self.exit_threshold.min(enter_threshold),
+            ..self.clone()
+        }
+    }
+}
+
+struct Arguments {
+    recording: String,
+    config: BacktestConfig,
+    sweep: Vec<f64>,
+    output: Option<String>,
+}
+
+impl Arguments {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut recording = None;
+        let mut config = BacktestConfig {
+            default_fee: None,
+            fees: BTreeMap::new(),
+            enter_threshold: 0.0,
+            exit_threshold: 0.0,
+            min_ticks: 1,
+            min_persistence_ms: 0,
+            max_quantity: None,
+        };
+        let mut sweep = vec![0.0, 0.05, 0.1, 0.25, 0.5, 1.0];
+        let mut output = None;
+
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                recording = Some(arg);
+                continue;
+            }
+            let value = args.next().ok_or_else(|| format!("Missing value for {}", arg))?;
+            match arg.as_str() {
+                "--fee" => match value.split_once('=') {
+                    Some((exchange, fee)) => {
+                        config.fees.insert(exchange.to_lowercase(), parse_number(&arg, fee)?);
+                    }
+                    None => config.default_fee = Some(parse_number(&arg, &value)?),
+                },
+                "--enter" => config.enter_threshold = parse_number(&arg, &value)?,
+                "--exit" => config.exit_threshold = parse_number(&arg, &value)?,
+                "--min-ticks" => config.min_ticks = parse_number(&arg, &value)?,
+                "--min-persistence-ms" => config.min_persistence_ms = parse_number(&arg, &value)?,
+                "--max-quantity" => config.max_quantity = Some(parse_number(&arg, &value)?),
+                "--sweep" => {
+                    sweep = value
+                        .split(',')
+                        .map(|threshold| parse_number(&arg, threshold))
+                        .collect::<Result<_, _>>()?;
+                }
+                "--output" => output = Some(value),
+                _ => return Err(format!("Unknown option {}", arg)),
+            }
+        }
+        config.exit_threshold = config.exit_threshold.min(config.enter_threshold);
+
+        Ok(Arguments {
+            recording: recording.ok_or("Missing recording path")?,
+            config,
+            sweep,
+            output,
+        })
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value.trim().parse().map_err(|_| format!("Invalid value '{}' for {}", value, option))
+}
+
+/// Distribution of the window durations, in milliseconds
+#[derive(Debug, Default, Serialize)]
+struct DurationStats {
+    min: i64,
+    p50: i64,
+    p90: i64,
+    p99: i64,
+    max: i64,
+    mean: f64,
+    histogram: Vec<DurationBucket>,
+}
+
+#[derive(Debug, Serialize)]
+struct DurationBucket {
+    /// Exclusive upper bound, `None` for the open-ended last bucket
+    below_ms: Option<i64>,
+    count: usize,
+}
+
+impl DurationStats {
+    const BUCKETS: [i64; 6] = [100, 500, 1_000, 5_000, 30_000, 60_000];
+
+    fn from_durations(mut durations: Vec<i64>) -> Self {
+        if durations.is_empty() {
+            return DurationStats::default();
+        }
+        durations.sort_unstable();
+        let percentile = |p: f64| durations[((durations.len() as f64 * p).ceil() as usize).clamp(1, durations.len()) - 1];
+
+        let mut histogram: Vec<DurationBucket> = Self::BUCKETS
+            .iter()
+            .map(|bound| Some(*bound))
+            .chain([None])
+            .map(|below_ms| DurationBucket { below_ms, count: 0 })
+            .collect();
+        for duration in &durations {
+            let bucket = Self::BUCKETS.iter().position(|bound| duration < bound).unwrap_or(Self::BUCKETS.len());
+            histogram[bucket].count += 1;
+        }
+
+        DurationStats {
+            min: durations[0],
+            p50: percentile(0.5),
+            p90: percentile(0.9),
+            p99: percentile(0.99),
+            max: durations[durations.len() - 1],
+            mean: durations.iter().sum::<i64>() as f64 / durations.len() as f64,
+            histogram,
+        }
+    }
+}
+
+/// Windows of one symbol bought on one venue and sold on another
+#[derive(Debug, Serialize)]
+struct CombinationStats {
+    symbol: String,
+    buy_exchange: String,
+    sell_exchange: String,
+    opportunities: usize,
+    /// Expected profit in quote currency of a single fill at the moment each window opened
+    theoretical_pnl: f64,
+    /// Mean of the peak profit percentage of each window
+    average_peak_profit_percentage: f64,
+    peak_profit_percentage: f64,
+    total_duration_ms: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct RunReport {
+    config: BacktestConfig,
+    opportunities: usize,
+    /// Windows still open when the recording ended, their duration runs until the last frame
+    still_open: usize,
+    theoretical_pnl: f64,
+    durations: DurationStats,
+    combinations: Vec<CombinationStats>,
+}
+
+#[derive(Debug, Serialize)]
+struct SweepResult {
+    enter_threshold: f64,
+    exit_threshold: f64,
+    opportunities: usize,
+    median_duration_ms: i64,
+    theoretical_pnl: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct BacktestReport {
+    recording: String,
+    files: usize,
+    frames: u64,
+    price_updates: u64,
+    first_frame_at: Option<DateTime<Utc>>,
+    last_frame_at: Option<DateTime<Utc>>,
+    run: RunReport,
+    sweep: Vec<SweepResult>,
+}
+
+/// One window as seen by the backtest: its final state and the expected profit when it opened
+struct Window {
+    tracked: TrackedOpportunity,
+    opening_profit: f64,
+}
+
+/// Replay the recording once with the given settings
+fn run(recording: &str, config: &BacktestConfig) -> Result<(RunReport, Replayer, ReplayTotals), String> {
+    let state = AppState::new(Config {
+        opportunity_enter_threshold: config.enter_threshold,
+        opportunity_exit_threshold: config.exit_threshold,
+        opportunity_min_ticks: config.min_ticks,
+        opportunity_min_persistence_ms: config.min_persistence_ms,
+        ..Config::default()
+    });
+    let mut detector = ArbitrageDetector::new(state.order_books.clone())
+        .with_fees(config.fee_schedule())
+        .with_quote_converter(state.quote_converter.clone())
+        .with_tick_filter(state.tick_filter.clone())
+        .with_min_profit_percentage(state.opportunities.exit_threshold());
+    if let Some(max_quantity) = config.max_quantity {
+        detector = detector.with_max_quantity(max_quantity);
+    }
+    let mut consumer = SocketConsumer::new()
+        .with_detector(detector)
+        .with_depeg_monitor(state.depeg_monitor.clone())
+        .with_opportunity_tracker(state.opportunities.clone())
+        .with_tick_filter(state.tick_filter.clone());
+
+    let mut events = state.events.subscribe();
+    let mut windows: HashMap<String, Window> = HashMap::new();
+    let mut replayer = Replayer::new(recording)?.with_order_books(state.order_books.clone());
+    let stats = replayer.run(|message| {
+        consumer.process_message(message);
+        collect_windows(&mut events, &mut windows)
+    })?;
+
+    // Windows open at the end of the recording last until its final frame
+    let last_frame_at = stats.last_frame_at.unwrap_or_else(Utc::now);
+    let mut still_open = 0;
+    for mut tracked in state.opportunities.open_opportunities() {
+        tracked.duration_ms = (last_frame_at - tracked.opened_at).num_milliseconds();
+        if let Some(window) = windows.get_mut(&tracked.id) {
+            window.tracked = tracked;
+            still_open += 1;
+        }
+    }
+
+    let totals = ReplayTotals {
+        frames: stats.frames,
+        price_updates: stats.messages,
+        first_frame_at: stats.first_frame_at,
+        last_frame_at: stats.last_frame_at,
+    };
+    Ok((summarize(config, windows.into_values().collect(), still_open), replayer, totals))
+}
+
+struct ReplayTotals {
+    frames: u64,
+    price_updates: u64,
+    first_frame_at: Option<DateTime<Utc>>,
+    last_frame_at: Option<DateTime<Utc>>,
+}
+
+/// Drain the lifecycle events published while processing a price update
+fn collect_windows(events: &mut Receiver<StreamEvent>, windows: &mut HashMap<String, Window>) -> Result<(), String> {
+    loop {
+        match events.try_recv() {
+            Ok(StreamEvent::Opportunity(event)) => {
+                let tracked = event.opportunity;
+                match event.kind {
+                    OpportunityEventKind::Opened => {
+                        let opening_profit = tracked.latest.expected_profit;
+                        windows.insert(tracked.id.clone(), Window { tracked, opening_profit });
+                    }
+                    OpportunityEventKind::Updated | OpportunityEventKind::Closed => {
+                        if let Some(window) = windows.get_mut(&tracked.id) {
+                            window.tracked = tracked;
+                        }
+                    }
+                }
+            }
+            Ok(_) => {}
+            Err(TryRecvError::Empty) => return Ok(()),
+            Err(TryRecvError::Lagged(skipped)) => return Err(format!("Missed {} opportunity events", skipped)),
+            Err(TryRecvError::Closed) => return Err("Event bus closed".to_string()),
+        }
+    }
+}
+
+fn summarize(config: &BacktestConfig, windows: Vec<Window>, still_open: usize) -> RunReport {
+    let mut combinations: BTreeMap<(String, String, String), Vec<&Window>> = BTreeMap::new();
+    for window in &windows {
+        let tracked = &window.tracked;
+        combinations
+            .entry((tracked.symbol.clone(), tracked.buy_exchange.clone(), tracked.sell_exchange.clone()))
+            .or_default()
+            .push(window);
+    }
+
+    let combinations = combinations
+        .into_iter()
+        .map(|((symbol, buy_exchange, sell_exchange), windows)| CombinationStats {
+            symbol,
+            buy_exchange,
+            sell_exchange,
+            opportunities: windows.len(),
+            theoretical_pnl: total_opening_profit(&windows),
+            average_peak_profit_percentage: windows.iter().map(|window| window.tracked.peak_profit_percentage).sum::<f64>()
+                / windows.len() as f64,
+            peak_profit_percentage: windows
+                .iter()
+                .map(|window| window.tracked.peak_profit_percentage)
+                .fold(f64::MIN, f64::max),
+            total_duration_ms: windows.iter().map(|window| window.tracked.duration_ms).sum(),
+        })
+        .collect();
+
+    RunReport {
+        config: config.clone(),
+        opportunities: windows.len(),
+        still_open,
+        theoretical_pnl: total_opening_profit(&windows.iter().collect::<Vec<_>>()),
+        durations: DurationStats::from_durations(windows.iter().map(|window| window.tracked.duration_ms).collect()),
+        combinations,
+    }
+}
+
+fn total_opening_profit(windows: &[&Window]) -> f64 {
+    windows.iter().fold(0.0, |total, window| total + window.opening_profit)
+}
+
+fn print_report(report: &BacktestReport) {
+    let run = &report.run;
+    println!("Recording: {} ({} files)", report.recording, report.files);
+    if let (Some(first), Some(last)) = (report.first_frame_at, report.last_frame_at) {
+        println!("Period:    {} -> {} ({}s)", first, last, (last - first).num_seconds());
+    }
+    println!("Frames:    {} ({} price updates)", report.frames, report.price_updates);
+    println!(
+        "Config:    enter {}% / exit {}%, min {} ticks, min {} ms, fees {}",
+        run.config.enter_threshold,
+        run.config.exit_threshold,
+        run.config.min_ticks,
+        run.config.min_persistence_ms,
+        describe_fees(&run.config)
+    );
+    println!();
+    println!("Opportunities: {} ({} still open at the end)", run.opportunities, run.still_open);
+    println!("Theoretical PnL: {:.4}", run.theoretical_pnl);
+
+    let durations = &run.durations;
+    println!();
+    println!("Duration (ms): min {} / p50 {} / p90 {} / p99 {} / max {} / mean {:.1}",
+        durations.min, durations.p50, durations.p90, durations.p99, durations.max, durations.mean);
+    for bucket in &durations.histogram {
+        let label = match bucket.below_ms {
+            Some(bound) => format!("< {} ms", bound),
+            None => format!(">= {} ms", DurationStats::BUCKETS[DurationStats::BUCKETS.len() - 1]),
+        };
+        println!("  {:>12}  {}", label, bucket.count);
+    }
+
+    println!();
+    println!("{:<10} {:<10} {:<10} {:>8} {:>14} {:>10} {:>10} {:>14}",
+        "symbol", "buy", "sell", "count", "pnl", "avg peak %", "peak %", "duration ms");
+    for combination in &run.combinations {
+        println!(
+            "{:<10} {:<10} {:<10} {:>8} {:>14.4} {:>10.4} {:>10.4} {:>14}",
+            combination.symbol,
+            combination.buy_exchange,
+            combination.sell_exchange,
+            combination.opportunities,
+            combination.theoretical_pnl,
+            combination.average_peak_profit_percentage,
+            combination.peak_profit_percentage,
+            combination.total_duration_ms
+        );
+    }
+
+    println!();
+    println!("Threshold sweep:");
+    println!("{:>10} {:>10} {:>8} {:>14} {:>14}", "enter %", "exit %", "count", "p50 ms", "pnl");
+    for result in &report.sweep {
+        println!(
+            "{:>10} {:>10} {:>8} {:>14} {:>14.4}",
+            result.enter_threshold, result.exit_threshold, result.opportunities, result.median_duration_ms, result.theoretical_pnl
+        );
+    }
+}
+
+fn describe_fees(config: &BacktestConfig) -> String {
+    let mut fees = vec![match config.default_fee {
+        Some(fee) => format!("default {}", fee),
+        None => "public base tier".to_string(),
+    }];
+    fees.extend(config.fees.iter().map(|(exchange, fee)| format!("{} {}", exchange, fee)));
+    fees.join(", ")
+}
+
+fn main() {
+    logger::init_logger(Some("warn"));
+
+    let arguments = match Arguments::parse(std::env::args().skip(1)) {
+        Ok(arguments) => arguments,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    if let Err(e) = backtest(&arguments) {
+        eprintln!("Backtest failed: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn backtest(arguments: &Arguments) -> Result<(), String> {
+    let (run_report, replayer, totals) = run(&arguments.recording, &arguments.config)?;
+
+    let mut sweep = Vec::new();
+    for threshold in &arguments.sweep {
+        let config = arguments.config.with_enter_threshold(*threshold);
+        let (report, _, _) = run(&arguments.recording, &config)?;
+        sweep.push(SweepResult {
+            enter_threshold: config.enter_threshold,
+            exit_threshold: config.exit_threshold,
+            opportunities: report.opportunities,
+            median_duration_ms: report.durations.p50,
+            theoretical_pnl: report.theoretical_pnl,
+        });
+    }
+
+    let report = BacktestReport {
+        recording: arguments.recording.clone(),
+        files: replayer.files().len(),
+        frames: totals.frames,
+        price_updates: totals.price_updates,
+        first_frame_at: totals.first_frame_at,
+        last_frame_at: totals.last_frame_at,
+        run: run_report,
+        sweep,
+    };
+    print_report(&report);
+
+    if let Some(output) = &arguments.output {
+        let json = serde_json::to_string_pretty(&report).map_err(|e| format!("Cannot serialize report: {}", e))?;
+        std::fs::write(output, json).map_err(|e| format!("Cannot write {}: {}", output, e))?;
+        println!();
+        println!("Report written to {}", output);
+    }
+    Ok(())
+}
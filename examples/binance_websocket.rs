@@ -20,12 +20,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     
     log_info!("Adding symbols to monitor: {:?}", symbols);
     
-    // Start monitoring each symbol
-    for symbol in symbols {
-        match container.add_symbol(symbol) {
-            Ok(_) => log_info!("✅ Started monitoring {}", symbol),
-            Err(e) => log_error!("❌ Failed to start monitoring {}: {}", symbol, e),
-        }
+    for symbol in &symbols {
+        container.add_symbol(symbol);
+    }
+
+    // Connect and start monitoring every symbol
+    match container.start_monitoring() {
+        Ok(_) => log_info!("✅ Started monitoring {:?}", symbols),
+        Err(e) => log_error!("❌ Failed to start monitoring: {}", e),
     }
     
     log_info!("WebSocket connections established! Monitoring for 30 seconds...");
@@ -0,0 +1,134 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::alerting::rules::{Alert, Severity};
+use crate::alerting::{headline, http_agent, post_json, DeliveryError, Notifier, OpportunityFilter, RateLimit};
+
+/// Discord entry of `ALERT_NOTIFIERS`
+#[derive(Debug, Clone, Deserialize)]
+pub struct DiscordConfig {
+    /// Sink name the rules route to, `discord-<position>` by default
+    pub name: Option<String>,
+    /// Channel webhook URL, `https://discord.com/api/webhooks/<id>/<token>`
+    pub webhook_url: String,
+    pub rate_limit: Option<RateLimit>,
+    #[serde(flatten)]
+    pub filter: OpportunityFilter,
+}
+
+/// Posts alerts to a Discord channel webhook as embeds
+pub struct DiscordNotifier {
+    webhook_url: String,
+    rate_limit: RateLimit,
+    agent: ureq::Agent,
+}
+
+impl DiscordNotifier {
+    /// Webhooks accept 5 requests every 2 seconds and 30 a minute per channel
+    pub const RATE_LIMIT: RateLimit = RateLimit { burst: 5, per_minute: 30 };
+
+    pub fn new(webhook_url: impl Into<String>) -> Self {
+        DiscordNotifier {
+            webhook_url: webhook_url.into(),
+            rate_limit: Self::RATE_LIMIT,
+            agent: http_agent(Duration::from_secs(5)),
+        }
+    }
+
+    pub fn with_rate_limit(mut self, rate_limit: RateLimit) -> Self {
+        self.rate_limit = rate_limit;
+        self
+    }
+
+    /// Give up on an attempt after this long
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.agent = http_agent(timeout);
+        self
+    }
+
+    pub fn payload(alert: &Alert) -> Value {
+        let field = |name: &str, value: String| json!({ "name": name, "value": value, "inline": true });
+        let fields = match &alert.opportunity {
+            Some(opportunity) => {
+                let latest = &opportunity.latest;
+                vec![
+                    field("Buy", format!("{} {} at {}", latest.buy_exchange, latest.buy_symbol, latest.buy_price)),
+                    field("Sell", format!("{} {} at {}", latest.sell_exchange, latest.sell_symbol, latest.sell_price)),
+                    field("Size", latest.quantity.to_string()),
+                    field("Expected profit", format!("{:.2}", latest.expected_profit)),
+                ]
+            }
+            None => Vec::new(),
+        };
+        // Side bar color of the embed
+        let color = match alert.severity {
+            Severity::Info => 0x3498db,
+            Severity::Warning => 0xf39c12,
+            Severity::Critical => 0xe74c3c,
+        };
+        json!({
+            "embeds": [{
+                "title": headline(alert),
+                "description": alert.message,
+                "color": color,
+                "fields": fields,
+                "footer": { "text": alert.opportunity.as_ref().map_or(alert.rule_id.clone(), |opportunity| format!("{} · {}", alert.rule_id, opportunity.id)) },
+                "timestamp": alert.triggered_at.to_rfc3339()
+            }],
+            // Never ping anyone from a symbol or venue name
+            "allowed_mentions": { "parse": [] }
+        })
+    }
+}
+
+impl Notifier for DiscordNotifier {
+    fn name(&self) -> String {
+        // The webhook URL is a credential
+        "discord".to_string()
+    }
+
+    fn notify(&mut self, alert: &Alert) -> Result<(), DeliveryError> {
+        post_json(self.agent.post(&self.webhook_url), Self::payload(alert).to_string())
+    }
+
+    fn rate_limit(&self) -> Option<RateLimit> {
+        Some(self.rate_limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alerting::rules::tests::event;
+    use crate::alerting::webhook::tests::alert;
+    use crate::models::OpportunityEventKind;
+
+    #[test]
+    fn renders_opportunities_as_embeds() {
+        let mut alert = alert();
+        alert.severity = Severity::Critical;
+        alert.opportunity = Some(event(OpportunityEventKind::Opened, "BTCUSD-binance-coinbase-1").opportunity);
+        let payload = DiscordNotifier::payload(&alert);
+
+        let embed = &payload["embeds"][0];
+        assert_eq!(embed["title"], "[CRITICAL] BTCUSD +0.800%");
+        assert_eq!(embed["color"], 0xe74c3c);
+        assert_eq!(embed["fields"].as_array().unwrap().len(), 4);
+        assert_eq!(embed["fields"][1]["value"], "coinbase BTCUSD at 101");
+        assert_eq!(embed["footer"]["text"], "feed-down · BTCUSD-binance-coinbase-1");
+        assert_eq!(embed["timestamp"], alert.triggered_at.to_rfc3339());
+        assert_eq!(payload["allowed_mentions"]["parse"].as_array().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn renders_other_alerts_without_fields() {
+        let payload = DiscordNotifier::payload(&alert());
+        let embed = &payload["embeds"][0];
+        assert_eq!(embed["description"], "No tick for 60s");
+        assert_eq!(embed["color"], 0xf39c12);
+        assert!(embed["fields"].as_array().unwrap().is_empty());
+        assert_eq!(embed["footer"]["text"], "feed-down");
+    }
+}
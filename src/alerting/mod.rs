@@ -0,0 +1,534 @@
+pub mod discord;
+pub mod email;
+pub mod rules;
+pub mod slack;
+pub mod telegram;
+pub mod webhook;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use chrono::{TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::alerting::discord::{DiscordConfig, DiscordNotifier};
+use crate::alerting::rules::{Alert, AlertRules, RoutedAlert, RuleEngine};
+use crate::alerting::slack::{SlackConfig, SlackNotifier};
+use crate::alerting::telegram::{TelegramConfig, TelegramNotifier};
+use crate::alerting::webhook::{WebhookConfig, WebhookNotifier};
+use crate::candles::CandleAggregator;
+use crate::detector::depeg::DepegMonitor;
+use crate::events::EventBus;
+use crate::models::{ArbitrageOpportunity, StreamEvent, TrackedOpportunity};
+use crate::{log_error, log_info, log_warn};
+
+/// Longest wait between two delivery attempts
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(60);
+
+/// How often the feed and peg rules are checked
+const EVALUATION_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Alerts waiting for a sink, further ones are dropped
+const QUEUE_CAPACITY: usize = 100;
+
+/// Age past which a queued alert is dropped instead of delivered
+const MAX_ALERT_AGE: Duration = Duration::from_secs(600);
+
+/// Opportunities a rule fires on. Empty lists and unset fields match everything.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OpportunityFilter {
+    /// Normalized symbols such as `BTCUSD`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub symbols: Vec<String>,
+    /// Venues on either leg
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exchanges: Vec<String>,
+    /// Minimum net profit percentage after fees
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_profit_percentage: Option<f64>,
+    /// Minimum executable base quantity
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_quantity: Option<f64>,
+    /// Minimum quote notional of the buy leg
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_notional: Option<f64>,
+    /// Minimum time the window has been open, in milliseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_duration_ms: Option<i64>,
+}
+
+impl OpportunityFilter {
+    pub fn matches(&self, opportunity: &TrackedOpportunity) -> bool {
+        let latest = &opportunity.latest;
+        let symbol = latest.symbol.replace("-", "").to_uppercase();
+        (self.symbols.is_empty() || self.symbols.iter().any(|candidate| candidate.replace("-", "").to_uppercase() == symbol))
+            && (self.exchanges.is_empty()
+                || self.exchanges.iter().any(|exchange| {
+                    exchange.eq_ignore_ascii_case(&latest.buy_exchange) || exchange.eq_ignore_ascii_case(&latest.sell_exchange)
+                }))
+            && self.min_profit_percentage.is_none_or(|min_profit| latest.profit_percentage >= min_profit)
+            && self.min_quantity.is_none_or(|min_quantity| latest.quantity >= min_quantity)
+            && self.min_notional.is_none_or(|min_notional| latest.buy_notional >= min_notional)
+            && self.min_duration_ms.is_none_or(|min_duration| opportunity.duration_ms >= min_duration)
+    }
+}
+
+/// Failed delivery of a notification
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeliveryError {
+    /// Worth retrying: network errors, timeouts and server errors
+    Transient(String),
+    /// The endpoint asked to slow down, for the given time when it said so
+    RateLimited(Option<Duration>),
+    /// Retrying would fail the same way, e.g. a rejected payload
+    Permanent(String),
+}
+
+impl fmt::Display for DeliveryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeliveryError::Transient(msg) => write!(f, "{}", msg),
+            DeliveryError::RateLimited(Some(retry_after)) => write!(f, "Rate limited for {:?}", retry_after),
+            DeliveryError::RateLimited(None) => write!(f, "Rate limited"),
+            DeliveryError::Permanent(msg) => write!(f, "{} (not retried)", msg),
+        }
+    }
+}
+
+/// Notifications a sink accepts: bursts of up to `burst`, refilled at `per_minute`
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct RateLimit {
+    pub burst: u32,
+    pub per_minute: u32,
+}
+
+/// Destination of the alerts
+pub trait Notifier: Send {
+    /// Name used in the logs, e.g. `webhook https://example.com/hook`
+    fn name(&self) -> String;
+
+    /// Deliver one alert
+    fn notify(&mut self, alert: &Alert) -> Result<(), DeliveryError>;
+
+    /// Pace the deliveries to stay within the limits of the endpoint
+    fn rate_limit(&self) -> Option<RateLimit> {
+        None
+    }
+}
+
+impl<N: Notifier + ?Sized> Notifier for Box<N> {
+    fn name(&self) -> String {
+        (**self).name()
+    }
+
+    fn notify(&mut self, alert: &Alert) -> Result<(), DeliveryError> {
+        (**self).notify(alert)
+    }
+
+    fn rate_limit(&self) -> Option<RateLimit> {
+        (**self).rate_limit()
+    }
+}
+
+/// One entry of the `ALERT_NOTIFIERS` JSON array, tagged by `type`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum NotifierConfig {
+    Webhook(WebhookConfig),
+    Slack(SlackConfig),
+    Telegram(TelegramConfig),
+    Discord(DiscordConfig),
+}
+
+impl NotifierConfig {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            NotifierConfig::Webhook(_) => "webhook",
+            NotifierConfig::Slack(_) => "slack",
+            NotifierConfig::Telegram(_) => "telegram",
+            NotifierConfig::Discord(_) => "discord",
+        }
+    }
+
+    /// Name the rules route to: the configured one, or the type and the
+    /// 1-based `position` of the sink, e.g. `slack-2`
+    pub fn sink_name(&self, position: usize) -> String {
+        let name = match self {
+            NotifierConfig::Webhook(config) => &config.name,
+            NotifierConfig::Slack(config) => &config.name,
+            NotifierConfig::Telegram(config) => &config.name,
+            NotifierConfig::Discord(config) => &config.name,
+        };
+        name.clone().unwrap_or_else(|| format!("{}-{}", self.kind(), position))
+    }
+
+    /// Opportunities the sink receives when no `ALERT_RULES` are configured
+    pub fn filter(&self) -> &OpportunityFilter {
+        match self {
+            NotifierConfig::Webhook(config) => &config.filter,
+            NotifierConfig::Slack(config) => &config.filter,
+            NotifierConfig::Telegram(config) => &config.filter,
+            NotifierConfig::Discord(config) => &config.filter,
+        }
+    }
+
+    /// Notifier giving up on an attempt after `timeout`
+    pub fn notifier(&self, timeout: Duration) -> Box<dyn Notifier> {
+        match self.clone() {
+            NotifierConfig::Webhook(config) => Box::new(WebhookNotifier::new(config.url).with_secret(config.secret).with_timeout(timeout)),
+            NotifierConfig::Slack(config) => Box::new(
+                SlackNotifier::new(config.webhook_url)
+                    .with_rate_limit(config.rate_limit.unwrap_or(SlackNotifier::RATE_LIMIT))
+                    .with_timeout(timeout),
+            ),
+            NotifierConfig::Telegram(config) => {
+                let mut notifier = TelegramNotifier::new(config.bot_token, config.chat_id)
+                    .with_rate_limit(config.rate_limit.unwrap_or(TelegramNotifier::RATE_LIMIT))
+                    .with_timeout(timeout);
+                if let Some(base_url) = config.base_url {
+                    notifier = notifier.with_base_url(base_url);
+                }
+                Box::new(notifier)
+            }
+            NotifierConfig::Discord(config) => Box::new(
+                DiscordNotifier::new(config.webhook_url)
+                    .with_rate_limit(config.rate_limit.unwrap_or(DiscordNotifier::RATE_LIMIT))
+                    .with_timeout(timeout),
+            ),
+        }
+    }
+}
+
+/// Delivers the alerts raised by the rules to the named sinks. Each sink is
+/// served by its own thread, so a slow, failing or rate limited endpoint only
+/// delays its own alerts; failed deliveries are retried with an exponential
+/// backoff. A sink's queue is bounded and alerts that waited too long in it
+/// are dropped, so a burst behind a rate limit is not delivered hours late.
+pub struct Alerter {
+    sinks: Vec<(String, Box<dyn Notifier>)>,
+    max_retries: u32,
+    retry_backoff: Duration,
+    queue_capacity: usize,
+    max_alert_age: Duration,
+}
+
+impl Alerter {
+    pub fn new() -> Self {
+        Alerter {
+            sinks: Vec::new(),
+            max_retries: 3,
+            retry_backoff: Duration::from_secs(1),
+            queue_capacity: QUEUE_CAPACITY,
+            max_alert_age: MAX_ALERT_AGE,
+        }
+    }
+
+    /// Retry a failed delivery up to `max_retries` times, waiting `backoff`
+    /// before the first retry and doubling the wait after each one
+    pub fn with_retries(mut self, max_retries: u32, backoff: Duration) -> Self {
+        self.max_retries = max_retries;
+        self.retry_backoff = backoff;
+        self
+    }
+
+    /// Keep at most `capacity` alerts waiting per sink and drop those older than `max_age`
+    pub fn with_queue(mut self, capacity: usize, max_age: Duration) -> Self {
+        self.queue_capacity = capacity.max(1);
+        self.max_alert_age = max_age;
+        self
+    }
+
+    /// Deliver the alerts routed to `name` to `notifier`
+    pub fn with_sink(mut self, name: impl Into<String>, notifier: impl Notifier + 'static) -> Self {
+        self.sinks.push((name.into(), Box::new(notifier)));
+        self
+    }
+
+    pub fn sink_names(&self) -> Vec<String> {
+        self.sinks.iter().map(|(name, _)| name.clone()).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    /// Evaluate `rules` from background threads: on the opportunity events
+    /// published on the bus, and every second on the feeds and pegs
+    pub fn start(self, rules: AlertRules, events: &EventBus, candles: CandleAggregator, depeg_monitor: DepegMonitor) -> JoinHandle<()> {
+        let mut receiver = events.subscribe();
+        let queues: HashMap<String, SyncSender<Alert>> = self
+            .sinks
+            .into_iter()
+            .map(|(name, notifier)| {
+                let (sender, queue) = mpsc::sync_channel(self.queue_capacity);
+                let (max_retries, retry_backoff, max_age) = (self.max_retries, self.retry_backoff, self.max_alert_age);
+                thread::spawn(move || deliver(notifier, queue, max_retries, retry_backoff, max_age));
+                (name, sender)
+            })
+            .collect();
+        let queues = Arc::new(queues);
+        let engine = Arc::new(Mutex::new(RuleEngine::new(rules)));
+
+        {
+            let (engine, queues) = (engine.clone(), queues.clone());
+            thread::spawn(move || loop {
+                thread::sleep(EVALUATION_INTERVAL);
+                let alerts = engine.lock().unwrap().evaluate(&candles.markets(), &depeg_monitor.pegs(), Utc::now());
+                route(&queues, alerts);
+            });
+        }
+
+        thread::spawn(move || {
+            log_info!(sinks = queues.len(), "Routing alerts");
+            loop {
+                match receiver.blocking_recv() {
+                    Ok(StreamEvent::Opportunity(event)) => {
+                        let alerts = engine.lock().unwrap().on_opportunity(&event, Utc::now());
+                        route(&queues, alerts);
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(skipped)) => {
+                        log_warn!(skipped, "Alerter lagging behind, events not evaluated");
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        })
+    }
+}
+
+impl Default for Alerter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn route(queues: &HashMap<String, SyncSender<Alert>>, alerts: Vec<RoutedAlert>) {
+    for (alert, sinks) in alerts {
+        log_info!(severity = alert.severity.name(), rule_id = %alert.rule_id, message = %alert.message, "Alert");
+        for (name, queue) in queues.iter() {
+            if (sinks.is_empty() || sinks.contains(name))
+                && let Err(TrySendError::Full(alert)) = queue.try_send(alert.clone())
+            {
+                log_warn!(sink = %name, rule_id = %alert.rule_id, subject = %alert.subject, "Sink queue full, dropped alert");
+            }
+        }
+    }
+}
+
+/// Token bucket blocking the caller until a delivery is allowed
+struct RateLimiter {
+    limit: RateLimit,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl RateLimiter {
+    fn new(limit: RateLimit) -> Self {
+        RateLimiter {
+            limit,
+            tokens: limit.burst as f64,
+            refilled_at: Instant::now(),
+        }
+    }
+
+    fn acquire(&mut self) {
+        let per_second = self.limit.per_minute.max(1) as f64 / 60.0;
+        loop {
+            let now = Instant::now();
+            self.tokens = (self.tokens + now.duration_since(self.refilled_at).as_secs_f64() * per_second).min(self.limit.burst.max(1) as f64);
+            self.refilled_at = now;
+            if self.tokens >= 1.0 {
+                self.tokens -= 1.0;
+                return;
+            }
+            thread::sleep(Duration::from_secs_f64((1.0 - self.tokens) / per_second));
+        }
+    }
+}
+
+fn deliver(mut notifier: Box<dyn Notifier>, queue: Receiver<Alert>, max_retries: u32, retry_backoff: Duration, max_age: Duration) {
+    let name = notifier.name();
+    let mut limiter = notifier.rate_limit().map(RateLimiter::new);
+    let max_age = TimeDelta::from_std(max_age).unwrap_or(TimeDelta::MAX);
+    for alert in queue {
+        let mut backoff = retry_backoff;
+        let mut attempt = 0;
+        loop {
+            if let Some(limiter) = &mut limiter {
+                limiter.acquire();
+            }
+            let age = Utc::now() - alert.triggered_at;
+            if age > max_age {
+                log_warn!(sink = %name, rule_id = %alert.rule_id, subject = %alert.subject, age_secs = age.num_seconds(), "Dropped stale alert");
+                break;
+            }
+            let e = match notifier.notify(&alert) {
+                Ok(()) => break,
+                Err(e) => e,
+            };
+            let wait = match &e {
+                DeliveryError::Transient(_) | DeliveryError::RateLimited(None) => Some(backoff),
+                DeliveryError::RateLimited(Some(retry_after)) => Some((*retry_after).min(MAX_RETRY_BACKOFF)),
+                DeliveryError::Permanent(_) => None,
+            };
+            let Some(wait) = wait.filter(|_| attempt < max_retries) else {
+                log_error!(sink = %name, rule_id = %alert.rule_id, subject = %alert.subject, error = %e, "Dropped alert");
+                break;
+            };
+            attempt += 1;
+            log_warn!(
+                sink = %name,
+                rule_id = %alert.rule_id,
+                subject = %alert.subject,
+                error = %e,
+                attempt,
+                max_retries,
+                wait = ?wait,
+                "Alert delivery failed, retrying"
+            );
+            thread::sleep(wait);
+            backoff = (backoff * 2).min(MAX_RETRY_BACKOFF);
+        }
+    }
+}
+
+/// HTTP agent that reports error statuses as responses, so they can be classified
+pub(crate) fn http_agent(timeout: Duration) -> ureq::Agent {
+    ureq::Agent::config_builder()
+        .timeout_global(Some(timeout))
+        .http_status_as_error(false)
+        .build()
+        .into()
+}
+
+/// POST a JSON body, classifying the failures by whether they are worth retrying
+pub(crate) fn post_json(request: ureq::RequestBuilder<ureq::typestate::WithBody>, body: String) -> Result<(), DeliveryError> {
+    let mut response = request
+        .header("Content-Type", "application/json")
+        .send(body)
+        .map_err(|e| DeliveryError::Transient(format!("Request failed: {}", e)))?;
+    let status = response.status();
+    if status.is_success() {
+        Ok(())
+    } else if status.as_u16() == 429 {
+        // Seconds to wait, in the header or in the body as Discord (`retry_after`) and Telegram (`parameters.retry_after`) do
+        let header = response
+            .headers()
+            .get("Retry-After")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<f64>().ok());
+        let retry_after = header.or_else(|| {
+            let body: Value = response.body_mut().read_json().ok()?;
+            body.get("retry_after")
+                .or_else(|| body.get("parameters").and_then(|parameters| parameters.get("retry_after")))
+                .and_then(Value::as_f64)
+        });
+        Err(DeliveryError::RateLimited(retry_after.filter(|seconds| *seconds >= 0.0).map(Duration::from_secs_f64)))
+    } else if status.is_server_error() {
+        Err(DeliveryError::Transient(format!("Endpoint answered {}", status)))
+    } else {
+        Err(DeliveryError::Permanent(format!("Endpoint answered {}", status)))
+    }
+}
+
+/// Headline of an opportunity, e.g. `BTCUSD +0.250%`
+pub(crate) fn title(opportunity: &ArbitrageOpportunity) -> String {
+    format!("{} {:+.3}%", opportunity.symbol, opportunity.profit_percentage)
+}
+
+/// Headline of an alert, e.g. `[CRITICAL] BTCUSD +0.250%` or `[WARNING] gemini`
+pub(crate) fn headline(alert: &Alert) -> String {
+    let subject = match &alert.opportunity {
+        Some(opportunity) => title(&opportunity.latest),
+        None => alert.subject.clone(),
+    };
+    format!("[{}] {}", alert.severity.name().to_uppercase(), subject)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::Sender;
+
+    use super::*;
+    use crate::alerting::webhook::tests::alert;
+
+    /// Notifier answering from a script, reporting every attempt
+    struct Scripted {
+        results: Vec<Result<(), DeliveryError>>,
+        attempts: Sender<String>,
+    }
+
+    impl Notifier for Scripted {
+        fn name(&self) -> String {
+            "scripted".to_string()
+        }
+
+        fn notify(&mut self, alert: &Alert) -> Result<(), DeliveryError> {
+            self.attempts.send(alert.rule_id.clone()).unwrap();
+            if self.results.is_empty() { Ok(()) } else { self.results.remove(0) }
+        }
+    }
+
+    /// Run `deliver` over `alerts` and return the rule ids of every attempt
+    fn deliveries(results: Vec<Result<(), DeliveryError>>, alerts: Vec<Alert>, max_age: Duration) -> Vec<String> {
+        let (attempts, attempted) = mpsc::channel();
+        let (sender, queue) = mpsc::sync_channel(alerts.len());
+        for alert in alerts {
+            sender.send(alert).unwrap();
+        }
+        drop(sender);
+        deliver(Box::new(Scripted { results, attempts }), queue, 2, Duration::from_millis(1), max_age);
+        attempted.try_iter().collect()
+    }
+
+    #[test]
+    fn paces_deliveries_with_a_token_bucket() {
+        let mut limiter = RateLimiter::new(RateLimit { burst: 2, per_minute: 600 });
+        let started = Instant::now();
+        limiter.acquire();
+        limiter.acquire();
+        assert!(started.elapsed() < Duration::from_millis(50));
+
+        // One token every 100 ms once the burst is spent
+        limiter.acquire();
+        limiter.acquire();
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(190), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(1), "{:?}", elapsed);
+    }
+
+    #[test]
+    fn retries_transient_failures_and_drops_permanent_ones() {
+        let transient = || Err(DeliveryError::Transient("timeout".to_string()));
+        assert_eq!(deliveries(vec![transient(), transient()], vec![alert()], MAX_ALERT_AGE).len(), 3);
+        // Gives up after the retries
+        assert_eq!(deliveries(vec![transient(), transient(), transient(), Ok(())], vec![alert()], MAX_ALERT_AGE).len(), 3);
+        assert_eq!(deliveries(vec![Err(DeliveryError::Permanent("bad".to_string()))], vec![alert()], MAX_ALERT_AGE).len(), 1);
+    }
+
+    #[test]
+    fn drops_stale_alerts() {
+        let mut stale = alert();
+        stale.rule_id = "stale".to_string();
+        stale.triggered_at = Utc::now() - TimeDelta::minutes(5);
+        assert_eq!(deliveries(Vec::new(), vec![stale, alert()], Duration::from_secs(60)), vec!["feed-down".to_string()]);
+    }
+
+    #[test]
+    fn drops_alerts_routed_to_a_full_queue() {
+        let (sender, queue) = mpsc::sync_channel(1);
+        let (other_sender, other_queue) = mpsc::sync_channel(1);
+        let queues = HashMap::from([("slack".to_string(), sender), ("email".to_string(), other_sender)]);
+        route(&queues, vec![(alert(), vec!["slack".to_string()]), (alert(), vec!["slack".to_string()])]);
+
+        assert_eq!(queue.try_iter().count(), 1);
+        assert_eq!(other_queue.try_iter().count(), 0);
+    }
+}
@@ -0,0 +1,570 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::alerting::{title, OpportunityFilter};
+use crate::models::{Market, OpportunityEvent, OpportunityEventKind, PegStatus, TrackedOpportunity};
+
+/// How urgent an alert is
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    #[default]
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        }
+    }
+}
+
+/// What a rule watches, tagged by `type`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AlertCondition {
+    /// Opportunity windows matching the filter, once per window
+    Opportunity(OpportunityFilter),
+    /// Exchange feed without a tick for at least `down_secs`, once per outage
+    FeedDown {
+        /// Every feed that ticked at least once when empty
+        #[serde(default)]
+        exchanges: Vec<String>,
+        down_secs: u64,
+    },
+    /// Stablecoin off its peg by at least `min_deviation_percentage` either way, once per depeg
+    Depeg {
+        /// Every monitored stablecoin when empty
+        #[serde(default)]
+        currencies: Vec<String>,
+        min_deviation_percentage: f64,
+    },
+}
+
+/// Entry of `ALERT_RULES`, also the body of the admin alert endpoints
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AlertRule {
+    /// Unique name of the rule, taken from the path on `PUT`
+    #[serde(default)]
+    pub id: String,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub severity: Severity,
+    pub condition: AlertCondition,
+    /// Quiet period after the rule fired for a subject, e.g. a route or a feed
+    #[serde(default)]
+    pub cooldown_secs: u64,
+    /// Names of the notified sinks, every sink when empty
+    #[serde(default)]
+    pub sinks: Vec<String>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl AlertRule {
+    /// Rule sending the opportunities matching `filter` to the sink `sink`,
+    /// the behaviour of a sink when no rule is configured
+    pub fn forward(sink: &str, filter: OpportunityFilter) -> Self {
+        AlertRule {
+            id: format!("{}-opportunities", sink),
+            enabled: true,
+            severity: Severity::Info,
+            condition: AlertCondition::Opportunity(filter),
+            cooldown_secs: 0,
+            sinks: vec![sink.to_string()],
+        }
+    }
+}
+
+/// Triggered rule, as delivered to the sinks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Alert {
+    pub rule_id: String,
+    pub severity: Severity,
+    /// What the alert is about, e.g. `BTCUSD binance -> coinbase`, `gemini` or `USDT on bitstamp`
+    pub subject: String,
+    /// One line description
+    pub message: String,
+    /// Identical across retries so receivers can deduplicate
+    pub triggered_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub opportunity: Option<TrackedOpportunity>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peg: Option<PegStatus>,
+}
+
+/// Alert and the names of the sinks it goes to, every sink when empty
+pub type RoutedAlert = (Alert, Vec<String>);
+
+/// Alert rules shared between the rule engine and the admin API. Edits apply
+/// from the next evaluation and last until the process exits.
+#[derive(Debug, Clone, Default)]
+pub struct AlertRules {
+    rules: Arc<RwLock<Vec<AlertRule>>>,
+    sinks: Arc<Vec<String>>,
+}
+
+impl AlertRules {
+    /// Rules routed to the given sink names
+    pub fn new(sinks: Vec<String>) -> Self {
+        AlertRules {
+            rules: Arc::default(),
+            sinks: Arc::new(sinks),
+        }
+    }
+
+    pub fn with_rules(self, rules: Vec<AlertRule>) -> Result<Self, String> {
+        for rule in rules {
+            self.insert(rule)?;
+        }
+        Ok(self)
+    }
+
+    /// Names the rules can route to
+    pub fn sinks(&self) -> &[String] {
+        &self.sinks
+    }
+
+    pub fn list(&self) -> Vec<AlertRule> {
+        self.rules.read().unwrap().clone()
+    }
+
+    pub fn get(&self, id: &str) -> Option<AlertRule> {
+        self.rules.read().unwrap().iter().find(|rule| rule.id == id).cloned()
+    }
+
+    /// Add a rule whose id is not taken yet
+    pub fn insert(&self, rule: AlertRule) -> Result<(), String> {
+        self.validate(&rule)?;
+        let mut rules = self.rules.write().unwrap();
+        if rules.iter().any(|existing| existing.id == rule.id) {
+            return Err(format!("Alert rule {} already exists", rule.id));
+        }
+        rules.push(rule);
+        Ok(())
+    }
+
+    /// Replace the rule with the same id or add it, returning whether it existed
+    pub fn upsert(&self, rule: AlertRule) -> Result<bool, String> {
+        self.validate(&rule)?;
+        let mut rules = self.rules.write().unwrap();
+        match rules.iter_mut().find(|existing| existing.id == rule.id) {
+            Some(existing) => {
+                *existing = rule;
+                Ok(true)
+            }
+            None => {
+                rules.push(rule);
+                Ok(false)
+            }
+        }
+    }
+
+    pub fn remove(&self, id: &str) -> Option<AlertRule> {
+        let mut rules = self.rules.write().unwrap();
+        let index = rules.iter().position(|rule| rule.id == id)?;
+        Some(rules.remove(index))
+    }
+
+    fn validate(&self, rule: &AlertRule) -> Result<(), String> {
+        if rule.id.trim().is_empty() {
+            return Err("Alert rule id must not be empty".to_string());
+        }
+        if let Some(sink) = rule.sinks.iter().find(|sink| !self.sinks.contains(sink)) {
+            return Err(format!("Unknown alert sink {}, expected one of [{}]", sink, self.sinks.join(", ")));
+        }
+        if seconds(rule.cooldown_secs).is_none() {
+            return Err(format!("cooldown_secs must be at most {}", MAX_DURATION_SECS));
+        }
+        match &rule.condition {
+            AlertCondition::FeedDown { down_secs: 0, .. } => Err("down_secs must be positive".to_string()),
+            AlertCondition::FeedDown { down_secs, .. } if seconds(*down_secs).is_none() => {
+                Err(format!("down_secs must be at most {}", MAX_DURATION_SECS))
+            }
+            AlertCondition::Depeg { min_deviation_percentage, .. } if min_deviation_percentage.is_nan() || *min_deviation_percentage <= 0.0 => {
+                Err("min_deviation_percentage must be positive".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Evaluates the rules against the opportunity events, the feed activity and
+/// the stablecoin pegs. A rule fires once per opportunity window, outage or
+/// depeg, and not again for the same subject until its cooldown has elapsed;
+/// a condition met during the cooldown fires when it ends if it still holds.
+pub struct RuleEngine {
+    rules: AlertRules,
+    /// (rule, subject) => last time the rule fired for the subject
+    fired_at: HashMap<(String, String), DateTime<Utc>>,
+    /// (rule, window, feed or peg) already notified for the current episode
+    active: HashSet<(String, String)>,
+}
+
+impl RuleEngine {
+    pub fn new(rules: AlertRules) -> Self {
+        RuleEngine {
+            rules,
+            fired_at: HashMap::new(),
+            active: HashSet::new(),
+        }
+    }
+
+    pub fn on_opportunity(&mut self, event: &OpportunityEvent, now: DateTime<Utc>) -> Vec<RoutedAlert> {
+        let opportunity = &event.opportunity;
+        if event.kind == OpportunityEventKind::Closed {
+            self.active.retain(|(_, key)| *key != opportunity.id);
+            return Vec::new();
+        }
+
+        let subject = format!("{} {} -> {}", opportunity.symbol, opportunity.buy_exchange, opportunity.sell_exchange);
+        let mut alerts = Vec::new();
+        for rule in self.rules.list().into_iter().filter(|rule| rule.enabled) {
+            let AlertCondition::Opportunity(filter) = &rule.condition else {
+                continue;
+            };
+            if !filter.matches(opportunity) || !self.fire(&rule, &opportunity.id, &subject, now) {
+                continue;
+            }
+            let latest = &opportunity.latest;
+            let message = format!(
+                "{}: buy {} at {}, sell {} at {}, size {}, expected profit {:.2}, open for {} ms",
+                title(latest),
+                latest.buy_exchange,
+                latest.buy_price,
+                latest.sell_exchange,
+                latest.sell_price,
+                latest.quantity,
+                latest.expected_profit,
+                opportunity.duration_ms
+            );
+            alerts.push(alert(rule, subject.clone(), message, now, Some(opportunity.clone()), None));
+        }
+        alerts
+    }
+
+    /// Check the feed and peg rules, with the markets giving the last tick of every feed
+    pub fn evaluate(&mut self, markets: &[Market], pegs: &[PegStatus], now: DateTime<Utc>) -> Vec<RoutedAlert> {
+        let mut last_ticks: BTreeMap<&str, DateTime<Utc>> = BTreeMap::new();
+        for market in markets {
+            let last_tick = last_ticks.entry(market.exchange.as_str()).or_insert(market.timestamp);
+            *last_tick = (*last_tick).max(market.timestamp);
+        }
+
+        let rules = self.rules.list();
+        // Forget the episodes of deleted rules
+        self.active.retain(|(id, _)| rules.iter().any(|rule| rule.id == *id));
+
+        let mut alerts = Vec::new();
+        for rule in rules {
+            match &rule.condition {
+                AlertCondition::FeedDown { exchanges, down_secs } => {
+                    for (exchange, last_tick) in &last_ticks {
+                        if !exchanges.is_empty() && !exchanges.iter().any(|candidate| candidate.eq_ignore_ascii_case(exchange)) {
+                            continue;
+                        }
+                        let down_for = now - *last_tick;
+                        if !rule.enabled || seconds(*down_secs).is_none_or(|down_secs| down_for < down_secs) {
+                            self.active.remove(&(rule.id.clone(), exchange.to_string()));
+                        } else if self.fire(&rule, exchange, exchange, now) {
+                            let message = format!("{} feed has not ticked for {} s, since {}", exchange, down_for.num_seconds(), last_tick.to_rfc3339());
+                            alerts.push(alert(rule.clone(), exchange.to_string(), message, now, None, None));
+                        }
+                    }
+                }
+                AlertCondition::Depeg { currencies, min_deviation_percentage } => {
+                    for peg in pegs {
+                        if !currencies.is_empty() && !currencies.iter().any(|candidate| candidate.eq_ignore_ascii_case(&peg.currency)) {
+                            continue;
+                        }
+                        let subject = format!("{} on {}", peg.currency, peg.exchange);
+                        if !rule.enabled || peg.deviation_percentage.abs() < *min_deviation_percentage {
+                            self.active.remove(&(rule.id.clone(), subject));
+                        } else if self.fire(&rule, &subject, &subject, now) {
+                            let message = format!(
+                                "{} at {} {}, {:+.3}% off peg",
+                                subject, peg.price, peg.reference_currency, peg.deviation_percentage
+                            );
+                            alerts.push(alert(rule.clone(), subject, message, now, None, Some(peg.clone())));
+                        }
+                    }
+                }
+                AlertCondition::Opportunity(_) => {}
+            }
+        }
+        alerts
+    }
+
+    /// Whether the rule fires for `key` now: not notified yet in this episode
+    /// and out of the cooldown of `subject`
+    fn fire(&mut self, rule: &AlertRule, key: &str, subject: &str, now: DateTime<Utc>) -> bool {
+        let episode = (rule.id.clone(), key.to_string());
+        if self.active.contains(&episode) {
+            return false;
+        }
+        let cooldown = (rule.id.clone(), subject.to_string());
+        if let Some(fired_at) = self.fired_at.get(&cooldown)
+            && seconds(rule.cooldown_secs).is_none_or(|cooldown| now - *fired_at < cooldown)
+        {
+            return false;
+        }
+        self.active.insert(episode);
+        self.fired_at.insert(cooldown, now);
+        true
+    }
+}
+
+/// Longest `cooldown_secs` or `down_secs` a rule accepts, about 31 years
+const MAX_DURATION_SECS: u64 = 1_000_000_000;
+
+/// Duration of a rule setting, none above `MAX_DURATION_SECS`
+fn seconds(secs: u64) -> Option<Duration> {
+    if secs > MAX_DURATION_SECS {
+        return None;
+    }
+    Duration::try_seconds(secs as i64)
+}
+
+fn alert(
+    rule: AlertRule,
+    subject: String,
+    message: String,
+    now: DateTime<Utc>,
+    opportunity: Option<TrackedOpportunity>,
+    peg: Option<PegStatus>,
+) -> RoutedAlert {
+    let alert = Alert {
+        rule_id: rule.id,
+        severity: rule.severity,
+        subject,
+        message,
+        triggered_at: now,
+        opportunity,
+        peg,
+    };
+    (alert, rule.sinks)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::models::{ArbitrageOpportunity, OpportunityStatus};
+
+    fn at(seconds: i64) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap() + Duration::seconds(seconds)
+    }
+
+    fn rules(rules: Vec<AlertRule>) -> AlertRules {
+        AlertRules::new(vec!["slack".to_string(), "email".to_string()]).with_rules(rules).unwrap()
+    }
+
+    fn rule(id: &str, condition: AlertCondition, cooldown_secs: u64) -> AlertRule {
+        AlertRule {
+            id: id.to_string(),
+            enabled: true,
+            severity: Severity::Warning,
+            condition,
+            cooldown_secs,
+            sinks: Vec::new(),
+        }
+    }
+
+    fn feed_down(down_secs: u64) -> AlertCondition {
+        AlertCondition::FeedDown { exchanges: Vec::new(), down_secs }
+    }
+
+    fn depeg(min_deviation_percentage: f64) -> AlertCondition {
+        AlertCondition::Depeg { currencies: Vec::new(), min_deviation_percentage }
+    }
+
+    pub(crate) fn event(kind: OpportunityEventKind, id: &str) -> OpportunityEvent {
+        let latest = ArbitrageOpportunity {
+            id: id.to_string(),
+            symbol: "BTCUSD".to_string(),
+            buy_exchange: "binance".to_string(),
+            sell_exchange: "coinbase".to_string(),
+            buy_price: 100.0,
+            sell_price: 101.0,
+            profit_percentage: 0.8,
+            quantity: 1.0,
+            buy_vwap: 100.0,
+            sell_vwap: 101.0,
+            buy_notional: 100.0,
+            sell_notional: 101.0,
+            expected_profit: 0.8,
+            buy_symbol: "BTCUSD".to_string(),
+            sell_symbol: "BTCUSD".to_string(),
+            buy_conversion: None,
+            sell_conversion: None,
+            timestamp: at(0),
+        };
+        let opportunity = TrackedOpportunity {
+            id: id.to_string(),
+            symbol: latest.symbol.clone(),
+            buy_exchange: latest.buy_exchange.clone(),
+            sell_exchange: latest.sell_exchange.clone(),
+            status: OpportunityStatus::Open,
+            opened_at: at(0),
+            updated_at: at(0),
+            closed_at: None,
+            duration_ms: 0,
+            tick_count: 1,
+            current_profit_percentage: 0.8,
+            peak_profit_percentage: 0.8,
+            current_spread: 1.0,
+            peak_spread: 1.0,
+            latest,
+        };
+        OpportunityEvent { kind, opportunity }
+    }
+
+    fn market(exchange: &str, timestamp: DateTime<Utc>) -> Market {
+        Market {
+            exchange: exchange.to_string(),
+            symbol: "BTCUSD".to_string(),
+            price: 100.0,
+            volume: None,
+            timestamp,
+        }
+    }
+
+    fn peg(deviation_percentage: f64) -> PegStatus {
+        PegStatus {
+            exchange: "kraken".to_string(),
+            symbol: "USDTUSD".to_string(),
+            currency: "USDT".to_string(),
+            reference_currency: "USD".to_string(),
+            price: 1.0 + deviation_percentage / 100.0,
+            deviation_percentage,
+            depegged: false,
+            updated_at: at(0),
+        }
+    }
+
+    #[test]
+    fn fires_once_per_opportunity_window() {
+        let mut engine = RuleEngine::new(rules(vec![rule("all", AlertCondition::Opportunity(OpportunityFilter::default()), 0)]));
+
+        let alerts = engine.on_opportunity(&event(OpportunityEventKind::Opened, "window-1"), at(0));
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].0.subject, "BTCUSD binance -> coinbase");
+        assert!(engine.on_opportunity(&event(OpportunityEventKind::Updated, "window-1"), at(1)).is_empty());
+
+        // Closing ends the episode, the next window fires again
+        assert!(engine.on_opportunity(&event(OpportunityEventKind::Closed, "window-1"), at(2)).is_empty());
+        assert_eq!(engine.on_opportunity(&event(OpportunityEventKind::Opened, "window-2"), at(3)).len(), 1);
+    }
+
+    #[test]
+    fn the_cooldown_suppresses_the_subject_until_it_elapses() {
+        let mut engine = RuleEngine::new(rules(vec![rule("all", AlertCondition::Opportunity(OpportunityFilter::default()), 60)]));
+
+        assert_eq!(engine.on_opportunity(&event(OpportunityEventKind::Opened, "window-1"), at(0)).len(), 1);
+        engine.on_opportunity(&event(OpportunityEventKind::Closed, "window-1"), at(10));
+        // Same route within the cooldown
+        assert!(engine.on_opportunity(&event(OpportunityEventKind::Opened, "window-2"), at(20)).is_empty());
+        // Still open when the cooldown ends
+        assert_eq!(engine.on_opportunity(&event(OpportunityEventKind::Updated, "window-2"), at(60)).len(), 1);
+    }
+
+    #[test]
+    fn fires_once_per_feed_outage() {
+        let mut engine = RuleEngine::new(rules(vec![rule("feed-down", feed_down(30), 0)]));
+        let markets = [market("gemini", at(0)), market("binance", at(50))];
+
+        assert!(engine.evaluate(&markets, &[], at(29)).is_empty());
+        let alerts = engine.evaluate(&markets, &[], at(30));
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].0.subject, "gemini");
+        assert!(engine.evaluate(&markets, &[], at(40)).is_empty());
+
+        // The feed recovers, then goes down again
+        let markets = [market("gemini", at(45)), market("binance", at(50))];
+        assert!(engine.evaluate(&markets, &[], at(50)).is_empty());
+        assert_eq!(engine.evaluate(&markets, &[], at(75)).len(), 1);
+    }
+
+    #[test]
+    fn fires_once_per_depeg_beyond_the_threshold() {
+        let mut engine = RuleEngine::new(rules(vec![rule("depeg", depeg(0.5), 0)]));
+
+        assert!(engine.evaluate(&[], &[peg(-0.4)], at(0)).is_empty());
+        let alerts = engine.evaluate(&[], &[peg(-0.5)], at(1));
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].0.subject, "USDT on kraken");
+        assert!(alerts[0].0.peg.is_some());
+        assert!(engine.evaluate(&[], &[peg(0.7)], at(2)).is_empty());
+
+        assert!(engine.evaluate(&[], &[peg(0.1)], at(3)).is_empty());
+        assert_eq!(engine.evaluate(&[], &[peg(0.6)], at(4)).len(), 1);
+    }
+
+    #[test]
+    fn disabled_rules_do_not_fire() {
+        let mut disabled = rule("feed-down", feed_down(30), 0);
+        disabled.enabled = false;
+        let mut engine = RuleEngine::new(rules(vec![disabled]));
+        assert!(engine.evaluate(&[market("gemini", at(0))], &[], at(60)).is_empty());
+    }
+
+    #[test]
+    fn routes_alerts_to_the_sinks_of_the_rule() {
+        let mut routed = rule("depeg", depeg(0.5), 0);
+        routed.sinks = vec!["email".to_string()];
+        let mut engine = RuleEngine::new(rules(vec![routed]));
+        let alerts = engine.evaluate(&[], &[peg(1.0)], at(0));
+        assert_eq!(alerts[0].1, vec!["email".to_string()]);
+        assert_eq!(alerts[0].0.rule_id, "depeg");
+    }
+
+    #[test]
+    fn validates_rules() {
+        let rules = rules(Vec::new());
+        let mut unknown_sink = rule("depeg", depeg(0.5), 0);
+        unknown_sink.sinks = vec!["pager".to_string()];
+        assert!(rules.insert(unknown_sink).unwrap_err().contains("Unknown alert sink pager"));
+        assert!(rules.insert(rule(" ", depeg(0.5), 0)).is_err());
+        assert!(rules.insert(rule("depeg", depeg(0.0), 0)).is_err());
+        assert!(rules.insert(rule("depeg", depeg(f64::NAN), 0)).is_err());
+        assert!(rules.insert(rule("feed-down", feed_down(0), 0)).is_err());
+        assert!(rules.list().is_empty());
+    }
+
+    #[test]
+    fn rejects_durations_out_of_range() {
+        let rules = rules(Vec::new());
+        assert!(rules.insert(rule("feed-down", feed_down(30), 10_000_000_000_000_000)).unwrap_err().contains("cooldown_secs"));
+        assert!(rules.upsert(rule("feed-down", feed_down(u64::MAX), 0)).unwrap_err().contains("down_secs"));
+        assert!(rules.insert(rule("feed-down", feed_down(MAX_DURATION_SECS), MAX_DURATION_SECS)).is_ok());
+
+        let mut engine = RuleEngine::new(rules);
+        assert!(engine.evaluate(&[market("gemini", at(0))], &[], at(60)).is_empty());
+    }
+
+    #[test]
+    fn insert_rejects_taken_ids_and_upsert_replaces_them() {
+        let rules = rules(vec![rule("feed-down", feed_down(30), 0)]);
+        assert!(rules.insert(rule("feed-down", feed_down(60), 0)).unwrap_err().contains("already exists"));
+
+        assert!(rules.upsert(rule("feed-down", feed_down(60), 0)).unwrap());
+        assert_eq!(rules.get("feed-down").unwrap().condition, feed_down(60));
+        assert!(!rules.upsert(rule("depeg", depeg(0.5), 0)).unwrap());
+        assert_eq!(rules.list().len(), 2);
+
+        assert!(rules.remove("feed-down").is_some());
+        assert!(rules.get("feed-down").is_none());
+    }
+}
@@ -0,0 +1,150 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::alerting::rules::Alert;
+use crate::alerting::{headline, http_agent, post_json, DeliveryError, Notifier, OpportunityFilter, RateLimit};
+
+/// Slack entry of `ALERT_NOTIFIERS`
+#[derive(Debug, Clone, Deserialize)]
+pub struct SlackConfig {
+    /// Sink name the rules route to, `slack-<position>` by default
+    pub name: Option<String>,
+    /// Incoming webhook URL, `https://hooks.slack.com/services/...`
+    pub webhook_url: String,
+    pub rate_limit: Option<RateLimit>,
+    #[serde(flatten)]
+    pub filter: OpportunityFilter,
+}
+
+/// Posts alerts to a Slack incoming webhook as Block Kit messages
+pub struct SlackNotifier {
+    webhook_url: String,
+    rate_limit: RateLimit,
+    agent: ureq::Agent,
+}
+
+impl SlackNotifier {
+    /// Incoming webhooks accept about one message per second
+    pub const RATE_LIMIT: RateLimit = RateLimit { burst: 1, per_minute: 60 };
+
+    pub fn new(webhook_url: impl Into<String>) -> Self {
+        SlackNotifier {
+            webhook_url: webhook_url.into(),
+            rate_limit: Self::RATE_LIMIT,
+            agent: http_agent(Duration::from_secs(5)),
+        }
+    }
+
+    pub fn with_rate_limit(mut self, rate_limit: RateLimit) -> Self {
+        self.rate_limit = rate_limit;
+        self
+    }
+
+    /// Give up on an attempt after this long
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.agent = http_agent(timeout);
+        self
+    }
+
+    pub fn payload(alert: &Alert) -> Value {
+        let field = |name: &str, value: String| json!({ "type": "mrkdwn", "text": format!("*{}*\n{}", name, escape(&value)) });
+        let (body, context) = match &alert.opportunity {
+            Some(opportunity) => {
+                let latest = &opportunity.latest;
+                let body = json!({
+                    "type": "section",
+                    "fields": [
+                        field("Buy", format!("{} {} at {}", latest.buy_exchange, latest.buy_symbol, latest.buy_price)),
+                        field("Sell", format!("{} {} at {}", latest.sell_exchange, latest.sell_symbol, latest.sell_price)),
+                        field("Size", latest.quantity.to_string()),
+                        field("Expected profit", format!("{:.2}", latest.expected_profit)),
+                    ]
+                });
+                (body, format!("Opened {} · `{}`", opportunity.opened_at.to_rfc3339(), escape(&opportunity.id)))
+            }
+            None => (
+                json!({ "type": "section", "text": { "type": "mrkdwn", "text": escape(&alert.message) } }),
+                format!("Triggered {}", alert.triggered_at.to_rfc3339()),
+            ),
+        };
+        json!({
+            "text": format!("{}: {}", headline(alert), alert.message),
+            "blocks": [
+                {
+                    "type": "header",
+                    "text": { "type": "plain_text", "text": headline(alert) }
+                },
+                body,
+                {
+                    "type": "context",
+                    "elements": [{
+                        "type": "mrkdwn",
+                        "text": format!("{} · rule `{}`", context, escape(&alert.rule_id))
+                    }]
+                }
+            ]
+        })
+    }
+}
+
+impl Notifier for SlackNotifier {
+    fn name(&self) -> String {
+        // The webhook URL is a credential
+        "slack".to_string()
+    }
+
+    fn notify(&mut self, alert: &Alert) -> Result<(), DeliveryError> {
+        post_json(self.agent.post(&self.webhook_url), Self::payload(alert).to_string())
+    }
+
+    fn rate_limit(&self) -> Option<RateLimit> {
+        Some(self.rate_limit)
+    }
+}
+
+/// Escape the characters Slack reserves for links and mentions
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alerting::rules::tests::event;
+    use crate::alerting::webhook::tests::alert;
+    use crate::models::OpportunityEventKind;
+
+    #[test]
+    fn renders_opportunities_as_block_kit_sections() {
+        let mut alert = alert();
+        alert.opportunity = Some(event(OpportunityEventKind::Opened, "BTCUSD-binance-coinbase-1").opportunity);
+        let payload = SlackNotifier::payload(&alert);
+
+        let blocks = payload["blocks"].as_array().unwrap();
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(blocks[0]["type"], "header");
+        assert_eq!(blocks[0]["text"]["text"], "[WARNING] BTCUSD +0.800%");
+        let fields = blocks[1]["fields"].as_array().unwrap();
+        assert_eq!(fields.len(), 4);
+        assert_eq!(fields[0]["text"], "*Buy*\nbinance BTCUSD at 100");
+        assert_eq!(blocks[2]["type"], "context");
+        assert!(blocks[2]["elements"][0]["text"].as_str().unwrap().ends_with("`BTCUSD-binance-coinbase-1` · rule `feed-down`"));
+        assert_eq!(payload["text"], "[WARNING] BTCUSD +0.800%: No tick for 60s");
+    }
+
+    #[test]
+    fn escapes_the_message_of_other_alerts() {
+        let mut alert = alert();
+        alert.message = "<!channel> & friends".to_string();
+        let payload = SlackNotifier::payload(&alert);
+        assert_eq!(payload["blocks"][1]["text"]["text"], "&lt;!channel&gt; &amp; friends");
+        assert!(payload["blocks"][2]["elements"][0]["text"].as_str().unwrap().starts_with("Triggered "));
+    }
+
+    #[test]
+    fn never_names_the_webhook_url() {
+        assert_eq!(SlackNotifier::new("https://hooks.slack.com/services/T000/B000/XXXX").name(), "slack");
+    }
+}
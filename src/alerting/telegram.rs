@@ -0,0 +1,164 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::alerting::rules::Alert;
+use crate::alerting::{headline, http_agent, post_json, DeliveryError, Notifier, OpportunityFilter, RateLimit};
+
+const TELEGRAM_API_URL: &str = "https://api.telegram.org";
+
+/// Telegram entry of `ALERT_NOTIFIERS`
+#[derive(Debug, Clone, Deserialize)]
+pub struct TelegramConfig {
+    /// Sink name the rules route to, `telegram-<position>` by default
+    pub name: Option<String>,
+    pub bot_token: String,
+    /// Numeric id or `@channelusername`, as a string
+    pub chat_id: String,
+    /// Bot API root, `https://api.telegram.org` by default
+    pub base_url: Option<String>,
+    pub rate_limit: Option<RateLimit>,
+    #[serde(flatten)]
+    pub filter: OpportunityFilter,
+}
+
+/// Sends alerts to a chat through the Bot API `sendMessage` method
+pub struct TelegramNotifier {
+    base_url: String,
+    bot_token: String,
+    chat_id: String,
+    rate_limit: RateLimit,
+    agent: ureq::Agent,
+}
+
+impl TelegramNotifier {
+    /// Bots may post about 20 messages a minute to a group
+    pub const RATE_LIMIT: RateLimit = RateLimit { burst: 1, per_minute: 20 };
+
+    pub fn new(bot_token: impl Into<String>, chat_id: impl Into<String>) -> Self {
+        TelegramNotifier {
+            base_url: TELEGRAM_API_URL.to_string(),
+            bot_token: bot_token.into(),
+            chat_id: chat_id.into(),
+            rate_limit: Self::RATE_LIMIT,
+            agent: http_agent(Duration::from_secs(5)),
+        }
+    }
+
+    /// Bot API root, e.g. a local stand-in
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn with_rate_limit(mut self, rate_limit: RateLimit) -> Self {
+        self.rate_limit = rate_limit;
+        self
+    }
+
+    /// Give up on an attempt after this long
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.agent = http_agent(timeout);
+        self
+    }
+
+    pub fn payload(chat_id: &str, alert: &Alert) -> Value {
+        let text = match &alert.opportunity {
+            Some(opportunity) => {
+                let latest = &opportunity.latest;
+                format!(
+                    "<b>{}</b>\nBuy {} {} at {}\nSell {} {} at {}\nSize {}, expected profit {:.2}\n<code>{}</code>",
+                    escape(&headline(alert)),
+                    escape(&latest.buy_exchange),
+                    escape(&latest.buy_symbol),
+                    latest.buy_price,
+                    escape(&latest.sell_exchange),
+                    escape(&latest.sell_symbol),
+                    latest.sell_price,
+                    latest.quantity,
+                    latest.expected_profit,
+                    escape(&opportunity.id),
+                )
+            }
+            None => format!(
+                "<b>{}</b>\n{}\n<code>{}</code>",
+                escape(&headline(alert)),
+                escape(&alert.message),
+                escape(&alert.rule_id)
+            ),
+        };
+        json!({
+            "chat_id": chat_id,
+            "text": text,
+            "parse_mode": "HTML",
+            "disable_web_page_preview": true
+        })
+    }
+}
+
+impl Notifier for TelegramNotifier {
+    fn name(&self) -> String {
+        format!("telegram {}", self.chat_id)
+    }
+
+    fn notify(&mut self, alert: &Alert) -> Result<(), DeliveryError> {
+        let url = format!("{}/bot{}/sendMessage", self.base_url, self.bot_token);
+        post_json(self.agent.post(&url), Self::payload(&self.chat_id, alert).to_string())
+    }
+
+    fn rate_limit(&self) -> Option<RateLimit> {
+        Some(self.rate_limit)
+    }
+}
+
+/// Escape the characters Telegram HTML messages reserve
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alerting::rules::tests::event;
+    use crate::alerting::webhook::tests::{alert, stand_in, OK};
+    use crate::models::OpportunityEventKind;
+
+    #[test]
+    fn renders_send_message_payloads_in_html() {
+        let mut alert = alert();
+        alert.opportunity = Some(event(OpportunityEventKind::Opened, "BTCUSD-binance-coinbase-1").opportunity);
+        let payload = TelegramNotifier::payload("@alerts", &alert);
+
+        assert_eq!(payload["chat_id"], "@alerts");
+        assert_eq!(payload["parse_mode"], "HTML");
+        assert_eq!(payload["disable_web_page_preview"], true);
+        assert_eq!(
+            payload["text"],
+            "<b>[WARNING] BTCUSD +0.800%</b>\nBuy binance BTCUSD at 100\nSell coinbase BTCUSD at 101\nSize 1, expected profit 0.80\n<code>BTCUSD-binance-coinbase-1</code>"
+        );
+    }
+
+    #[test]
+    fn escapes_html_in_other_alerts() {
+        let mut alert = alert();
+        alert.message = "bid < ask & ask > bid".to_string();
+        let payload = TelegramNotifier::payload("42", &alert);
+        assert_eq!(payload["text"], "<b>[WARNING] gemini</b>\nbid &lt; ask &amp; ask &gt; bid\n<code>feed-down</code>");
+    }
+
+    #[test]
+    fn posts_to_the_bot_api_at_the_base_url() {
+        let (url, endpoint) = stand_in(OK);
+        let base_url = url.strip_suffix("/hook").unwrap();
+        let mut notifier = TelegramNotifier::new("123:secret", "42").with_base_url(format!("{}/", base_url));
+        notifier.notify(&alert()).unwrap();
+
+        let (request_line, headers, body) = endpoint.join().unwrap();
+        assert_eq!(request_line, "POST /bot123:secret/sendMessage HTTP/1.1");
+        assert_eq!(headers["content-type"], "application/json");
+        let body: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body["chat_id"], "42");
+        assert_eq!(notifier.name(), "telegram 42");
+    }
+}
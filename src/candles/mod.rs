@@ -0,0 +1,262 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::sync::{Arc, RwLock};
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use serde::Deserialize;
+
+use crate::models::{Candle, CandleInterval, FeedUptime, Market, SymbolMessage};
+
+const DEFAULT_LIMIT: usize = 500;
+
+/// Candles kept per market and interval: an hour of 1s, a day of 1m, a week of 5m and a month of 1h
+fn capacity(interval: CandleInterval) -> usize {
+    match interval {
+        CandleInterval::OneSecond => 3_600,
+        CandleInterval::OneMinute => 1_440,
+        CandleInterval::FiveMinutes => 2_016,
+        CandleInterval::OneHour => 720,
+    }
+}
+
+/// Filters of a candle query
+#[derive(Debug, Clone, Deserialize)]
+pub struct CandleQuery {
+    pub symbol: String,
+    /// All venues quoting the symbol when unset
+    pub exchange: Option<String>,
+    pub interval: CandleInterval,
+    /// Inclusive start of the candle open times
+    pub from: Option<DateTime<Utc>>,
+    /// Exclusive end of the candle open times
+    pub to: Option<DateTime<Utc>>,
+    /// Most recent candles returned, 500 by default
+    pub limit: Option<usize>,
+}
+
+/// (exchange, symbol, interval) => candles, oldest first
+type CandleSeries = HashMap<(String, String, CandleInterval), VecDeque<Candle>>;
+
+/// Minutes with ticks and last tick time of an exchange
+type FeedActivity = (BTreeSet<DateTime<Utc>>, Option<DateTime<Utc>>);
+
+/// Builds 1s, 1m, 5m and 1h OHLCV candles per (exchange, symbol) from the
+/// accepted ticks, bucketed by receive time. The volume is the sum of the
+/// trade quantities reported with the ticks, so it stays unset on feeds that
+/// only publish prices.
+#[derive(Debug, Clone, Default)]
+pub struct CandleAggregator {
+    series: Arc<RwLock<CandleSeries>>,
+    /// (exchange, symbol) => last 24 hour volume reported by the feed
+    reported_volumes: Arc<RwLock<HashMap<(String, String), f64>>>,
+}
+
+impl CandleAggregator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn on_tick(&self, message: &SymbolMessage) {
+        let at = message.received_at;
+        if let Some(volume) = message.volume_24h {
+            self.reported_volumes
+                .write()
+                .unwrap()
+                .insert((message.exchange.clone(), message.symbol.clone()), volume);
+        }
+        let mut series = self.series.write().unwrap();
+
+        for interval in CandleInterval::ALL {
+            let width = interval.millis();
+            let open_time = Utc
+                .timestamp_millis_opt(at.timestamp_millis().div_euclid(width) * width)
+                .single()
+                .unwrap_or(at);
+            let candles = series
+                .entry((message.exchange.clone(), message.symbol.clone(), interval))
+                .or_default();
+
+            // Ticks almost always belong to the last candle, out of order ones are looked up
+            let position = match candles.back() {
+                Some(last) if last.open_time == open_time => Ok(candles.len() - 1),
+                Some(last) if last.open_time < open_time => Err(candles.len()),
+                _ => candles.binary_search_by_key(&open_time, |candle| candle.open_time),
+            };
+            match position {
+                Ok(index) => {
+                    let candle = &mut candles[index];
+                    candle.high = candle.high.max(message.price);
+                    candle.low = candle.low.min(message.price);
+                    if at >= candle.updated_at {
+                        candle.close = message.price;
+                        candle.updated_at = at;
+                    }
+                    if let Some(quantity) = message.quantity {
+                        *candle.volume.get_or_insert(0.0) += quantity;
+                    }
+                    candle.ticks += 1;
+                }
+                Err(index) => {
+                    candles.insert(index, Candle {
+                        exchange: message.exchange.clone(),
+                        symbol: message.symbol.clone(),
+                        interval,
+                        open_time,
+                        open: message.price,
+                        high: message.price,
+                        low: message.price,
+                        close: message.price,
+                        volume: message.quantity,
+                        ticks: 1,
+                        updated_at: at,
+                    });
+                    while candles.len() > capacity(interval) {
+                        candles.pop_front();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Candles matching the query, oldest first
+    pub fn candles(&self, query: &CandleQuery) -> Vec<Candle> {
+        let symbol = query.symbol.replace("-", "").to_uppercase();
+        let exchange = query.exchange.as_ref().map(|exchange| exchange.to_lowercase());
+        let series = self.series.read().unwrap();
+
+        let mut candles: Vec<Candle> = series
+            .iter()
+            .filter(|((candle_exchange, candle_symbol, interval), _)| {
+                *candle_symbol == symbol
+                    && *interval == query.interval
+                    && exchange.as_ref().is_none_or(|exchange| exchange == candle_exchange)
+            })
+            .flat_map(|(_, candles)| candles.iter())
+            .filter(|candle| query.from.is_none_or(|from| candle.open_time >= from))
+            .filter(|candle| query.to.is_none_or(|to| candle.open_time < to))
+            .cloned()
+            .collect();
+        candles.sort_by(|a, b| a.open_time.cmp(&b.open_time).then_with(|| a.exchange.cmp(&b.exchange)));
+
+        let limit = query.limit.unwrap_or(DEFAULT_LIMIT);
+        let skip = candles.len().saturating_sub(limit);
+        candles.split_off(skip)
+    }
+
+    /// Last price and 24 hour volume of every market, from the 1m candles and
+    /// the volume reported by the feed when it does
+    pub fn markets(&self) -> Vec<Market> {
+        let series = self.series.read().unwrap();
+        let reported_volumes = self.reported_volumes.read().unwrap();
+        let mut markets: Vec<Market> = series
+            .iter()
+            .filter(|((_, _, interval), _)| *interval == CandleInterval::OneMinute)
+            .filter_map(|((exchange, symbol, _), candles)| {
+                let last = candles.back()?;
+                let since = last.updated_at - Duration::hours(24);
+                let traded = candles
+                    .iter()
+                    .filter(|candle| candle.open_time >= since)
+                    .filter_map(|candle| candle.volume)
+                    .reduce(|total, volume| total + volume);
+                Some(Market {
+                    exchange: exchange.clone(),
+                    symbol: symbol.clone(),
+                    price: last.close,
+                    volume: reported_volumes.get(&(exchange.clone(), symbol.clone())).copied().or(traded),
+                    timestamp: last.updated_at,
+                })
+            })
+            .collect();
+        markets.sort_by(|a, b| a.symbol.cmp(&b.symbol).then_with(|| a.exchange.cmp(&b.exchange)));
+        markets
+    }
+
+    /// Uptime of every exchange feed over `[from, to)`, from the minutes with
+    /// ticks on any of its markets. Only the last day of 1m candles is kept.
+    pub fn feed_uptime(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<FeedUptime> {
+        let minutes = ((to - from).num_seconds().max(0) as u64).div_ceil(60);
+        let series = self.series.read().unwrap();
+        let mut feeds: BTreeMap<&str, FeedActivity> = BTreeMap::new();
+        for ((exchange, _, interval), candles) in series.iter() {
+            if *interval != CandleInterval::OneMinute {
+                continue;
+            }
+            let (active, last_tick_at) = feeds.entry(exchange.as_str()).or_default();
+            for candle in candles.iter().filter(|candle| candle.open_time >= from && candle.open_time < to) {
+                active.insert(candle.open_time);
+            }
+            if let Some(last) = candles.back() {
+                *last_tick_at = (*last_tick_at).max(Some(last.updated_at));
+            }
+        }
+
+        feeds
+            .into_iter()
+            .map(|(exchange, (active, last_tick_at))| FeedUptime {
+                exchange: exchange.to_string(),
+                active_minutes: active.len() as u64,
+                minutes,
+                uptime_percentage: if minutes == 0 { 0.0 } else { active.len() as f64 / minutes as f64 * 100.0 },
+                last_tick_at,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tick(exchange: &str, price: f64, seconds: i64) -> SymbolMessage {
+        let mut message = SymbolMessage::new(exchange, "BTCUSD".to_string(), price);
+        message.received_at = Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap() + Duration::seconds(seconds);
+        message
+    }
+
+    fn minute_candles(candles: &CandleAggregator, exchange: &str) -> Vec<Candle> {
+        candles.candles(&CandleQuery {
+            symbol: "BTCUSD".to_string(),
+            exchange: Some(exchange.to_string()),
+            interval: CandleInterval::OneMinute,
+            from: None,
+            to: None,
+            limit: None,
+        })
+    }
+
+    #[test]
+    fn sums_trade_sizes_and_leaves_price_only_feeds_without_volume() {
+        let candles = CandleAggregator::new();
+        candles.on_tick(&tick("coinbase", 100.0, 1).with_quantity(Some(0.5)));
+        candles.on_tick(&tick("coinbase", 102.0, 2).with_quantity(Some(1.5)));
+        candles.on_tick(&tick("coinbase", 101.0, 3));
+        candles.on_tick(&tick("binance", 100.0, 1));
+
+        let coinbase = &minute_candles(&candles, "coinbase")[0];
+        assert_eq!((coinbase.open, coinbase.high, coinbase.close), (100.0, 102.0, 101.0));
+        assert_eq!(coinbase.volume, Some(2.0));
+        assert_eq!(coinbase.ticks, 3);
+        assert_eq!(minute_candles(&candles, "binance")[0].volume, None);
+    }
+
+    #[test]
+    fn markets_prefer_the_volume_reported_by_the_feed() {
+        let candles = CandleAggregator::new();
+        candles.on_tick(&tick("binance", 100.0, 1).with_volume_24h(Some(1200.0)));
+        candles.on_tick(&tick("binance", 101.0, 2).with_volume_24h(Some(1250.0)));
+        candles.on_tick(&tick("coinbase", 100.0, 1).with_quantity(Some(0.5)));
+        candles.on_tick(&tick("coinbase", 100.0, 61).with_quantity(Some(0.25)));
+        candles.on_tick(&tick("gemini", 100.0, 1));
+
+        let volumes: Vec<(String, Option<f64>)> =
+            candles.markets().into_iter().map(|market| (market.exchange, market.volume)).collect();
+        assert_eq!(
+            volumes,
+            vec![
+                ("binance".to_string(), Some(1250.0)),
+                ("coinbase".to_string(), Some(0.75)),
+                ("gemini".to_string(), None),
+            ]
+        );
+    }
+}
@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use chrono::Utc;
+
+use crate::events::EventBus;
+use crate::models::{CurrencyPair, DepegAlert, DepegAlertKind, PegStatus, StreamEvent};
+use crate::{log_info, log_warn};
+
+/// Watches the stablecoin markets published by the connectors and raises an
+/// alert whenever a coin moves beyond `threshold_percentage` away from 1.0 of
+/// the reference currency on a venue, and again once it is back within it.
+#[derive(Debug, Clone)]
+pub struct DepegMonitor {
+    currencies: Vec<String>,
+    reference_currency: String,
+    threshold_percentage: f64,
+    pegs: Arc<RwLock<HashMap<(String, String), PegStatus>>>, // (exchange, currency) => latest peg
+    events: Option<EventBus>,
+}
+
+impl DepegMonitor {
+    pub fn new(threshold_percentage: f64) -> Self {
+        DepegMonitor {
+            currencies: ["USDT", "USDC", "DAI"].map(String::from).to_vec(),
+            reference_currency: "USD".to_string(),
+            threshold_percentage,
+            pegs: Arc::new(RwLock::new(HashMap::new())),
+            events: None,
+        }
+    }
+
+    /// Override the monitored stablecoins
+    pub fn with_currencies(mut self, currencies: &[&str]) -> Self {
+        self.currencies = currencies.iter().map(|currency| currency.to_uppercase()).collect();
+        self
+    }
+
+    pub fn with_reference_currency(mut self, reference_currency: &str) -> Self {
+        self.reference_currency = reference_currency.to_uppercase();
+        self
+    }
+
+    /// Publish alerts to the streaming API
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    pub fn threshold_percentage(&self) -> f64 {
+        self.threshold_percentage
+    }
+
+    /// Record the price of a market, returns the alert raised if the peg crossed the threshold
+    pub fn on_price(&self, exchange: &str, symbol: &str, price: f64) -> Option<DepegAlert> {
+        let pair = CurrencyPair::parse(symbol)?;
+        if price <= 0.0 || !price.is_finite() {
+            return None;
+        }
+
+        let (currency, peg_price) = if pair.quote == self.reference_currency && self.is_monitored(&pair.base) {
+            (pair.base.clone(), price)
+        } else if pair.base == self.reference_currency && self.is_monitored(&pair.quote) {
+            (pair.quote.clone(), 1.0 / price)
+        } else {
+            return None;
+        };
+
+        let deviation_percentage = (peg_price - 1.0) * 100.0;
+        let peg = PegStatus {
+            exchange: exchange.to_string(),
+            symbol: pair.symbol(),
+            currency: currency.clone(),
+            reference_currency: self.reference_currency.clone(),
+            price: peg_price,
+            deviation_percentage,
+            depegged: deviation_percentage.abs() > self.threshold_percentage,
+            updated_at: Utc::now(),
+        };
+
+        let was_depegged = self.pegs
+            .write()
+            .unwrap()
+            .insert((exchange.to_string(), currency), peg.clone())
+            .is_some_and(|previous| previous.depegged);
+
+        let kind = match (was_depegged, peg.depegged) {
+            (false, true) => DepegAlertKind::Depegged,
+            (true, false) => DepegAlertKind::Recovered,
+            _ => return None,
+        };
+
+        match kind {
+            DepegAlertKind::Depegged => log_warn!(
+                currency = %peg.currency,
+                exchange = %peg.exchange,
+                price = peg.price,
+                reference_currency = %peg.reference_currency,
+                deviation_percentage = peg.deviation_percentage,
+                "Depegged"
+            ),
+            DepegAlertKind::Recovered => log_info!(
+                currency = %peg.currency,
+                exchange = %peg.exchange,
+                price = peg.price,
+                reference_currency = %peg.reference_currency,
+                threshold_percentage = self.threshold_percentage,
+                "Back within the peg"
+            ),
+        }
+
+        let alert = DepegAlert {
+            kind,
+            threshold_percentage: self.threshold_percentage,
+            peg,
+        };
+        if let Some(events) = &self.events {
+            events.publish(StreamEvent::DepegAlert(alert.clone()));
+        }
+        Some(alert)
+    }
+
+    /// Current peg of every monitored stablecoin on every venue quoting it
+    pub fn pegs(&self) -> Vec<PegStatus> {
+        let mut pegs: Vec<PegStatus> = self.pegs.read().unwrap().values().cloned().collect();
+        pegs.sort_by(|a, b| a.currency.cmp(&b.currency).then_with(|| a.exchange.cmp(&b.exchange)));
+        pegs
+    }
+
+    fn is_monitored(&self, currency: &str) -> bool {
+        self.currencies.iter().any(|monitored| monitored == currency)
+    }
+}
+
+impl Default for DepegMonitor {
+    fn default() -> Self {
+        Self::new(0.5)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn alerts_once_when_crossing_the_threshold_and_on_recovery() {
+        let monitor = DepegMonitor::new(0.5);
+        assert!(monitor.on_price("binance", "USDTUSD", 0.999).is_none());
+
+        let alert = monitor.on_price("binance", "USDTUSD", 0.994).unwrap();
+        assert_eq!(alert.kind, DepegAlertKind::Depegged);
+        assert_eq!(alert.threshold_percentage, 0.5);
+        assert_eq!(alert.peg.currency, "USDT");
+        assert_close(alert.peg.deviation_percentage, -0.6);
+
+        // Still off peg, no new alert
+        assert!(monitor.on_price("binance", "USDTUSD", 0.990).is_none());
+
+        let alert = monitor.on_price("binance", "USDTUSD", 0.998).unwrap();
+        assert_eq!(alert.kind, DepegAlertKind::Recovered);
+        assert!(!alert.peg.depegged);
+    }
+
+    #[test]
+    fn the_threshold_itself_is_within_the_peg() {
+        let monitor = DepegMonitor::new(0.5);
+        assert!(monitor.on_price("gemini", "USDCUSD", 1.005).is_none());
+        assert!(!monitor.pegs()[0].depegged);
+        assert_eq!(monitor.on_price("gemini", "USDCUSD", 1.0051).unwrap().kind, DepegAlertKind::Depegged);
+    }
+
+    #[test]
+    fn inverts_markets_quoted_in_the_stablecoin() {
+        let monitor = DepegMonitor::new(0.5);
+        let alert = monitor.on_price("binance", "USDUSDT", 1.01).unwrap();
+        assert_eq!(alert.peg.currency, "USDT");
+        assert_close(alert.peg.price, 1.0 / 1.01);
+        assert!(alert.peg.deviation_percentage < -0.5);
+    }
+
+    #[test]
+    fn ignores_other_markets_and_invalid_prices() {
+        let monitor = DepegMonitor::new(0.5).with_currencies(&["usdt"]);
+        assert!(monitor.on_price("binance", "BTCUSD", 50_000.0).is_none());
+        assert!(monitor.on_price("binance", "DAIUSD", 0.9).is_none());
+        assert!(monitor.on_price("binance", "USDTUSD", 0.0).is_none());
+        assert!(monitor.on_price("binance", "USDTUSD", f64::NAN).is_none());
+        assert!(monitor.pegs().is_empty());
+    }
+
+    #[test]
+    fn tracks_pegs_per_venue_and_publishes_alerts() {
+        let events = EventBus::new();
+        let mut receiver = events.subscribe();
+        let monitor = DepegMonitor::new(0.5).with_events(events);
+
+        monitor.on_price("kraken", "USDTUSD", 0.99);
+        monitor.on_price("bitstamp", "USDTUSD", 1.0);
+        monitor.on_price("bitstamp", "DAIUSD", 1.0);
+
+        let pegs: Vec<(String, String, bool)> = monitor
+            .pegs()
+            .into_iter()
+            .map(|peg| (peg.currency, peg.exchange, peg.depegged))
+            .collect();
+        assert_eq!(
+            pegs,
+            [
+                ("DAI".to_string(), "bitstamp".to_string(), false),
+                ("USDT".to_string(), "bitstamp".to_string(), false),
+                ("USDT".to_string(), "kraken".to_string(), true),
+            ]
+        );
+
+        let Ok(StreamEvent::DepegAlert(alert)) = receiver.try_recv() else {
+            panic!("expected a depeg alert");
+        };
+        assert_eq!(alert.peg.exchange, "kraken");
+        assert!(receiver.try_recv().is_err());
+    }
+}
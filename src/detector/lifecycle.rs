@@ -0,0 +1,401 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::events::EventBus;
+use crate::log_info;
+use crate::models::{
+    ArbitrageOpportunity, OpportunityEvent, OpportunityEventKind, OpportunityStatus, StreamEvent, TrackedOpportunity,
+};
+
+/// Closed windows kept in memory for the API
+const CLOSED_HISTORY: usize = 1000;
+
+/// (symbol, buy exchange, sell exchange)
+type OpportunityKey = (String, String, String);
+
+#[derive(Debug, Default)]
+struct TrackerState {
+    /// Candidates above the enter threshold that have not persisted long enough yet
+    pending: HashMap<OpportunityKey, TrackedOpportunity>,
+    open: HashMap<OpportunityKey, TrackedOpportunity>,
+    closed: VecDeque<TrackedOpportunity>,
+}
+
+/// Turns the per-tick detections into opportunity windows keyed by
+/// (symbol, buy venue, sell venue).
+///
+/// A combination becomes a candidate once its profit reaches the enter
+/// threshold and is published as opened once it has stayed there for the
+/// minimum number of ticks and the minimum persistence time. An open window is
+/// updated while its profit stays at or above the exit threshold and closes on
+/// the first evaluation of the symbol where it drops below it or is no longer
+/// detected. Candidates that vanish before being published are dropped silently,
+/// so a spread oscillating around a single threshold does not flap.
+#[derive(Debug, Clone)]
+pub struct OpportunityTracker {
+    state: Arc<RwLock<TrackerState>>,
+    events: Option<EventBus>,
+    enter_threshold: f64,
+    exit_threshold: f64,
+    min_ticks: u64,
+    min_persistence: Duration,
+}
+
+impl OpportunityTracker {
+    pub fn new() -> Self {
+        OpportunityTracker {
+            state: Arc::new(RwLock::new(TrackerState::default())),
+            events: None,
+            enter_threshold: 0.0,
+            exit_threshold: 0.0,
+            min_ticks: 1,
+            min_persistence: Duration::ZERO,
+        }
+    }
+
+    /// Profit percentages a window opens at and closes below; `exit` is capped at `enter`
+    pub fn with_thresholds(mut self, enter: f64, exit: f64) -> Self {
+        self.enter_threshold = enter;
+        self.exit_threshold = exit.min(enter);
+        self
+    }
+
+    /// Consecutive ticks above the enter threshold before a window is published
+    pub fn with_min_ticks(mut self, min_ticks: u64) -> Self {
+        self.min_ticks = min_ticks.max(1);
+        self
+    }
+
+    /// Time above the enter threshold before a window is published
+    pub fn with_min_persistence(mut self, min_persistence: Duration) -> Self {
+        self.min_persistence = min_persistence;
+        self
+    }
+
+    /// Lowest profit percentage the detector has to report for the exit threshold to apply
+    pub fn exit_threshold(&self) -> f64 {
+        self.exit_threshold
+    }
+
+    /// Publish lifecycle events to the streaming API
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Apply the result of one evaluation of `symbol`, which must be the symbol
+    /// the opportunities are reported under, and return the resulting transitions
+    pub fn update(&self, symbol: &str, opportunities: Vec<ArbitrageOpportunity>) -> Vec<OpportunityEvent> {
+        self.update_at(symbol, opportunities, Utc::now())
+    }
+
+    /// Same as `update` for an evaluation that happened at `now`, e.g. the
+    /// receive time of the tick that triggered it when replaying recorded data
+    pub fn update_at(
+        &self,
+        symbol: &str,
+        opportunities: Vec<ArbitrageOpportunity>,
+        now: DateTime<Utc>,
+    ) -> Vec<OpportunityEvent> {
+        let mut events = Vec::new();
+        let mut state = self.state.write().unwrap();
+
+        let mut detected = Vec::with_capacity(opportunities.len());
+        for opportunity in opportunities {
+            let key = (opportunity.symbol.clone(), opportunity.buy_exchange.clone(), opportunity.sell_exchange.clone());
+
+            if let Some(tracked) = state.open.get_mut(&key) {
+                if opportunity.profit_percentage < self.exit_threshold {
+                    continue;
+                }
+                let changed = tracked.current_profit_percentage != opportunity.profit_percentage
+                    || tracked.latest.quantity != opportunity.quantity;
+                Self::refresh(tracked, opportunity, now);
+                if changed {
+                    events.push(OpportunityEvent { kind: OpportunityEventKind::Updated, opportunity: tracked.clone() });
+                }
+                detected.push(key);
+                continue;
+            }
+
+            if opportunity.profit_percentage < self.enter_threshold {
+                state.pending.remove(&key);
+                continue;
+            }
+            let candidate = match state.pending.remove(&key) {
+                Some(mut candidate) => {
+                    Self::refresh(&mut candidate, opportunity, now);
+                    candidate
+                }
+                None => Self::open(opportunity, now),
+            };
+            detected.push(key.clone());
+
+            if !self.has_persisted(&candidate, now) {
+                state.pending.insert(key, candidate);
+                continue;
+            }
+            log_info!(
+                symbol = %candidate.symbol,
+                buy_exchange = %candidate.buy_exchange,
+                sell_exchange = %candidate.sell_exchange,
+                profit_percentage = candidate.current_profit_percentage,
+                "Opportunity opened"
+            );
+            events.push(OpportunityEvent { kind: OpportunityEventKind::Opened, opportunity: candidate.clone() });
+            state.open.insert(key, candidate);
+        }
+
+        state.pending.retain(|key, _| key.0 != symbol || detected.contains(key));
+        let expired: Vec<OpportunityKey> = state.open
+            .keys()
+            .filter(|key| key.0 == symbol && !detected.contains(key))
+            .cloned()
+            .collect();
+        for key in expired {
+            let Some(mut tracked) = state.open.remove(&key) else {
+                continue;
+            };
+            tracked.status = OpportunityStatus::Closed;
+            tracked.closed_at = Some(now);
+            tracked.duration_ms = (now - tracked.opened_at).num_milliseconds();
+            log_info!(
+                symbol = %tracked.symbol,
+                buy_exchange = %tracked.buy_exchange,
+                sell_exchange = %tracked.sell_exchange,
+                duration_ms = tracked.duration_ms,
+                peak_profit_percentage = tracked.peak_profit_percentage,
+                "Opportunity closed"
+            );
+
+            events.push(OpportunityEvent { kind: OpportunityEventKind::Closed, opportunity: tracked.clone() });
+            state.closed.push_back(tracked);
+            if state.closed.len() > CLOSED_HISTORY {
+                state.closed.pop_front();
+            }
+        }
+        drop(state);
+
+        if let Some(bus) = &self.events {
+            for event in &events {
+                bus.publish(StreamEvent::Opportunity(Box::new(event.clone())));
+            }
+        }
+        events
+    }
+
+    /// Windows currently open, longest open first
+    pub fn open_opportunities(&self) -> Vec<TrackedOpportunity> {
+        let now = Utc::now();
+        let mut open: Vec<TrackedOpportunity> = self.state
+            .read()
+            .unwrap()
+            .open
+            .values()
+            .cloned()
+            .map(|mut tracked| {
+                tracked.duration_ms = (now - tracked.opened_at).num_milliseconds();
+                tracked
+            })
+            .collect();
+        open.sort_by_key(|tracked| tracked.opened_at);
+        open
+    }
+
+    /// Most recently closed windows, latest first
+    pub fn closed_opportunities(&self) -> Vec<TrackedOpportunity> {
+        self.state.read().unwrap().closed.iter().rev().cloned().collect()
+    }
+
+    fn has_persisted(&self, candidate: &TrackedOpportunity, now: DateTime<Utc>) -> bool {
+        let persisted = (now - candidate.opened_at).to_std().unwrap_or_default();
+        candidate.tick_count >= self.min_ticks && persisted >= self.min_persistence
+    }
+
+    fn open(opportunity: ArbitrageOpportunity, now: DateTime<Utc>) -> TrackedOpportunity {
+        let spread = opportunity.sell_price - opportunity.buy_price;
+        let mut latest = opportunity;
+        latest.timestamp = now;
+        latest.id = format!("{}-{}-{}-{}", latest.symbol, latest.buy_exchange, latest.sell_exchange, now.timestamp_millis());
+
+        TrackedOpportunity {
+            id: latest.id.clone(),
+            symbol: latest.symbol.clone(),
+            buy_exchange: latest.buy_exchange.clone(),
+            sell_exchange: latest.sell_exchange.clone(),
+            status: OpportunityStatus::Open,
+            opened_at: now,
+            updated_at: now,
+            closed_at: None,
+            duration_ms: 0,
+            tick_count: 1,
+            current_profit_percentage: latest.profit_percentage,
+            peak_profit_percentage: latest.profit_percentage,
+            current_spread: spread,
+            peak_spread: spread,
+            latest,
+        }
+    }
+
+    fn refresh(tracked: &mut TrackedOpportunity, mut opportunity: ArbitrageOpportunity, now: DateTime<Utc>) {
+        let spread = opportunity.sell_price - opportunity.buy_price;
+        opportunity.id = tracked.id.clone();
+        opportunity.timestamp = now;
+
+        tracked.updated_at = now;
+        tracked.duration_ms = (now - tracked.opened_at).num_milliseconds();
+        tracked.tick_count += 1;
+        tracked.current_profit_percentage = opportunity.profit_percentage;
+        tracked.peak_profit_percentage = tracked.peak_profit_percentage.max(opportunity.profit_percentage);
+        tracked.current_spread = spread;
+        tracked.peak_spread = tracked.peak_spread.max(spread);
+        tracked.latest = opportunity;
+    }
+}
+
+impl Default for OpportunityTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn opportunity(symbol: &str, profit_percentage: f64) -> ArbitrageOpportunity {
+        ArbitrageOpportunity {
+            id: String::new(),
+            symbol: symbol.to_string(),
+            buy_exchange: "binance".to_string(),
+            sell_exchange: "coinbase".to_string(),
+            buy_price: 100.0,
+            sell_price: 100.0 + profit_percentage,
+            profit_percentage,
+            quantity: 1.0,
+            buy_vwap: 100.0,
+            sell_vwap: 100.0 + profit_percentage,
+            buy_notional: 100.0,
+            sell_notional: 100.0 + profit_percentage,
+            expected_profit: profit_percentage,
+            buy_symbol: symbol.to_string(),
+            sell_symbol: symbol.to_string(),
+            buy_conversion: None,
+            sell_conversion: None,
+            timestamp: Utc::now(),
+        }
+    }
+
+    fn at(seconds: i64) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap() + chrono::Duration::seconds(seconds)
+    }
+
+    fn kinds(events: &[OpportunityEvent]) -> Vec<OpportunityEventKind> {
+        events.iter().map(|event| event.kind).collect()
+    }
+
+    #[test]
+    fn opens_at_the_enter_threshold_and_closes_below_the_exit_threshold() {
+        let tracker = OpportunityTracker::new().with_thresholds(0.5, 0.2);
+        let update = |profit: f64, second: i64| tracker.update_at("BTCUSDT", vec![opportunity("BTCUSDT", profit)], at(second));
+
+        assert!(update(0.4, 0).is_empty());
+        assert_eq!(kinds(&update(0.6, 1)), [OpportunityEventKind::Opened]);
+        // Between the thresholds the window stays open
+        assert_eq!(kinds(&update(0.3, 2)), [OpportunityEventKind::Updated]);
+
+        let events = update(0.1, 4);
+        assert_eq!(kinds(&events), [OpportunityEventKind::Closed]);
+        let closed = &events[0].opportunity;
+        assert_eq!(closed.status, OpportunityStatus::Closed);
+        assert_eq!(closed.opened_at, at(1));
+        assert_eq!(closed.closed_at, Some(at(4)));
+        assert_eq!(closed.duration_ms, 3000);
+        assert_eq!(closed.peak_profit_percentage, 0.6);
+
+        // Back between the thresholds is not enough to reopen
+        assert!(update(0.3, 5).is_empty());
+        assert!(tracker.open_opportunities().is_empty());
+        assert_eq!(tracker.closed_opportunities().len(), 1);
+    }
+
+    #[test]
+    fn exit_threshold_is_capped_at_the_enter_threshold() {
+        assert_eq!(OpportunityTracker::new().with_thresholds(0.5, 0.8).exit_threshold(), 0.5);
+    }
+
+    #[test]
+    fn publishes_after_the_minimum_consecutive_ticks() {
+        let tracker = OpportunityTracker::new().with_min_ticks(3);
+        let update = |second: i64| tracker.update_at("BTCUSDT", vec![opportunity("BTCUSDT", 0.6)], at(second));
+
+        assert!(update(0).is_empty());
+        assert!(update(1).is_empty());
+        // The candidate vanishes before being published: dropped without any event
+        assert!(tracker.update_at("BTCUSDT", Vec::new(), at(2)).is_empty());
+
+        assert!(update(3).is_empty());
+        assert!(update(4).is_empty());
+        let events = update(5);
+        assert_eq!(kinds(&events), [OpportunityEventKind::Opened]);
+        assert_eq!(events[0].opportunity.tick_count, 3);
+        assert_eq!(events[0].opportunity.opened_at, at(3));
+    }
+
+    #[test]
+    fn publishes_after_the_minimum_persistence() {
+        let tracker = OpportunityTracker::new().with_min_persistence(Duration::from_secs(2));
+        let update = |second: i64| tracker.update_at("BTCUSDT", vec![opportunity("BTCUSDT", 0.6)], at(second));
+
+        assert!(update(0).is_empty());
+        assert!(update(1).is_empty());
+        assert_eq!(kinds(&update(2)), [OpportunityEventKind::Opened]);
+    }
+
+    #[test]
+    fn updates_only_on_change_and_tracks_the_peak() {
+        let tracker = OpportunityTracker::new();
+        let update = |profit: f64, second: i64| tracker.update_at("BTCUSDT", vec![opportunity("BTCUSDT", profit)], at(second));
+
+        update(0.6, 0);
+        assert_eq!(kinds(&update(0.9, 1)), [OpportunityEventKind::Updated]);
+        assert!(update(0.9, 2).is_empty());
+        update(0.7, 3);
+
+        let open = tracker.open_opportunities();
+        assert_eq!(open.len(), 1);
+        assert_eq!(open[0].tick_count, 4);
+        assert_eq!(open[0].current_profit_percentage, 0.7);
+        assert_eq!(open[0].peak_profit_percentage, 0.9);
+    }
+
+    #[test]
+    fn only_the_evaluated_symbol_closes() {
+        let tracker = OpportunityTracker::new();
+        tracker.update_at("BTCUSDT", vec![opportunity("BTCUSDT", 0.6)], at(0));
+        tracker.update_at("ETHUSDT", vec![opportunity("ETHUSDT", 0.6)], at(0));
+
+        let events = tracker.update_at("ETHUSDT", Vec::new(), at(1));
+        assert_eq!(kinds(&events), [OpportunityEventKind::Closed]);
+        assert_eq!(events[0].opportunity.symbol, "ETHUSDT");
+        assert_eq!(tracker.open_opportunities()[0].symbol, "BTCUSDT");
+    }
+
+    #[test]
+    fn publishes_events_to_the_bus() {
+        let events = EventBus::new();
+        let mut receiver = events.subscribe();
+        let tracker = OpportunityTracker::new().with_events(events);
+        tracker.update_at("BTCUSDT", vec![opportunity("BTCUSDT", 0.6)], at(0));
+
+        let Ok(StreamEvent::Opportunity(event)) = receiver.try_recv() else {
+            panic!("expected an opportunity event");
+        };
+        assert_eq!(event.kind, OpportunityEventKind::Opened);
+    }
+}
@@ -0,0 +1,335 @@
+pub mod depeg;
+pub mod lifecycle;
+pub mod negative_cycle;
+pub mod quote_conversion;
+pub mod sizing;
+pub mod triangular;
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+
+use crate::models::{ArbitrageOpportunity, ConversionRate, CurrencyPair};
+use crate::orderbook::{Level, OrderBook, OrderBookStore, Side};
+use crate::socket::tick_filter::TickFilter;
+use quote_conversion::QuoteConverter;
+use sizing::size_levels;
+
+/// Taker fee rates (as fractions, e.g. `0.001` for 0.1%) per exchange
+#[derive(Debug, Clone)]
+pub struct FeeSchedule {
+    default_fee: f64,
+    fees: HashMap<String, f64>,
+}
+
+impl FeeSchedule {
+    pub fn new(default_fee: f64) -> Self {
+        FeeSchedule {
+            default_fee,
+            fees: HashMap::new(),
+        }
+    }
+
+    pub fn with_fee(mut self, exchange: &str, fee: f64) -> Self {
+        self.fees.insert(exchange.to_string(), fee);
+        self
+    }
+
+    pub fn taker_fee(&self, exchange: &str) -> f64 {
+        self.fees.get(exchange).copied().unwrap_or(self.default_fee)
+    }
+}
+
+impl Default for FeeSchedule {
+    /// Public base-tier taker fees of the supported venues
+    fn default() -> Self {
+        FeeSchedule::new(0.001)
+            .with_fee("binance", 0.001)
+            .with_fee("coinbase", 0.006)
+            .with_fee("gemini", 0.004)
+            .with_fee("bitstamp", 0.004)
+    }
+}
+
+/// Cross-exchange detector comparing the order books of the same symbol on
+/// every pair of venues and sizing the executable opportunity between them.
+///
+/// With a `QuoteConverter`, markets sharing a base currency but quoted in
+/// different stablecoins (`BTCUSDT`, `BTCUSDC`, `BTCUSD`) are compared too,
+/// their prices being expressed in the converter's reference currency.
+///
+/// With a `TickFilter`, books whose top is an outlier are left out.
+#[derive(Debug, Clone)]
+pub struct ArbitrageDetector {
+    order_books: OrderBookStore,
+    fees: FeeSchedule,
+    min_profit_percentage: f64,
+    max_quantity: Option<f64>,
+    quote_converter: Option<QuoteConverter>,
+    tick_filter: Option<TickFilter>,
+}
+
+impl ArbitrageDetector {
+    pub fn new(order_books: OrderBookStore) -> Self {
+        ArbitrageDetector {
+            order_books,
+            fees: FeeSchedule::default(),
+            min_profit_percentage: 0.0,
+            max_quantity: None,
+            quote_converter: None,
+            tick_filter: None,
+        }
+    }
+
+    pub fn with_fees(mut self, fees: FeeSchedule) -> Self {
+        self.fees = fees;
+        self
+    }
+
+    /// Only report opportunities whose net profit exceeds this percentage
+    pub fn with_min_profit_percentage(mut self, min_profit_percentage: f64) -> Self {
+        self.min_profit_percentage = min_profit_percentage;
+        self
+    }
+
+    /// Cap the sized quantity, e.g. to the inventory available on each venue
+    pub fn with_max_quantity(mut self, max_quantity: f64) -> Self {
+        self.max_quantity = Some(max_quantity);
+        self
+    }
+
+    /// Compare markets quoted in different convertible currencies through live rates
+    pub fn with_quote_converter(mut self, quote_converter: QuoteConverter) -> Self {
+        self.quote_converter = Some(quote_converter);
+        self
+    }
+
+    /// Leave out the books whose top the filter rejects
+    pub fn with_tick_filter(mut self, tick_filter: TickFilter) -> Self {
+        self.tick_filter = Some(tick_filter);
+        self
+    }
+
+    pub fn fees(&self) -> &FeeSchedule {
+        &self.fees
+    }
+
+    pub fn quote_converter(&self) -> Option<&QuoteConverter> {
+        self.quote_converter.as_ref()
+    }
+
+    /// Evaluate every (buy venue, sell venue) combination of a symbol
+    pub fn detect(&self, symbol: &str) -> Vec<ArbitrageOpportunity> {
+        self.detect_at(symbol, Utc::now())
+    }
+
+    /// Same as `detect` with the opportunities stamped `timestamp`, e.g. the
+    /// receive time of the tick that triggered the evaluation when replaying
+    pub fn detect_at(&self, symbol: &str, timestamp: DateTime<Utc>) -> Vec<ArbitrageOpportunity> {
+        let (symbol, books) = self.comparable_books(symbol, timestamp);
+
+        let mut opportunities = Vec::new();
+        for (buy_book, buy_conversion) in &books {
+            for (sell_book, sell_conversion) in &books {
+                if buy_book.exchange == sell_book.exchange {
+                    continue;
+                }
+
+                let buy_fee = self.fees.taker_fee(&buy_book.exchange);
+                let sell_fee = self.fees.taker_fee(&sell_book.exchange);
+                let asks = Self::converted_levels(buy_book, Side::Ask, buy_conversion.as_ref());
+                let bids = Self::converted_levels(sell_book, Side::Bid, sell_conversion.as_ref());
+                let Some(sized) = size_levels(&asks, &bids, buy_fee, sell_fee, self.max_quantity) else {
+                    continue;
+                };
+
+                let profit_percentage = sized.profit_percentage(buy_fee);
+                if profit_percentage <= self.min_profit_percentage {
+                    continue;
+                }
+
+                opportunities.push(ArbitrageOpportunity {
+                    id: format!("{}-{}-{}-{}", symbol, buy_book.exchange, sell_book.exchange, timestamp.timestamp_millis()),
+                    symbol: symbol.clone(),
+                    buy_exchange: buy_book.exchange.clone(),
+                    sell_exchange: sell_book.exchange.clone(),
+                    buy_price: asks.first().map_or(sized.buy_vwap, |level| level.price),
+                    sell_price: bids.first().map_or(sized.sell_vwap, |level| level.price),
+                    profit_percentage,
+                    quantity: sized.quantity,
+                    buy_vwap: sized.buy_vwap,
+                    sell_vwap: sized.sell_vwap,
+                    buy_notional: sized.buy_notional,
+                    sell_notional: sized.sell_notional,
+                    expected_profit: sized.expected_profit,
+                    buy_symbol: buy_book.symbol.clone(),
+                    sell_symbol: sell_book.symbol.clone(),
+                    buy_conversion: buy_conversion.clone(),
+                    sell_conversion: sell_conversion.clone(),
+                    timestamp,
+                });
+            }
+        }
+
+        opportunities
+    }
+
+    /// Symbol the opportunities of `symbol` are reported under, e.g. `BTCUSD`
+    /// for `BTCUSDT` when quotes are converted to USD
+    pub fn normalized_symbol(&self, symbol: &str) -> String {
+        match self.convertible_pair(symbol) {
+            Some((converter, pair)) => format!("{}{}", pair.base, converter.reference_currency()),
+            None => symbol.to_string(),
+        }
+    }
+
+    fn convertible_pair(&self, symbol: &str) -> Option<(&QuoteConverter, CurrencyPair)> {
+        let converter = self.quote_converter.as_ref()?;
+        let pair = CurrencyPair::parse(symbol)?;
+        converter.is_convertible(&pair.quote).then_some((converter, pair))
+    }
+
+    /// Books comparable with `symbol` and the rate expressing each of them in
+    /// the reported symbol's quote currency.
+    ///
+    /// Without a converter, or for a quote currency it cannot convert, only the
+    /// books of the exact symbol are compared. Books quoted in a currency
+    /// without a live rate at `at` are left out.
+    fn comparable_books(&self, symbol: &str, at: DateTime<Utc>) -> (String, Vec<(OrderBook, Option<ConversionRate>)>) {
+        let Some((converter, pair)) = self.convertible_pair(symbol) else {
+            let books = self.order_books
+                .keys()
+                .into_iter()
+                .filter(|(_, book_symbol)| book_symbol == symbol)
+                .filter_map(|(exchange, book_symbol)| self.order_books.get(&exchange, &book_symbol))
+                .filter(|book| self.passes_filter(book))
+                .map(|book| (book, None))
+                .collect();
+            return (symbol.to_string(), books);
+        };
+
+        let books = self.order_books
+            .keys()
+            .into_iter()
+            .filter_map(|(exchange, book_symbol)| {
+                let book_pair = CurrencyPair::parse(&book_symbol)?;
+                if book_pair.base != pair.base || !converter.is_convertible(&book_pair.quote) {
+                    return None;
+                }
+                let conversion = converter.rate_at(&book_pair.quote, at)?;
+                let book = self.order_books.get(&exchange, &book_symbol)?;
+                self.passes_filter(&book).then_some((book, Some(conversion)))
+            })
+            .collect();
+        (self.normalized_symbol(symbol), books)
+    }
+
+    fn passes_filter(&self, book: &OrderBook) -> bool {
+        self.tick_filter.as_ref().is_none_or(|filter| filter.check_book(book).is_ok())
+    }
+
+    /// Levels of one side, best first, with prices expressed in the reference currency
+    fn converted_levels(book: &OrderBook, side: Side, conversion: Option<&ConversionRate>) -> Vec<Level> {
+        let rate = conversion.map_or(1.0, |conversion| conversion.rate);
+        book.levels(side)
+            .map(|level| Level::new(level.price * rate, level.quantity))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> OrderBookStore {
+        let store = OrderBookStore::new();
+        store.update("binance", "BTCUSDT", |book| {
+            book.apply_snapshot(&[Level::new(99.0, 1.0)], &[Level::new(100.0, 1.0), Level::new(101.0, 2.0)], 1);
+        });
+        store.update("coinbase", "BTCUSDT", |book| {
+            book.apply_snapshot(&[Level::new(103.0, 1.5), Level::new(102.0, 1.0)], &[Level::new(104.0, 1.0)], 1);
+        });
+        store.update("coinbase", "ETHUSDT", |book| {
+            book.apply_snapshot(&[Level::new(10.0, 1.0)], &[Level::new(11.0, 1.0)], 1);
+        });
+        store
+    }
+
+    #[test]
+    fn detects_the_profitable_direction_only() {
+        let detector = ArbitrageDetector::new(store()).with_fees(FeeSchedule::new(0.0));
+        let opportunities = detector.detect("BTCUSDT");
+
+        assert_eq!(opportunities.len(), 1);
+        let opportunity = &opportunities[0];
+        assert_eq!(opportunity.buy_exchange, "binance");
+        assert_eq!(opportunity.sell_exchange, "coinbase");
+        assert_eq!(opportunity.buy_price, 100.0);
+        assert_eq!(opportunity.sell_price, 103.0);
+        assert!((opportunity.quantity - 2.5).abs() < 1e-9);
+        assert!((opportunity.expected_profit - 5.0).abs() < 1e-9);
+        assert!((opportunity.profit_percentage - 5.0 / 251.5 * 100.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn stamps_opportunities_with_the_evaluation_time() {
+        let detector = ArbitrageDetector::new(store()).with_fees(FeeSchedule::new(0.0));
+        let at = DateTime::from_timestamp(1_767_225_600, 0).unwrap();
+        let opportunity = &detector.detect_at("BTCUSDT", at)[0];
+        assert_eq!(opportunity.timestamp, at);
+        assert_eq!(opportunity.id, "BTCUSDT-binance-coinbase-1767225600000");
+    }
+
+    #[test]
+    fn applies_fees_and_minimum_profit() {
+        // Every level stays profitable after fees: 256.5 * 0.994 - 251.5 * 1.001 on 251.75, about 1.27%
+        let fees = FeeSchedule::new(0.0).with_fee("binance", 0.001).with_fee("coinbase", 0.006);
+        let detector = ArbitrageDetector::new(store()).with_fees(fees.clone());
+        let opportunity = &detector.detect("BTCUSDT")[0];
+        assert!((opportunity.quantity - 2.5).abs() < 1e-9);
+        assert!((opportunity.expected_profit - (256.5 * 0.994 - 251.5 * 1.001)).abs() < 1e-9);
+        assert!((opportunity.profit_percentage - 1.2749).abs() < 1e-4);
+
+        let detector = ArbitrageDetector::new(store()).with_fees(fees).with_min_profit_percentage(1.5);
+        assert!(detector.detect("BTCUSDT").is_empty());
+    }
+
+    #[test]
+    fn caps_the_quantity_and_ignores_single_venue_symbols() {
+        let detector = ArbitrageDetector::new(store())
+            .with_fees(FeeSchedule::new(0.0))
+            .with_max_quantity(0.5);
+        assert!((detector.detect("BTCUSDT")[0].quantity - 0.5).abs() < 1e-9);
+        assert!(detector.detect("ETHUSDT").is_empty());
+        assert!(detector.detect("XRPUSDT").is_empty());
+    }
+
+    #[test]
+    fn leaves_out_books_the_tick_filter_rejects() {
+        use crate::models::SymbolMessage;
+
+        let filter = TickFilter::new();
+        for i in 0..10 {
+            filter.check(&SymbolMessage::new("binance", "BTCUSDT".to_string(), 100.0 + i as f64 * 0.1)).unwrap();
+        }
+        let store = store();
+        let detector = ArbitrageDetector::new(store.clone())
+            .with_fees(FeeSchedule::new(0.0))
+            .with_tick_filter(filter.clone());
+        assert_eq!(detector.detect("BTCUSDT").len(), 1);
+
+        // A bad quote on coinbase would look like a 400% spread
+        store.update("coinbase", "BTCUSDT", |book| {
+            book.apply_snapshot(&[Level::new(500.0, 1.0)], &[Level::new(501.0, 1.0)], 2);
+        });
+        assert!(detector.detect("BTCUSDT").is_empty());
+        assert_eq!(filter.rejection_counters()["coinbase"].books, 1);
+    }
+
+    #[test]
+    fn fee_schedule_falls_back_to_the_default() {
+        let fees = FeeSchedule::new(0.002).with_fee("binance", 0.001);
+        assert_eq!(fees.taker_fee("binance"), 0.001);
+        assert_eq!(fees.taker_fee("kraken"), 0.002);
+    }
+}
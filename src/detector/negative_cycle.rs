@@ -0,0 +1,496 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use chrono::{DateTime, Utc};
+
+use crate::detector::FeeSchedule;
+use crate::models::{CurrencyPair, MultiLegKind, MultiLegOpportunity, OpportunityLeg, TradeSide};
+use crate::orderbook::OrderBookStore;
+
+/// Relaxations smaller than this are treated as floating point noise
+const EPSILON: f64 = 1e-12;
+
+/// A currency held on a given exchange
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Node {
+    exchange: String,
+    currency: String,
+}
+
+#[derive(Debug, Clone)]
+struct GraphEdge {
+    from: usize,
+    to: usize,
+    /// `-ln(rate)`, infinite while the market has no quote
+    weight: f64,
+    side: TradeSide,
+    /// Market symbol for trades, transferred currency for transfers
+    symbol: String,
+    price: f64,
+}
+
+/// Finds profitable conversion cycles of any length across every
+/// (exchange, pair) quote and cross-exchange transfer.
+///
+/// Each quote becomes a `-ln(rate)` edge after the taker fee, so a cycle whose
+/// weights sum to a negative value multiplies the start amount by more than one.
+/// Distances are kept between runs: a quote that improved only re-relaxes from
+/// its source node (SPFA), while a quote that worsened triggers a full
+/// Bellman-Ford pass since previously found distances may no longer hold.
+#[derive(Debug, Clone)]
+pub struct NegativeCycleDetector {
+    order_books: OrderBookStore,
+    fees: FeeSchedule,
+    transfer_fee: f64,
+    min_profit_percentage: f64,
+    nodes: Vec<Node>,
+    edges: Vec<GraphEdge>,
+    outgoing: Vec<Vec<usize>>,
+    /// (exchange, symbol) => [buy edge, sell edge]
+    markets: HashMap<(String, String), [usize; 2]>,
+    distance: Vec<f64>,
+    parent: Vec<Option<usize>>,
+    pending_sources: HashSet<usize>,
+    needs_full_run: bool,
+}
+
+impl NegativeCycleDetector {
+    /// Build the graph from (exchange, symbol) markets, e.g. `("binance", "BTCUSDT")`
+    pub fn new(markets: &[(String, String)], order_books: OrderBookStore) -> Self {
+        let mut detector = NegativeCycleDetector {
+            order_books,
+            fees: FeeSchedule::default(),
+            transfer_fee: 0.001,
+            min_profit_percentage: 0.0,
+            nodes: Vec::new(),
+            edges: Vec::new(),
+            outgoing: Vec::new(),
+            markets: HashMap::new(),
+            distance: Vec::new(),
+            parent: Vec::new(),
+            pending_sources: HashSet::new(),
+            needs_full_run: true,
+        };
+
+        for (exchange, symbol) in markets {
+            let Some(pair) = CurrencyPair::parse(symbol) else {
+                continue;
+            };
+            let base = detector.node(exchange, &pair.base);
+            let quote = detector.node(exchange, &pair.quote);
+            let buy = detector.add_edge(quote, base, TradeSide::Buy, &pair.symbol());
+            let sell = detector.add_edge(base, quote, TradeSide::Sell, &pair.symbol());
+            detector.markets.insert((exchange.clone(), pair.symbol()), [buy, sell]);
+        }
+        detector.add_transfer_edges();
+
+        detector.distance = vec![0.0; detector.nodes.len()];
+        detector.parent = vec![None; detector.nodes.len()];
+        detector
+    }
+
+    pub fn with_fees(mut self, fees: FeeSchedule) -> Self {
+        self.fees = fees;
+        self
+    }
+
+    /// Cost of moving a currency between exchanges, as a fraction of the amount
+    pub fn with_transfer_fee(mut self, transfer_fee: f64) -> Self {
+        self.transfer_fee = transfer_fee;
+        let weight = -(1.0 - transfer_fee).ln();
+        for edge in self.edges.iter_mut().filter(|edge| edge.side == TradeSide::Transfer) {
+            edge.weight = weight;
+        }
+        self.needs_full_run = true;
+        self
+    }
+
+    /// Only report cycles whose net profit exceeds this percentage
+    pub fn with_min_profit_percentage(mut self, min_profit_percentage: f64) -> Self {
+        self.min_profit_percentage = min_profit_percentage;
+        self
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn edge_count(&self) -> usize {
+        self.edges.len()
+    }
+
+    /// Reload the buy / sell edges of a market from its top of book
+    pub fn refresh_market(&mut self, exchange: &str, symbol: &str) {
+        let Some([buy, sell]) = self.markets.get(&(exchange.to_string(), symbol.to_string())).copied() else {
+            return;
+        };
+
+        let fee = self.fees.taker_fee(exchange);
+        let quotes = self.order_books.top_of_book(exchange, symbol);
+        let (buy_price, sell_price) = quotes.map_or((f64::NAN, f64::NAN), |(bid, ask)| (ask.price, bid.price));
+
+        self.set_edge(buy, buy_price, -((1.0 - fee) / buy_price).ln());
+        self.set_edge(sell, sell_price, -(sell_price * (1.0 - fee)).ln());
+    }
+
+    /// Reload every market of the graph
+    pub fn refresh_all(&mut self) {
+        let markets: Vec<(String, String)> = self.markets.keys().cloned().collect();
+        for (exchange, symbol) in markets {
+            self.refresh_market(&exchange, &symbol);
+        }
+    }
+
+    /// Run negative cycle detection on the current quotes
+    pub fn detect(&mut self) -> Vec<MultiLegOpportunity> {
+        self.detect_at(Utc::now())
+    }
+
+    /// Same as `detect` with the opportunities stamped `timestamp`, e.g. the
+    /// receive time of the tick that refreshed the graph
+    pub fn detect_at(&mut self, timestamp: DateTime<Utc>) -> Vec<MultiLegOpportunity> {
+        let cycles = if self.needs_full_run {
+            self.bellman_ford()
+        } else {
+            self.relax_pending()
+        };
+        self.pending_sources.clear();
+
+        // Distances around a negative cycle keep decreasing, start over next time
+        self.needs_full_run = !cycles.is_empty();
+
+        cycles.into_iter().filter_map(|cycle| self.to_opportunity(&cycle, timestamp)).collect()
+    }
+
+    fn node(&mut self, exchange: &str, currency: &str) -> usize {
+        let node = Node { exchange: exchange.to_string(), currency: currency.to_string() };
+        if let Some(index) = self.nodes.iter().position(|existing| *existing == node) {
+            return index;
+        }
+        self.nodes.push(node);
+        self.outgoing.push(Vec::new());
+        self.nodes.len() - 1
+    }
+
+    fn add_edge(&mut self, from: usize, to: usize, side: TradeSide, symbol: &str) -> usize {
+        self.edges.push(GraphEdge {
+            from,
+            to,
+            weight: f64::INFINITY,
+            side,
+            symbol: symbol.to_string(),
+            price: f64::NAN,
+        });
+        self.outgoing[from].push(self.edges.len() - 1);
+        self.edges.len() - 1
+    }
+
+    fn add_transfer_edges(&mut self) {
+        let weight = -(1.0 - self.transfer_fee).ln();
+        for from in 0..self.nodes.len() {
+            for to in 0..self.nodes.len() {
+                if from != to
+                    && self.nodes[from].currency == self.nodes[to].currency
+                    && self.nodes[from].exchange != self.nodes[to].exchange
+                {
+                    let currency = self.nodes[from].currency.clone();
+                    let edge = self.add_edge(from, to, TradeSide::Transfer, &currency);
+                    self.edges[edge].weight = weight;
+                    self.edges[edge].price = 1.0;
+                }
+            }
+        }
+    }
+
+    fn set_edge(&mut self, index: usize, price: f64, weight: f64) {
+        let weight = if weight.is_finite() { weight } else { f64::INFINITY };
+        let edge = &mut self.edges[index];
+        if weight > edge.weight + EPSILON {
+            self.needs_full_run = true;
+        } else if weight < edge.weight - EPSILON {
+            self.pending_sources.insert(edge.from);
+        }
+        edge.weight = weight;
+        edge.price = price;
+    }
+
+    /// Full Bellman-Ford from a virtual source connected to every node
+    fn bellman_ford(&mut self) -> Vec<Vec<usize>> {
+        self.distance.iter_mut().for_each(|distance| *distance = 0.0);
+        self.parent.iter_mut().for_each(|parent| *parent = None);
+
+        for _ in 1..self.nodes.len() {
+            let mut relaxed = false;
+            for (index, edge) in self.edges.iter().enumerate() {
+                let candidate = self.distance[edge.from] + edge.weight;
+                if candidate < self.distance[edge.to] - EPSILON {
+                    self.distance[edge.to] = candidate;
+                    self.parent[edge.to] = Some(index);
+                    relaxed = true;
+                }
+            }
+            if !relaxed {
+                return Vec::new();
+            }
+        }
+
+        // Any edge that still relaxes leads into a negative cycle
+        let mut cycles = Vec::new();
+        let mut seen = HashSet::new();
+        for (index, edge) in self.edges.iter().enumerate() {
+            if self.distance[edge.from] + edge.weight < self.distance[edge.to] - EPSILON {
+                let mut parent = self.parent.clone();
+                parent[edge.to] = Some(index);
+                if let Some(cycle) = self.extract_cycle(&parent, edge.to)
+                    && seen.insert(Self::cycle_key(&cycle))
+                {
+                    cycles.push(cycle);
+                }
+            }
+        }
+        cycles
+    }
+
+    /// Incremental SPFA relaxation from the sources of improved edges
+    fn relax_pending(&mut self) -> Vec<Vec<usize>> {
+        let mut queue: VecDeque<usize> = self.pending_sources.iter().copied().collect();
+        let mut queued: HashSet<usize> = queue.iter().copied().collect();
+        let mut cycles = Vec::new();
+        let mut seen = HashSet::new();
+        let mut budget = self.nodes.len() * self.edges.len().max(1);
+
+        while let Some(node) = queue.pop_front() {
+            queued.remove(&node);
+            for &index in &self.outgoing[node] {
+                let edge = &self.edges[index];
+                let candidate = self.distance[edge.from] + edge.weight;
+                if candidate >= self.distance[edge.to] - EPSILON {
+                    continue;
+                }
+
+                self.distance[edge.to] = candidate;
+                self.parent[edge.to] = Some(index);
+                if let Some(cycle) = self.extract_cycle(&self.parent, edge.to) {
+                    if seen.insert(Self::cycle_key(&cycle)) {
+                        cycles.push(cycle);
+                    }
+                    continue;
+                }
+                if queued.insert(edge.to) {
+                    queue.push_back(edge.to);
+                }
+            }
+
+            budget = budget.saturating_sub(1);
+            if budget == 0 {
+                break;
+            }
+        }
+        cycles
+    }
+
+    /// Follow parent edges from `start`; returns the cycle's edges in traversal order if one is reached
+    fn extract_cycle(&self, parent: &[Option<usize>], start: usize) -> Option<Vec<usize>> {
+        // Walking |V| steps guarantees we end up inside the cycle if there is one
+        let mut node = start;
+        for _ in 0..self.nodes.len() {
+            node = self.edges[parent[node]?].from;
+        }
+
+        let mut cycle = Vec::new();
+        let cycle_start = node;
+        loop {
+            let edge = parent[node]?;
+            cycle.push(edge);
+            node = self.edges[edge].from;
+            if node == cycle_start {
+                break;
+            }
+            if cycle.len() > self.nodes.len() {
+                return None;
+            }
+        }
+        cycle.reverse();
+
+        let weight: f64 = cycle.iter().map(|edge| self.edges[*edge].weight).sum();
+        (weight < -EPSILON).then_some(cycle)
+    }
+
+    fn cycle_key(cycle: &[usize]) -> Vec<usize> {
+        let mut key = cycle.to_vec();
+        key.sort_unstable();
+        key
+    }
+
+    fn to_opportunity(&self, cycle: &[usize], timestamp: DateTime<Utc>) -> Option<MultiLegOpportunity> {
+        // Start from the most quote-like currency, then the first exchange by name,
+        // so the same loop is always reported alike
+        let rank = |edge: &usize| {
+            let node = &self.nodes[self.edges[*edge].from];
+            let position = CurrencyPair::QUOTES.iter().position(|quote| *quote == node.currency);
+            (position.unwrap_or(usize::MAX), node.currency.clone(), node.exchange.clone())
+        };
+        let start = (0..cycle.len()).min_by_key(|index| rank(&cycle[*index]))?;
+        let ordered: Vec<usize> = cycle[start..].iter().chain(&cycle[..start]).copied().collect();
+
+        let weight: f64 = ordered.iter().map(|edge| self.edges[*edge].weight).sum();
+        let profit_percentage = ((-weight).exp() - 1.0) * 100.0;
+        if profit_percentage <= self.min_profit_percentage {
+            return None;
+        }
+
+        let legs: Vec<OpportunityLeg> = ordered
+            .iter()
+            .map(|index| {
+                let edge = &self.edges[*index];
+                let from = &self.nodes[edge.from];
+                let to = &self.nodes[edge.to];
+                OpportunityLeg {
+                    exchange: from.exchange.clone(),
+                    symbol: edge.symbol.clone(),
+                    side: edge.side,
+                    from_currency: from.currency.clone(),
+                    to_currency: to.currency.clone(),
+                    price: edge.price,
+                    rate: (-edge.weight).exp(),
+                    destination_exchange: (edge.side == TradeSide::Transfer).then(|| to.exchange.clone()),
+                }
+            })
+            .collect();
+
+        let route: Vec<String> = legs.iter().map(|leg| format!("{}:{}", leg.exchange, leg.from_currency)).collect();
+        Some(MultiLegOpportunity {
+            id: format!("cycle-{}-{}", route.join("-"), timestamp.timestamp_millis()),
+            kind: MultiLegKind::NegativeCycle,
+            start_currency: legs.first()?.from_currency.clone(),
+            legs,
+            profit_percentage,
+            max_start_amount: None,
+            timestamp,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orderbook::Level;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-6, "{} != {}", actual, expected);
+    }
+
+    fn set_top(store: &OrderBookStore, exchange: &str, symbol: &str, bid: f64, ask: f64) {
+        store.update(exchange, symbol, |book| {
+            book.apply_snapshot(&[Level::new(bid, 1.0)], &[Level::new(ask, 1.0)], 1)
+        });
+    }
+
+    fn markets(markets: &[(&str, &str)]) -> Vec<(String, String)> {
+        markets.iter().map(|(exchange, symbol)| (exchange.to_string(), symbol.to_string())).collect()
+    }
+
+    fn cross_exchange() -> (NegativeCycleDetector, OrderBookStore) {
+        let store = OrderBookStore::new();
+        set_top(&store, "binance", "BTCUSDT", 49_990.0, 50_000.0);
+        set_top(&store, "coinbase", "BTCUSDT", 50_000.0, 50_010.0);
+        let detector = NegativeCycleDetector::new(&markets(&[("binance", "BTCUSDT"), ("coinbase", "BTCUSDT")]), store.clone())
+            .with_fees(FeeSchedule::new(0.001))
+            .with_transfer_fee(0.001);
+        (detector, store)
+    }
+
+    #[test]
+    fn builds_trade_and_transfer_edges() {
+        let (detector, _) = cross_exchange();
+        // BTC and USDT on both venues
+        assert_eq!(detector.node_count(), 4);
+        // Buy and sell per market, both ways per currency between the venues
+        assert_eq!(detector.edge_count(), 8);
+    }
+
+    #[test]
+    fn detects_a_triangle_on_one_venue() {
+        let store = OrderBookStore::new();
+        set_top(&store, "binance", "BTCUSDT", 49_990.0, 50_000.0);
+        set_top(&store, "binance", "ETHBTC", 0.0599, 0.06);
+        set_top(&store, "binance", "ETHUSDT", 3_100.0, 3_101.0);
+        let mut detector = NegativeCycleDetector::new(
+            &markets(&[("binance", "BTCUSDT"), ("binance", "ETHBTC"), ("binance", "ETHUSDT")]),
+            store,
+        )
+        .with_fees(FeeSchedule::new(0.001));
+        detector.refresh_all();
+
+        let received_at = DateTime::from_timestamp_millis(1_767_225_600_000).unwrap();
+        let cycles = detector.detect_at(received_at);
+        assert_eq!(cycles.len(), 1);
+        let cycle = &cycles[0];
+        assert_eq!(cycle.timestamp, received_at);
+        assert_eq!(cycle.id, "cycle-binance:USDT-binance:BTC-binance:ETH-1767225600000");
+        assert_eq!(cycle.kind, MultiLegKind::NegativeCycle);
+        assert_eq!(cycle.start_currency, "USDT");
+        let route: Vec<&str> = cycle.legs.iter().map(|leg| leg.to_currency.as_str()).collect();
+        assert_eq!(route, ["BTC", "ETH", "USDT"]);
+        assert_close(cycle.profit_percentage, (3_100.0 / 3_000.0 * 0.999_f64.powi(3) - 1.0) * 100.0);
+    }
+
+    #[test]
+    fn detects_a_cycle_through_transfers_incrementally() {
+        let (mut detector, store) = cross_exchange();
+        detector.refresh_all();
+        assert!(detector.detect().is_empty());
+
+        // Coinbase bids 1% above the Binance ask
+        set_top(&store, "coinbase", "BTCUSDT", 50_500.0, 50_510.0);
+        detector.refresh_market("coinbase", "BTCUSDT");
+        let cycles = detector.detect();
+        assert_eq!(cycles.len(), 1);
+
+        let cycle = &cycles[0];
+        let legs: Vec<(&str, TradeSide, Option<&str>)> = cycle
+            .legs
+            .iter()
+            .map(|leg| (leg.exchange.as_str(), leg.side, leg.destination_exchange.as_deref()))
+            .collect();
+        assert_eq!(cycle.start_currency, "USDT");
+        assert_eq!(
+            legs,
+            [
+                ("binance", TradeSide::Buy, None),
+                ("binance", TradeSide::Transfer, Some("coinbase")),
+                ("coinbase", TradeSide::Sell, None),
+                ("coinbase", TradeSide::Transfer, Some("binance")),
+            ]
+        );
+        // Two trades and two transfers at 0.1% each
+        assert_close(cycle.profit_percentage, (50_500.0 / 50_000.0 * 0.999_f64.powi(4) - 1.0) * 100.0);
+
+        // The spread closes again
+        set_top(&store, "coinbase", "BTCUSDT", 50_000.0, 50_010.0);
+        detector.refresh_market("coinbase", "BTCUSDT");
+        assert!(detector.detect().is_empty());
+    }
+
+    #[test]
+    fn transfer_fees_and_minimum_profit_filter_cycles() {
+        let (detector, store) = cross_exchange();
+        set_top(&store, "coinbase", "BTCUSDT", 50_500.0, 50_510.0);
+
+        let mut expensive_transfers = detector.clone().with_transfer_fee(0.004);
+        expensive_transfers.refresh_all();
+        assert!(expensive_transfers.detect().is_empty());
+
+        let mut demanding = detector.with_min_profit_percentage(1.0);
+        demanding.refresh_all();
+        assert!(demanding.detect().is_empty());
+    }
+
+    #[test]
+    fn markets_without_quotes_close_no_cycle() {
+        let store = OrderBookStore::new();
+        set_top(&store, "binance", "BTCUSDT", 49_990.0, 50_000.0);
+        let mut detector = NegativeCycleDetector::new(&markets(&[("binance", "BTCUSDT"), ("coinbase", "BTCUSDT")]), store);
+        detector.refresh_all();
+        assert!(detector.detect().is_empty());
+    }
+}
@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+use crate::models::{ConversionRate, CurrencyPair};
+
+#[derive(Debug, Clone)]
+struct PairQuote {
+    price: f64,
+    updated_at: DateTime<Utc>,
+}
+
+/// (exchange, pair) => latest price
+type QuoteMap = HashMap<(String, CurrencyPair), PairQuote>;
+
+/// Expresses quotes in USDT, USDC, ... in a common reference currency (USD by
+/// default) from the live stablecoin prices published by the feeds.
+#[derive(Debug, Clone)]
+pub struct QuoteConverter {
+    reference_currency: String,
+    convertible: Vec<String>,
+    /// Age beyond which a quote no longer gives a rate
+    max_age: TimeDelta,
+    quotes: Arc<RwLock<QuoteMap>>,
+}
+
+impl QuoteConverter {
+    pub fn new(reference_currency: &str) -> Self {
+        QuoteConverter {
+            reference_currency: reference_currency.to_uppercase(),
+            convertible: ["USDT", "USDC", "DAI", "BUSD", "FDUSD"].map(String::from).to_vec(),
+            max_age: TimeDelta::seconds(60),
+            quotes: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Override the quote currencies that may be converted into the reference currency
+    pub fn with_convertible(mut self, currencies: &[&str]) -> Self {
+        self.convertible = currencies.iter().map(|currency| currency.to_uppercase()).collect();
+        self
+    }
+
+    /// Ignore the quotes not updated for longer than `max_age`, so that a stalled
+    /// feed does not keep converting at its last price
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = TimeDelta::from_std(max_age).unwrap_or(TimeDelta::MAX);
+        self
+    }
+
+    pub fn reference_currency(&self) -> &str {
+        &self.reference_currency
+    }
+
+    /// Currencies that can be expressed in the reference currency, reference excluded
+    pub fn convertible(&self) -> &[String] {
+        &self.convertible
+    }
+
+    /// Whether a quote currency can be converted at all, regardless of a live rate
+    pub fn is_convertible(&self, currency: &str) -> bool {
+        currency == self.reference_currency || self.convertible.iter().any(|known| known == currency)
+    }
+
+    /// Record the price of a market received at `received_at`; only pairs
+    /// between convertible currencies are kept
+    pub fn on_price(&self, exchange: &str, symbol: &str, price: f64, received_at: DateTime<Utc>) {
+        let Some(pair) = CurrencyPair::parse(symbol) else {
+            return;
+        };
+        if !(self.is_convertible(&pair.base) && self.is_convertible(&pair.quote)) || price <= 0.0 {
+            return;
+        }
+
+        self.quotes.write().unwrap().insert((exchange.to_string(), pair), PairQuote {
+            price,
+            updated_at: received_at,
+        });
+    }
+
+    /// Rate of `currency` in the reference currency, directly or through one intermediate stablecoin
+    pub fn rate(&self, currency: &str) -> Option<ConversionRate> {
+        self.rate_at(currency, Utc::now())
+    }
+
+    /// Same as `rate` with the quotes aged against `at`, e.g. the receive time
+    /// of the tick being evaluated when replaying
+    pub fn rate_at(&self, currency: &str, at: DateTime<Utc>) -> Option<ConversionRate> {
+        let currency = currency.to_uppercase();
+        if currency == self.reference_currency {
+            return Some(ConversionRate {
+                currency: currency.clone(),
+                reference_currency: self.reference_currency.clone(),
+                rate: 1.0,
+                source: "identity".to_string(),
+                updated_at: at,
+            });
+        }
+
+        let quotes = self.quotes.read().unwrap();
+        let direct_rate = |currency: &str, reference: &str| self.direct_rate(&quotes, currency, reference, at);
+        if let Some((rate, source, updated_at)) = direct_rate(&currency, &self.reference_currency) {
+            return Some(self.conversion(&currency, rate, source, updated_at));
+        }
+
+        self.convertible.iter().filter(|via| **via != currency).find_map(|via| {
+            let (first, first_source, first_time) = direct_rate(&currency, via)?;
+            let (second, second_source, second_time) = direct_rate(via, &self.reference_currency)?;
+            Some(self.conversion(
+                &currency,
+                first * second,
+                format!("{} * {}", first_source, second_source),
+                first_time.min(second_time),
+            ))
+        })
+    }
+
+    /// Rates of every convertible currency with a live quote
+    pub fn rates(&self) -> Vec<ConversionRate> {
+        self.convertible.iter().filter_map(|currency| self.rate(currency)).collect()
+    }
+
+    /// Freshest direct or inverse quote of `currency` in `reference` across all
+    /// venues, unless it is older than the max age at `at`
+    fn direct_rate(
+        &self,
+        quotes: &QuoteMap,
+        currency: &str,
+        reference: &str,
+        at: DateTime<Utc>,
+    ) -> Option<(f64, String, DateTime<Utc>)> {
+        let direct = CurrencyPair::new(currency, reference);
+        let inverse = CurrencyPair::new(reference, currency);
+
+        quotes
+            .iter()
+            .filter_map(|((exchange, pair), quote)| {
+                if *pair == direct {
+                    Some((quote.price, format!("{}:{}", exchange, pair.symbol()), quote.updated_at))
+                } else if *pair == inverse {
+                    Some((1.0 / quote.price, format!("1/{}:{}", exchange, pair.symbol()), quote.updated_at))
+                } else {
+                    None
+                }
+            })
+            .max_by_key(|(_, _, updated_at)| *updated_at)
+            .filter(|(_, _, updated_at)| at.signed_duration_since(*updated_at) <= self.max_age)
+    }
+
+    fn conversion(&self, currency: &str, rate: f64, source: String, updated_at: DateTime<Utc>) -> ConversionRate {
+        ConversionRate {
+            currency: currency.to_string(),
+            reference_currency: self.reference_currency.clone(),
+            rate,
+            source,
+            updated_at,
+        }
+    }
+}
+
+impl Default for QuoteConverter {
+    fn default() -> Self {
+        Self::new("USD")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn at(seconds: i64) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap() + TimeDelta::seconds(seconds)
+    }
+
+    #[test]
+    fn converts_the_reference_currency_at_par() {
+        let rate = QuoteConverter::default().rate_at("usd", at(0)).unwrap();
+
+        assert_eq!(rate.rate, 1.0);
+        assert_eq!(rate.source, "identity");
+        assert_eq!(rate.updated_at, at(0));
+    }
+
+    #[test]
+    fn uses_the_freshest_direct_quote() {
+        let converter = QuoteConverter::default();
+        converter.on_price("kraken", "USDTUSD", 0.998, at(0));
+        converter.on_price("bitstamp", "USDTUSD", 0.999, at(5));
+
+        let rate = converter.rate_at("USDT", at(10)).unwrap();
+
+        assert_eq!(rate.rate, 0.999);
+        assert_eq!(rate.source, "bitstamp:USDTUSD");
+        assert_eq!(rate.updated_at, at(5));
+    }
+
+    #[test]
+    fn inverts_a_quote_in_the_other_direction() {
+        let converter = QuoteConverter::default();
+        converter.on_price("binance", "USDUSDT", 1.25, at(0));
+
+        let rate = converter.rate_at("USDT", at(0)).unwrap();
+
+        assert_eq!(rate.rate, 0.8);
+        assert_eq!(rate.source, "1/binance:USDUSDT");
+    }
+
+    #[test]
+    fn converts_through_an_intermediate_stablecoin() {
+        let converter = QuoteConverter::default();
+        converter.on_price("binance", "FDUSDUSDT", 0.5, at(0));
+        converter.on_price("kraken", "USDTUSD", 0.998, at(3));
+
+        let rate = converter.rate_at("FDUSD", at(5)).unwrap();
+
+        assert_eq!(rate.rate, 0.5 * 0.998);
+        assert_eq!(rate.source, "binance:FDUSDUSDT * kraken:USDTUSD");
+        assert_eq!(rate.updated_at, at(0));
+    }
+
+    #[test]
+    fn ignores_quotes_older_than_the_max_age() {
+        let converter = QuoteConverter::default().with_max_age(Duration::from_secs(30));
+        converter.on_price("kraken", "USDTUSD", 0.998, at(0));
+        converter.on_price("binance", "FDUSDUSDT", 0.5, at(20));
+
+        assert!(converter.rate_at("USDT", at(30)).is_some());
+        assert!(converter.rate_at("USDT", at(31)).is_none());
+        assert!(converter.rate_at("FDUSD", at(31)).is_none());
+
+        converter.on_price("bitstamp", "USDTUSD", 0.999, at(31));
+        assert_eq!(converter.rate_at("FDUSD", at(31)).unwrap().rate, 0.5 * 0.999);
+    }
+
+    #[test]
+    fn keeps_only_pairs_between_convertible_currencies() {
+        let converter = QuoteConverter::default();
+        converter.on_price("kraken", "BTCUSD", 50_000.0, at(0));
+        converter.on_price("kraken", "DAIUSD", 0.0, at(0));
+
+        assert!(converter.quotes.read().unwrap().is_empty());
+        assert!(converter.rate_at("DAI", at(0)).is_none());
+        assert!(converter.rate_at("BTC", at(0)).is_none());
+    }
+}
@@ -0,0 +1,175 @@
+use serde::Serialize;
+
+use crate::orderbook::{Level, OrderBook, Side};
+
+/// Result of walking the ask side of the buy book against the bid side of the
+/// sell book while the trade stays profitable after fees.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct SizedOpportunity {
+    /// Executable base quantity
+    pub quantity: f64,
+    /// Volume weighted average price paid on the buy venue
+    pub buy_vwap: f64,
+    /// Volume weighted average price received on the sell venue
+    pub sell_vwap: f64,
+    /// Quote spent on the buy leg, fees excluded
+    pub buy_notional: f64,
+    /// Quote received on the sell leg, fees excluded
+    pub sell_notional: f64,
+    /// Expected profit in quote currency after both taker fees
+    pub expected_profit: f64,
+}
+
+impl SizedOpportunity {
+    /// Net profit relative to the quote spent on the buy leg including its fee
+    pub fn profit_percentage(&self, buy_fee: f64) -> f64 {
+        let cost = self.buy_notional * (1.0 + buy_fee);
+        if cost > 0.0 { self.expected_profit / cost * 100.0 } else { 0.0 }
+    }
+}
+
+/// Size a buy-on-`buy_book` / sell-on-`sell_book` trade.
+///
+/// Levels are consumed best price first on both sides; the walk stops as soon as
+/// the marginal unit is no longer profitable after the taker fees, or when
+/// `max_quantity` is reached. Returns `None` when not even the top of book is profitable.
+pub fn size_opportunity(
+    buy_book: &OrderBook,
+    sell_book: &OrderBook,
+    buy_fee: f64,
+    sell_fee: f64,
+    max_quantity: Option<f64>,
+) -> Option<SizedOpportunity> {
+    let asks: Vec<Level> = buy_book.levels(Side::Ask).collect();
+    let bids: Vec<Level> = sell_book.levels(Side::Bid).collect();
+    size_levels(&asks, &bids, buy_fee, sell_fee, max_quantity)
+}
+
+/// Same as `size_opportunity` on raw level slices, asks ascending and bids descending
+pub fn size_levels(
+    asks: &[Level],
+    bids: &[Level],
+    buy_fee: f64,
+    sell_fee: f64,
+    max_quantity: Option<f64>,
+) -> Option<SizedOpportunity> {
+    let mut quantity = 0.0;
+    let mut buy_notional = 0.0;
+    let mut sell_notional = 0.0;
+
+    let (mut ask_index, mut bid_index) = (0, 0);
+    let mut ask_remaining = asks.first()?.quantity;
+    let mut bid_remaining = bids.first()?.quantity;
+
+    while ask_index < asks.len() && bid_index < bids.len() {
+        let ask = asks[ask_index];
+        let bid = bids[bid_index];
+
+        let unit_profit = bid.price * (1.0 - sell_fee) - ask.price * (1.0 + buy_fee);
+        if unit_profit <= 0.0 {
+            break;
+        }
+
+        let mut step = ask_remaining.min(bid_remaining);
+        if let Some(max_quantity) = max_quantity {
+            step = step.min(max_quantity - quantity);
+        }
+        if step <= 0.0 {
+            break;
+        }
+
+        quantity += step;
+        buy_notional += step * ask.price;
+        sell_notional += step * bid.price;
+        ask_remaining -= step;
+        bid_remaining -= step;
+
+        if ask_remaining <= f64::EPSILON {
+            ask_index += 1;
+            ask_remaining = asks.get(ask_index).map_or(0.0, |level| level.quantity);
+        }
+        if bid_remaining <= f64::EPSILON {
+            bid_index += 1;
+            bid_remaining = bids.get(bid_index).map_or(0.0, |level| level.quantity);
+        }
+    }
+
+    if quantity <= 0.0 {
+        return None;
+    }
+
+    Some(SizedOpportunity {
+        quantity,
+        buy_vwap: buy_notional / quantity,
+        sell_vwap: sell_notional / quantity,
+        buy_notional,
+        sell_notional,
+        expected_profit: sell_notional * (1.0 - sell_fee) - buy_notional * (1.0 + buy_fee),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+    }
+
+    fn asks() -> Vec<Level> {
+        vec![Level::new(100.0, 1.0), Level::new(101.0, 2.0)]
+    }
+
+    fn bids() -> Vec<Level> {
+        vec![Level::new(103.0, 1.5), Level::new(102.0, 1.0)]
+    }
+
+    #[test]
+    fn walks_levels_while_profitable_without_fees() {
+        let sized = size_levels(&asks(), &bids(), 0.0, 0.0, None).unwrap();
+        // 1 @ 100 -> 103, 0.5 @ 101 -> 103, 1 @ 101 -> 102
+        assert_close(sized.quantity, 2.5);
+        assert_close(sized.buy_notional, 251.5);
+        assert_close(sized.sell_notional, 256.5);
+        assert_close(sized.buy_vwap, 100.6);
+        assert_close(sized.sell_vwap, 102.6);
+        assert_close(sized.expected_profit, 5.0);
+    }
+
+    #[test]
+    fn stops_at_the_first_level_unprofitable_after_fees() {
+        let sized = size_levels(&asks(), &bids(), 0.01, 0.01, None).unwrap();
+        // 101 * 1.01 = 102.01 costs more than 103 * 0.99 = 101.97 brings
+        assert_close(sized.quantity, 1.0);
+        assert_close(sized.expected_profit, 103.0 * 0.99 - 100.0 * 1.01);
+        assert_close(sized.profit_percentage(0.01), (103.0 * 0.99 - 101.0) / 101.0 * 100.0);
+    }
+
+    #[test]
+    fn caps_the_quantity() {
+        let sized = size_levels(&asks(), &bids(), 0.0, 0.0, Some(0.4)).unwrap();
+        assert_close(sized.quantity, 0.4);
+        assert_close(sized.expected_profit, 0.4 * 3.0);
+    }
+
+    #[test]
+    fn none_when_the_top_of_book_is_not_profitable() {
+        let asks = [Level::new(100.0, 1.0)];
+        assert_eq!(size_levels(&asks, &[Level::new(99.0, 1.0)], 0.0, 0.0, None), None);
+        // Profitable before fees only
+        assert_eq!(size_levels(&asks, &[Level::new(100.1, 1.0)], 0.001, 0.001, None), None);
+        assert_eq!(size_levels(&[], &[Level::new(101.0, 1.0)], 0.0, 0.0, None), None);
+    }
+
+    #[test]
+    fn sizes_books() {
+        let mut buy_book = OrderBook::new("binance", "BTCUSDT");
+        buy_book.apply_snapshot(&[Level::new(99.0, 1.0)], &asks(), 1);
+        let mut sell_book = OrderBook::new("coinbase", "BTCUSDT");
+        sell_book.apply_snapshot(&bids(), &[Level::new(104.0, 1.0)], 1);
+
+        let sized = size_opportunity(&buy_book, &sell_book, 0.0, 0.0, None).unwrap();
+        assert_close(sized.quantity, 2.5);
+        assert_eq!(size_opportunity(&sell_book, &buy_book, 0.0, 0.0, None), None);
+    }
+}
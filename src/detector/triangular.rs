@@ -0,0 +1,258 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+
+use crate::detector::FeeSchedule;
+use crate::models::{CurrencyPair, MultiLegKind, MultiLegOpportunity, OpportunityLeg, TradeSide};
+use crate::orderbook::OrderBookStore;
+
+/// Conversion offered by one pair of the venue: buying the base with the quote
+/// at the ask, or selling the base for the quote at the bid
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Edge {
+    pair: CurrencyPair,
+    side: TradeSide,
+    from: String,
+    to: String,
+}
+
+impl Edge {
+    fn new(pair: &CurrencyPair, side: TradeSide) -> Self {
+        let (from, to) = if side == TradeSide::Buy {
+            (pair.quote.clone(), pair.base.clone())
+        } else {
+            (pair.base.clone(), pair.quote.clone())
+        };
+        Edge { pair: pair.clone(), side, from, to }
+    }
+
+    fn from(&self) -> &str {
+        &self.from
+    }
+
+    fn to(&self) -> &str {
+        &self.to
+    }
+}
+
+/// Detects loops such as USDT→BTC→ETH→USDT on a single exchange from the
+/// top of book of every subscribed pair, after the venue taker fee on each leg.
+#[derive(Debug, Clone)]
+pub struct TriangularDetector {
+    exchange: String,
+    order_books: OrderBookStore,
+    fees: FeeSchedule,
+    min_profit_percentage: f64,
+    triangles: Vec<[Edge; 3]>,
+}
+
+impl TriangularDetector {
+    /// Build the currency graph of the venue from its subscribed symbols
+    pub fn new(exchange: &str, symbols: &[String], order_books: OrderBookStore) -> Self {
+        let pairs: Vec<CurrencyPair> = symbols.iter().filter_map(|symbol| CurrencyPair::parse(symbol)).collect();
+
+        TriangularDetector {
+            exchange: exchange.to_string(),
+            order_books,
+            fees: FeeSchedule::default(),
+            min_profit_percentage: 0.0,
+            triangles: Self::build_triangles(&pairs),
+        }
+    }
+
+    pub fn with_fees(mut self, fees: FeeSchedule) -> Self {
+        self.fees = fees;
+        self
+    }
+
+    /// Only report loops whose net profit exceeds this percentage
+    pub fn with_min_profit_percentage(mut self, min_profit_percentage: f64) -> Self {
+        self.min_profit_percentage = min_profit_percentage;
+        self
+    }
+
+    pub fn exchange(&self) -> &str {
+        &self.exchange
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.triangles.len()
+    }
+
+    /// Evaluate every triangle of the venue
+    pub fn detect(&self) -> Vec<MultiLegOpportunity> {
+        let timestamp = Utc::now();
+        self.triangles.iter().filter_map(|triangle| self.evaluate(triangle, timestamp)).collect()
+    }
+
+    /// Evaluate the triangles with a leg on the updated symbol, stamping the
+    /// opportunities `timestamp`, the receive time of the update
+    pub fn detect_for_symbol(&self, symbol: &str, timestamp: DateTime<Utc>) -> Vec<MultiLegOpportunity> {
+        self.triangles
+            .iter()
+            .filter(|triangle| triangle.iter().any(|edge| edge.pair.symbol() == symbol))
+            .filter_map(|triangle| self.evaluate(triangle, timestamp))
+            .collect()
+    }
+
+    fn build_triangles(pairs: &[CurrencyPair]) -> Vec<[Edge; 3]> {
+        let edges: Vec<Edge> = pairs
+            .iter()
+            .flat_map(|pair| {
+                [TradeSide::Buy, TradeSide::Sell].map(|side| Edge::new(pair, side))
+            })
+            .collect();
+
+        let mut seen = HashSet::new();
+        let mut triangles = Vec::new();
+        for first in &edges {
+            for second in edges.iter().filter(|edge| edge.from() == first.to() && edge.to() != first.from()) {
+                for third in edges.iter().filter(|edge| edge.from() == second.to() && edge.to() == first.from()) {
+                    let rotation = Self::canonical_rotation([first.clone(), second.clone(), third.clone()]);
+                    if seen.insert(rotation.clone()) {
+                        triangles.push(rotation);
+                    }
+                }
+            }
+        }
+        triangles
+    }
+
+    /// Rotate a loop so it starts from the most quote-like currency (USDT before BTC, ...)
+    fn canonical_rotation(triangle: [Edge; 3]) -> [Edge; 3] {
+        let rank = |currency: &str| {
+            let position = CurrencyPair::QUOTES.iter().position(|quote| *quote == currency);
+            (position.unwrap_or(usize::MAX), currency.to_string())
+        };
+
+        let start = (0..3).min_by_key(|index| rank(triangle[*index].from())).unwrap_or(0);
+        let [a, b, c] = triangle;
+        match start {
+            1 => [b, c, a],
+            2 => [c, a, b],
+            _ => [a, b, c],
+        }
+    }
+
+    fn evaluate(&self, triangle: &[Edge; 3], timestamp: DateTime<Utc>) -> Option<MultiLegOpportunity> {
+        let fee = self.fees.taker_fee(&self.exchange);
+        let mut legs = Vec::with_capacity(3);
+        let mut growth = 1.0;
+        let mut max_start_amount = f64::INFINITY;
+
+        for edge in triangle {
+            let symbol = edge.pair.symbol();
+            let (bid, ask) = self.order_books.top_of_book(&self.exchange, &symbol)?;
+
+            // Quantity the top level can absorb, in units of the leg's input currency
+            let (price, rate, capacity) = if edge.side == TradeSide::Buy {
+                (ask.price, (1.0 - fee) / ask.price, ask.quantity * ask.price)
+            } else {
+                (bid.price, bid.price * (1.0 - fee), bid.quantity)
+            };
+
+            max_start_amount = max_start_amount.min(capacity / growth);
+            growth *= rate;
+
+            legs.push(OpportunityLeg {
+                exchange: self.exchange.clone(),
+                symbol,
+                side: edge.side,
+                from_currency: edge.from().to_string(),
+                to_currency: edge.to().to_string(),
+                price,
+                rate,
+                destination_exchange: None,
+            });
+        }
+
+        let profit_percentage = (growth - 1.0) * 100.0;
+        if profit_percentage <= self.min_profit_percentage {
+            return None;
+        }
+
+        let route: Vec<&str> = legs.iter().map(|leg| leg.from_currency.as_str()).collect();
+        Some(MultiLegOpportunity {
+            id: format!("{}-{}-{}", self.exchange, route.join("-"), timestamp.timestamp_millis()),
+            kind: MultiLegKind::Triangular,
+            start_currency: triangle[0].from().to_string(),
+            legs,
+            profit_percentage,
+            max_start_amount: max_start_amount.is_finite().then_some(max_start_amount),
+            timestamp,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orderbook::Level;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-6, "{} != {}", actual, expected);
+    }
+
+    fn detector(fee: f64) -> TriangularDetector {
+        let store = OrderBookStore::new();
+        let set_top = |symbol: &str, bid: Level, ask: Level| {
+            store.update("binance", symbol, |book| book.apply_snapshot(&[bid], &[ask], 1));
+        };
+        set_top("BTCUSDT", Level::new(49_990.0, 1.0), Level::new(50_000.0, 1.0));
+        set_top("ETHBTC", Level::new(0.0599, 10.0), Level::new(0.06, 10.0));
+        // ETH is worth 3000 USDT through BTC but bid at 3100
+        set_top("ETHUSDT", Level::new(3_100.0, 5.0), Level::new(3_101.0, 5.0));
+
+        let symbols = ["BTCUSDT", "ETHBTC", "ETHUSDT", "XRPUSDT"].map(str::to_string);
+        TriangularDetector::new("binance", &symbols, store).with_fees(FeeSchedule::new(fee))
+    }
+
+    #[test]
+    fn builds_both_directions_of_each_triangle_once() {
+        assert_eq!(detector(0.0).triangle_count(), 2);
+    }
+
+    #[test]
+    fn detects_the_profitable_loop_after_fees() {
+        let opportunities = detector(0.001).detect();
+        assert_eq!(opportunities.len(), 1);
+
+        let opportunity = &opportunities[0];
+        assert_eq!(opportunity.kind, MultiLegKind::Triangular);
+        assert_eq!(opportunity.start_currency, "USDT");
+        let route: Vec<(&str, &str, TradeSide)> = opportunity
+            .legs
+            .iter()
+            .map(|leg| (leg.symbol.as_str(), leg.to_currency.as_str(), leg.side))
+            .collect();
+        assert_eq!(
+            route,
+            [("BTCUSDT", "BTC", TradeSide::Buy), ("ETHBTC", "ETH", TradeSide::Buy), ("ETHUSDT", "USDT", TradeSide::Sell)]
+        );
+
+        let growth = 3_100.0 / (50_000.0 * 0.06) * 0.999_f64.powi(3);
+        assert_close(opportunity.profit_percentage, (growth - 1.0) * 100.0);
+        // The 5 ETH bid is the bottleneck: 5 * 3000 USDT before the fees of the first two legs
+        assert_close(opportunity.max_start_amount.unwrap(), 5.0 * 3_000.0 / 0.999_f64.powi(2));
+    }
+
+    #[test]
+    fn fees_and_minimum_profit_filter_loops() {
+        // 3.3% before fees
+        assert!(detector(0.012).detect().is_empty());
+        assert!(detector(0.0).with_min_profit_percentage(3.5).detect().is_empty());
+        assert_eq!(detector(0.0).with_min_profit_percentage(3.0).detect().len(), 1);
+    }
+
+    #[test]
+    fn only_evaluates_triangles_of_the_updated_symbol() {
+        let detector = detector(0.001);
+        let received_at = DateTime::from_timestamp_millis(1_767_225_600_000).unwrap();
+
+        let opportunities = detector.detect_for_symbol("ETHBTC", received_at);
+        assert_eq!(opportunities.len(), 1);
+        assert_eq!(opportunities[0].timestamp, received_at);
+        assert_eq!(opportunities[0].id, "binance-USDT-BTC-ETH-1767225600000");
+        assert!(detector.detect_for_symbol("XRPUSDT", received_at).is_empty());
+    }
+}
@@ -11,6 +11,8 @@ pub enum AppError {
     InternalServerError(String),
     BadRequest(String),
     NotFound(String),
+    Unauthorized(String),
+    Forbidden(String),
     ConfigError(String),
 }
 
@@ -20,6 +22,8 @@ impl fmt::Display for AppError {
             AppError::InternalServerError(msg) => write!(f, "Internal Server Error: {}", msg),
             AppError::BadRequest(msg) => write!(f, "Bad Request: {}", msg),
             AppError::NotFound(msg) => write!(f, "Not Found: {}", msg),
+            AppError::Unauthorized(msg) => write!(f, "Unauthorized: {}", msg),
+            AppError::Forbidden(msg) => write!(f, "Forbidden: {}", msg),
             AppError::ConfigError(msg) => write!(f, "Configuration Error: {}", msg),
         }
     }
@@ -31,6 +35,8 @@ impl IntoResponse for AppError {
             AppError::InternalServerError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
             AppError::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg),
             AppError::NotFound(msg) => (StatusCode::NOT_FOUND, msg),
+            AppError::Unauthorized(msg) => (StatusCode::UNAUTHORIZED, msg),
+            AppError::Forbidden(msg) => (StatusCode::FORBIDDEN, msg),
             AppError::ConfigError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
         };
 
@@ -49,4 +55,10 @@ impl From<envy::Error> for AppError {
     }
 }
 
+impl From<rusqlite::Error> for AppError {
+    fn from(err: rusqlite::Error) -> Self {
+        AppError::InternalServerError(format!("Database error: {}", err))
+    }
+}
+
 pub type AppResult<T> = Result<T, AppError>;
\ No newline at end of file
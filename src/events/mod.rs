@@ -0,0 +1,40 @@
+use tokio::sync::broadcast;
+
+use crate::models::StreamEvent;
+
+/// Events kept for subscribers that fall behind before they start losing the oldest ones
+const EVENT_BUFFER: usize = 1024;
+
+/// Fan-out of the events pushed to the streaming API, publishable from the
+/// connector threads as well as from async handlers.
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<StreamEvent>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUFFER);
+        EventBus { sender }
+    }
+
+    /// Send an event to every current subscriber, returns how many received it
+    pub fn publish(&self, event: StreamEvent) -> usize {
+        // Without subscribers the event is simply dropped
+        self.sender.send(event).unwrap_or(0)
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<StreamEvent> {
+        self.sender.subscribe()
+    }
+
+    pub fn subscriber_count(&self) -> usize {
+        self.sender.receiver_count()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,206 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+
+use crate::export::{self, ExportFormat};
+use crate::models::{OpportunityEventKind, QuoteResolution};
+use crate::orderbook::OrderBookStore;
+use crate::storage::{
+    opportunities::{OpportunityHistory, OpportunityHistoryQuery},
+    quotes::{QuoteHistory, QuoteHistoryQuery},
+    Database,
+};
+
+pub const USAGE: &str = "Usage: arbitrage_detector export <opportunities|ticks> [options]
+
+Options:
+  --from <rfc3339>        Inclusive start of the time range, required
+  --to <rfc3339>          Exclusive end of the time range, required
+  --format <csv|parquet>  File format, from the output extension or csv by default
+  --output <path>         Destination file, `-` for stdout; named after the range by default
+  --database <path>       SQLite database, DATABASE_PATH by default
+  --symbol <symbol>       Only this symbol
+  --exchange <exchange>   Only this venue, on either leg for opportunities
+  --kind <kind>           Opportunities only: opened, updated or closed
+  --resolution <r>        Ticks only: raw, 1s or 1m; the finest one retained at --from by default";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dataset {
+    Opportunities,
+    Ticks,
+}
+
+/// Arguments of the `export` subcommand
+#[derive(Debug, Clone)]
+pub struct ExportCommand {
+    dataset: Dataset,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    format: Option<ExportFormat>,
+    output: Option<String>,
+    database: Option<String>,
+    symbol: Option<String>,
+    exchange: Option<String>,
+    kind: Option<OpportunityEventKind>,
+    resolution: Option<QuoteResolution>,
+}
+
+impl ExportCommand {
+    /// Parse the arguments following `export`
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let dataset = match args.next().as_deref() {
+            Some("opportunities") => Dataset::Opportunities,
+            Some("ticks") => Dataset::Ticks,
+            Some(other) => return Err(format!("Unknown dataset {}", other)),
+            None => return Err("Missing dataset".to_string()),
+        };
+        let mut command = ExportCommand {
+            dataset,
+            from: None,
+            to: None,
+            format: None,
+            output: None,
+            database: None,
+            symbol: None,
+            exchange: None,
+            kind: None,
+            resolution: None,
+        };
+
+        while let Some(arg) = args.next() {
+            let value = args.next().ok_or_else(|| format!("Missing value for {}", arg))?;
+            match arg.as_str() {
+                "--from" => command.from = Some(parse_time(&arg, &value)?),
+                "--to" => command.to = Some(parse_time(&arg, &value)?),
+                "--format" => {
+                    command.format = Some(ExportFormat::parse(&value).ok_or_else(|| format!("Invalid value '{}' for {}", value, arg))?)
+                }
+                "--output" => command.output = Some(value),
+                "--database" => command.database = Some(value),
+                "--symbol" => command.symbol = Some(value),
+                "--exchange" => command.exchange = Some(value),
+                "--kind" => {
+                    command.kind = Some(match value.as_str() {
+                        "opened" => OpportunityEventKind::Opened,
+                        "updated" => OpportunityEventKind::Updated,
+                        "closed" => OpportunityEventKind::Closed,
+                        _ => return Err(format!("Invalid value '{}' for {}", value, arg)),
+                    })
+                }
+                "--resolution" => {
+                    command.resolution =
+                        Some(QuoteResolution::parse(&value).ok_or_else(|| format!("Invalid value '{}' for {}", value, arg))?)
+                }
+                _ => return Err(format!("Unknown option {}", arg)),
+            }
+        }
+
+        // Same range rule as the export endpoints
+        match (command.from, command.to) {
+            (Some(from), Some(to)) if from < to => Ok(command),
+            (Some(_), Some(_)) => Err("--from must be before --to".to_string()),
+            _ => Err("Exports need both --from and --to".to_string()),
+        }
+    }
+
+    /// Write the export, returning the destination and the number of rows
+    pub fn run(&self) -> Result<(String, usize), String> {
+        let path = self
+            .database
+            .clone()
+            .or_else(|| std::env::var("DATABASE_PATH").ok())
+            .ok_or("No database, pass --database or set DATABASE_PATH")?;
+        if !Path::new(&path).exists() {
+            return Err(format!("Database {} does not exist", path));
+        }
+        let database = Database::open(&path).map_err(|e| format!("Cannot open database {}: {}", path, e))?;
+
+        let format = self.format.unwrap_or_else(|| {
+            self.output
+                .as_deref()
+                .and_then(|output| Path::new(output).extension())
+                .and_then(|extension| ExportFormat::parse(&extension.to_string_lossy()))
+                .unwrap_or_default()
+        });
+        let name = match self.dataset {
+            Dataset::Opportunities => "opportunities",
+            Dataset::Ticks => "ticks",
+        };
+        let output = self
+            .output
+            .clone()
+            .unwrap_or_else(|| export::file_name(name, self.from, self.to, format));
+        let writer: Box<dyn Write + Send> = if output == "-" {
+            Box::new(BufWriter::new(io::stdout()))
+        } else {
+            Box::new(BufWriter::new(File::create(&output).map_err(|e| format!("Cannot create {}: {}", output, e))?))
+        };
+
+        let rows = match self.dataset {
+            Dataset::Opportunities => {
+                let query = OpportunityHistoryQuery {
+                    from: self.from,
+                    to: self.to,
+                    symbol: self.symbol.clone(),
+                    exchange: self.exchange.clone(),
+                    kind: self.kind,
+                    ..OpportunityHistoryQuery::default()
+                };
+                let mut writer = export::ExportWriter::opportunities(format, writer)?;
+                OpportunityHistory::new(database).export(&query, |batch| writer.write(batch))?;
+                writer.finish()?
+            }
+            Dataset::Ticks => {
+                let query = QuoteHistoryQuery {
+                    exchange: self.exchange.clone(),
+                    from: self.from,
+                    to: self.to,
+                    resolution: self.resolution,
+                    ..QuoteHistoryQuery::default()
+                };
+                let mut writer = export::ExportWriter::ticks(format, writer)?;
+                QuoteHistory::new(database, OrderBookStore::new()).export(self.symbol.as_deref(), &query, |batch| writer.write(batch))?;
+                writer.finish()?
+            }
+        };
+        Ok((output, rows))
+    }
+}
+
+fn parse_time(option: &str, value: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|at| at.with_timezone(&Utc))
+        .map_err(|_| format!("Invalid value '{}' for {}, expected an RFC 3339 time", value, option))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<ExportCommand, String> {
+        ExportCommand::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn requires_an_ordered_time_range() {
+        let command = parse(&["ticks", "--from", "2026-01-01T00:00:00Z", "--to", "2026-01-01T01:00:00+01:00", "--symbol", "BTCUSD"]);
+        assert_eq!(command.unwrap_err(), "--from must be before --to");
+
+        assert_eq!(parse(&["ticks", "--from", "2026-01-01T00:00:00Z"]).unwrap_err(), "Exports need both --from and --to");
+        assert_eq!(parse(&["opportunities"]).unwrap_err(), "Exports need both --from and --to");
+
+        let command = parse(&["opportunities", "--to", "2026-01-02T00:00:00Z", "--from", "2026-01-01T00:00:00Z"]).unwrap();
+        assert_eq!(command.dataset, Dataset::Opportunities);
+        assert!(command.from < command.to);
+    }
+
+    #[test]
+    fn rejects_invalid_arguments() {
+        assert_eq!(parse(&["trades"]).unwrap_err(), "Unknown dataset trades");
+        assert_eq!(parse(&["ticks", "--from"]).unwrap_err(), "Missing value for --from");
+        assert!(parse(&["ticks", "--from", "yesterday"]).unwrap_err().contains("expected an RFC 3339 time"));
+        assert_eq!(parse(&["opportunities", "--kind", "stale"]).unwrap_err(), "Invalid value 'stale' for --kind");
+    }
+}
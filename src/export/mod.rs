@@ -0,0 +1,322 @@
+pub mod command;
+
+use std::io::Write;
+use std::sync::Arc;
+
+use arrow_array::{ArrayRef, Float64Array, Int64Array, RecordBatch, StringArray, TimestampMillisecondArray};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use serde::Deserialize;
+
+use crate::models::{OpportunityHistoryEntry, QuoteSnapshot};
+
+/// File format of an export
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Parquet,
+}
+
+impl ExportFormat {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "csv" => Some(ExportFormat::Csv),
+            "parquet" => Some(ExportFormat::Parquet),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Parquet => "application/vnd.apache.parquet",
+        }
+    }
+}
+
+/// File name of an export covering `[from, to)`, e.g. `ticks_20240101T000000Z_20240102T000000Z.parquet`
+pub fn file_name(dataset: &str, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>, format: ExportFormat) -> String {
+    let bound = |at: Option<DateTime<Utc>>, open: &str| {
+        at.map(|at| at.format("%Y%m%dT%H%M%SZ").to_string()).unwrap_or_else(|| open.to_string())
+    };
+    format!("{}_{}_{}.{}", dataset, bound(from, "start"), bound(to, "now"), format.extension())
+}
+
+/// Writes an export a batch of rows at a time, so that no more than one batch
+/// is held in memory. Every batch becomes a row group in Parquet.
+pub struct ExportWriter<T, W: Write + Send> {
+    columns: fn(&[T]) -> Vec<TableColumn>,
+    output: Output<W>,
+    rows: usize,
+}
+
+enum Output<W: Write + Send> {
+    Csv(csv::Writer<W>),
+    Parquet(ArrowWriter<W>, SchemaRef),
+}
+
+impl<W: Write + Send> ExportWriter<OpportunityHistoryEntry, W> {
+    /// One row per opportunity lifecycle event.
+    ///
+    /// Columns, in order: `event_id`, `event_at`, `kind`, `opportunity_id`,
+    /// `symbol`, `buy_exchange`, `sell_exchange`, `buy_symbol`, `sell_symbol`,
+    /// `opened_at`, `updated_at`, `closed_at`, `duration_ms`, `tick_count`,
+    /// `profit_percentage`, `peak_profit_percentage`, `spread`, `peak_spread`,
+    /// `buy_price`, `sell_price`, `quantity`, `buy_vwap`, `sell_vwap`,
+    /// `buy_notional`, `sell_notional`, `expected_profit`.
+    pub fn opportunities(format: ExportFormat, writer: W) -> Result<Self, String> {
+        Self::new(opportunity_columns, format, writer)
+    }
+}
+
+impl<W: Write + Send> ExportWriter<QuoteSnapshot, W> {
+    /// One row per stored quote.
+    ///
+    /// Columns, in order: `at`, `exchange`, `symbol`, `resolution`, `price`,
+    /// `bid`, `bid_quantity`, `ask`, `ask_quantity`, `samples`.
+    pub fn ticks(format: ExportFormat, writer: W) -> Result<Self, String> {
+        Self::new(tick_columns, format, writer)
+    }
+}
+
+impl<T, W: Write + Send> ExportWriter<T, W> {
+    fn new(columns: fn(&[T]) -> Vec<TableColumn>, format: ExportFormat, writer: W) -> Result<Self, String> {
+        let header = columns(&[]);
+        let output = match format {
+            ExportFormat::Csv => {
+                let mut csv = csv::Writer::from_writer(writer);
+                csv.write_record(header.iter().map(|(name, _, _)| *name))
+                    .map_err(|e| format!("Cannot write CSV header: {}", e))?;
+                Output::Csv(csv)
+            }
+            ExportFormat::Parquet => {
+                let schema = Arc::new(Schema::new(
+                    header
+                        .iter()
+                        .map(|(name, nullable, column)| Field::new(*name, column.data_type(), *nullable))
+                        .collect::<Vec<_>>(),
+                ));
+                let properties = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+                let parquet = ArrowWriter::try_new(writer, schema.clone(), Some(properties))
+                    .map_err(|e| format!("Cannot write Parquet: {}", e))?;
+                Output::Parquet(parquet, schema)
+            }
+        };
+        Ok(ExportWriter { columns, output, rows: 0 })
+    }
+
+    pub fn write(&mut self, rows: &[T]) -> Result<(), String> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+        let table = (self.columns)(rows);
+        match &mut self.output {
+            Output::Csv(csv) => {
+                for row in 0..rows.len() {
+                    csv.write_record(table.iter().map(|(_, _, column)| column.cell(row)))
+                        .map_err(|e| format!("Cannot write CSV row: {}", e))?;
+                }
+            }
+            Output::Parquet(parquet, schema) => {
+                let batch = RecordBatch::try_new(schema.clone(), table.iter().map(|(_, _, column)| column.to_array()).collect())
+                    .map_err(|e| format!("Cannot build Parquet rows: {}", e))?;
+                parquet.write(&batch).map_err(|e| format!("Cannot write Parquet rows: {}", e))?;
+                parquet.flush().map_err(|e| format!("Cannot write Parquet row group: {}", e))?;
+            }
+        }
+        self.rows += rows.len();
+        Ok(())
+    }
+
+    /// Complete the file and return the number of rows written
+    pub fn finish(self) -> Result<usize, String> {
+        match self.output {
+            Output::Csv(mut csv) => csv.flush().map_err(|e| format!("Cannot write CSV: {}", e))?,
+            Output::Parquet(parquet, _) => {
+                parquet.close().map_err(|e| format!("Cannot write Parquet footer: {}", e))?;
+            }
+        }
+        Ok(self.rows)
+    }
+}
+
+fn opportunity_columns(entries: &[OpportunityHistoryEntry]) -> Vec<TableColumn> {
+    let latest = |f: fn(&OpportunityHistoryEntry) -> f64| Column::float(entries.iter().map(|entry| Some(f(entry))));
+    vec![
+        ("event_id", false, Column::Int(entries.iter().map(|entry| Some(entry.id)).collect())),
+        ("event_at", false, Column::timestamp(entries.iter().map(|entry| Some(entry.event_at)))),
+        ("kind", false, Column::text(entries.iter().map(|entry| Some(entry.kind.name())))),
+        ("opportunity_id", false, Column::text(entries.iter().map(|entry| Some(entry.opportunity.id.as_str())))),
+        ("symbol", false, Column::text(entries.iter().map(|entry| Some(entry.opportunity.symbol.as_str())))),
+        ("buy_exchange", false, Column::text(entries.iter().map(|entry| Some(entry.opportunity.buy_exchange.as_str())))),
+        ("sell_exchange", false, Column::text(entries.iter().map(|entry| Some(entry.opportunity.sell_exchange.as_str())))),
+        ("buy_symbol", false, Column::text(entries.iter().map(|entry| Some(entry.opportunity.latest.buy_symbol.as_str())))),
+        ("sell_symbol", false, Column::text(entries.iter().map(|entry| Some(entry.opportunity.latest.sell_symbol.as_str())))),
+        ("opened_at", false, Column::timestamp(entries.iter().map(|entry| Some(entry.opportunity.opened_at)))),
+        ("updated_at", false, Column::timestamp(entries.iter().map(|entry| Some(entry.opportunity.updated_at)))),
+        ("closed_at", true, Column::timestamp(entries.iter().map(|entry| entry.opportunity.closed_at))),
+        ("duration_ms", false, Column::Int(entries.iter().map(|entry| Some(entry.opportunity.duration_ms)).collect())),
+        ("tick_count", false, Column::Int(entries.iter().map(|entry| Some(entry.opportunity.tick_count as i64)).collect())),
+        ("profit_percentage", false, latest(|entry| entry.opportunity.current_profit_percentage)),
+        ("peak_profit_percentage", false, latest(|entry| entry.opportunity.peak_profit_percentage)),
+        ("spread", false, latest(|entry| entry.opportunity.current_spread)),
+        ("peak_spread", false, latest(|entry| entry.opportunity.peak_spread)),
+        ("buy_price", false, latest(|entry| entry.opportunity.latest.buy_price)),
+        ("sell_price", false, latest(|entry| entry.opportunity.latest.sell_price)),
+        ("quantity", false, latest(|entry| entry.opportunity.latest.quantity)),
+        ("buy_vwap", false, latest(|entry| entry.opportunity.latest.buy_vwap)),
+        ("sell_vwap", false, latest(|entry| entry.opportunity.latest.sell_vwap)),
+        ("buy_notional", false, latest(|entry| entry.opportunity.latest.buy_notional)),
+        ("sell_notional", false, latest(|entry| entry.opportunity.latest.sell_notional)),
+        ("expected_profit", false, latest(|entry| entry.opportunity.latest.expected_profit)),
+    ]
+}
+
+fn tick_columns(quotes: &[QuoteSnapshot]) -> Vec<TableColumn> {
+    vec![
+        ("at", false, Column::timestamp(quotes.iter().map(|quote| Some(quote.at)))),
+        ("exchange", false, Column::text(quotes.iter().map(|quote| Some(quote.exchange.as_str())))),
+        ("symbol", false, Column::text(quotes.iter().map(|quote| Some(quote.symbol.as_str())))),
+        ("resolution", false, Column::text(quotes.iter().map(|quote| Some(quote.resolution.name())))),
+        ("price", false, Column::float(quotes.iter().map(|quote| Some(quote.price)))),
+        ("bid", true, Column::float(quotes.iter().map(|quote| quote.bid))),
+        ("bid_quantity", true, Column::float(quotes.iter().map(|quote| quote.bid_quantity))),
+        ("ask", true, Column::float(quotes.iter().map(|quote| quote.ask))),
+        ("ask_quantity", true, Column::float(quotes.iter().map(|quote| quote.ask_quantity))),
+        ("samples", false, Column::Int(quotes.iter().map(|quote| Some(quote.samples as i64)).collect())),
+    ]
+}
+
+/// Values of one column. Timestamps are epoch milliseconds, written as
+/// RFC 3339 in CSV and as UTC millisecond timestamps in Parquet.
+enum Column {
+    Timestamp(Vec<Option<i64>>),
+    Int(Vec<Option<i64>>),
+    Float(Vec<Option<f64>>),
+    Text(Vec<Option<String>>),
+}
+
+/// (name, nullable, values)
+type TableColumn = (&'static str, bool, Column);
+
+impl Column {
+    fn timestamp(values: impl Iterator<Item = Option<DateTime<Utc>>>) -> Self {
+        Column::Timestamp(values.map(|at| at.map(|at| at.timestamp_millis())).collect())
+    }
+
+    fn float(values: impl Iterator<Item = Option<f64>>) -> Self {
+        Column::Float(values.collect())
+    }
+
+    fn text<'a>(values: impl Iterator<Item = Option<&'a str>>) -> Self {
+        Column::Text(values.map(|value| value.map(str::to_string)).collect())
+    }
+
+    fn data_type(&self) -> DataType {
+        match self {
+            Column::Timestamp(_) => DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())),
+            Column::Int(_) => DataType::Int64,
+            Column::Float(_) => DataType::Float64,
+            Column::Text(_) => DataType::Utf8,
+        }
+    }
+
+    fn to_array(&self) -> ArrayRef {
+        match self {
+            Column::Timestamp(values) => Arc::new(TimestampMillisecondArray::from(values.clone()).with_timezone("UTC")),
+            Column::Int(values) => Arc::new(Int64Array::from(values.clone())),
+            Column::Float(values) => Arc::new(Float64Array::from(values.clone())),
+            Column::Text(values) => Arc::new(StringArray::from(values.clone())),
+        }
+    }
+
+    /// CSV cell of a row, empty when null
+    fn cell(&self, row: usize) -> String {
+        match self {
+            Column::Timestamp(values) => values[row]
+                .and_then(|millis| Utc.timestamp_millis_opt(millis).single())
+                .map(|at| at.to_rfc3339_opts(SecondsFormat::Millis, true))
+                .unwrap_or_default(),
+            Column::Int(values) => values[row].map(|value| value.to_string()).unwrap_or_default(),
+            Column::Float(values) => values[row].map(|value| value.to_string()).unwrap_or_default(),
+            Column::Text(values) => values[row].clone().unwrap_or_default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    use super::*;
+    use crate::models::QuoteResolution;
+
+    fn quote(price: f64, seconds: i64) -> QuoteSnapshot {
+        QuoteSnapshot {
+            exchange: "coinbase".to_string(),
+            symbol: "BTCUSD".to_string(),
+            resolution: QuoteResolution::Raw,
+            at: Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, seconds as u32).unwrap(),
+            price,
+            bid: Some(price - 0.5),
+            bid_quantity: Some(1.0),
+            ask: None,
+            ask_quantity: None,
+            samples: 1,
+        }
+    }
+
+    #[test]
+    fn writes_csv_batch_by_batch() {
+        let mut file = Vec::new();
+        let mut writer = ExportWriter::ticks(ExportFormat::Csv, &mut file).unwrap();
+        writer.write(&[quote(100.0, 0), quote(101.0, 1)]).unwrap();
+        writer.write(&[]).unwrap();
+        writer.write(&[quote(102.5, 2)]).unwrap();
+        assert_eq!(writer.finish().unwrap(), 3);
+
+        let csv = String::from_utf8(file).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "at,exchange,symbol,resolution,price,bid,bid_quantity,ask,ask_quantity,samples");
+        assert_eq!(lines[3], "2026-01-01T00:00:02.000Z,coinbase,BTCUSD,raw,102.5,102,1,,,1");
+        assert_eq!(lines.len(), 4);
+    }
+
+    #[test]
+    fn writes_a_parquet_row_group_per_batch() {
+        let path = std::env::temp_dir().join(format!("export-test-{}.parquet", std::process::id()));
+        let mut writer = ExportWriter::ticks(ExportFormat::Parquet, File::create(&path).unwrap()).unwrap();
+        writer.write(&[quote(100.0, 0), quote(101.0, 1)]).unwrap();
+        writer.write(&[quote(102.0, 2)]).unwrap();
+        assert_eq!(writer.finish().unwrap(), 3);
+
+        let reader = SerializedFileReader::new(File::open(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let metadata = reader.metadata();
+        assert_eq!(metadata.num_row_groups(), 2);
+        assert_eq!(metadata.file_metadata().num_rows(), 3);
+        assert_eq!(metadata.file_metadata().schema_descr().num_columns(), 10);
+    }
+
+    #[test]
+    fn writes_an_empty_export_with_its_header() {
+        let mut file = Vec::new();
+        let writer = ExportWriter::opportunities(ExportFormat::Csv, &mut file).unwrap();
+        assert_eq!(writer.finish().unwrap(), 0);
+        assert!(String::from_utf8(file).unwrap().starts_with("event_id,event_at,kind,"));
+    }
+}
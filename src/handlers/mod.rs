@@ -1,14 +1,41 @@
 use axum::{
-    extract::State,
-    response::Json,
-    http::StatusCode,
+    body::Body,
+    extract::{MatchedPath, Path, Query, Request, State},
+    middleware::Next,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Json, Response,
+    },
+    http::{header, StatusCode},
 };
+use futures::Stream;
+use serde::Deserialize;
 use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{self, Write};
 use std::sync::Arc;
-use chrono::Utc;
+use std::time::Instant;
+use chrono::{DateTime, Utc};
+use subtle::ConstantTimeEq;
+use tokio::sync::{broadcast::error::RecvError, mpsc};
 
+use crate::alerting::rules::AlertRule;
+use crate::error::{AppError, AppResult};
+use crate::metrics::metrics;
+use crate::log_warn;
+use crate::candles::CandleQuery;
+use crate::export::{self, ExportFormat, ExportWriter};
+use crate::models::{ApiResponse, Candle, Market, OpportunityHistoryEntry, OpportunityStatus, Page, PegStatus, QuoteSnapshot, TrackedOpportunity};
+use crate::orderbook::integrity::IntegrityCounters;
+use crate::socket::tick_filter::TickRejectionCounters;
+use crate::storage::{opportunities::OpportunityHistoryQuery, quotes::QuoteHistoryQuery};
 use crate::AppState;
 
+/// Bytes an export buffers before handing them to the response body
+const EXPORT_CHUNK_BYTES: usize = 64 * 1024;
+/// Chunks an export may write ahead of the client
+const EXPORT_CHUNKS_IN_FLIGHT: usize = 16;
+
 /// Health check endpoint
 pub async fn health() -> Result<Json<Value>, StatusCode> {
     Ok(Json(json!({
@@ -29,4 +56,358 @@ pub async fn app_info(State(_state): State<Arc<AppState>>) -> Json<Value> {
         "version": env!("CARGO_PKG_VERSION"),
         "description": "A web service for detecting arbitrage opportunities"
     }))
-}
\ No newline at end of file
+}
+
+/// Order book integrity counters per exchange
+pub async fn get_orderbook_integrity(
+    State(state): State<Arc<AppState>>,
+) -> Json<ApiResponse<HashMap<String, IntegrityCounters>>> {
+    Json(ApiResponse::success(state.order_books.integrity_counters()))
+}
+
+/// Rejected tick counters per exchange
+pub async fn get_tick_rejections(
+    State(state): State<Arc<AppState>>,
+) -> Json<ApiResponse<HashMap<String, TickRejectionCounters>>> {
+    Json(ApiResponse::success(state.tick_filter.rejection_counters()))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ArbitrageQuery {
+    /// `open` (default) or `closed`
+    pub status: Option<OpportunityStatus>,
+}
+
+/// Open opportunity windows, or the most recently closed ones with `?status=closed`
+pub async fn get_arbitrage_opportunities(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ArbitrageQuery>,
+) -> Json<ApiResponse<Vec<TrackedOpportunity>>> {
+    let opportunities = match query.status.unwrap_or(OpportunityStatus::Open) {
+        OpportunityStatus::Open => state.opportunities.open_opportunities(),
+        OpportunityStatus::Closed => state.opportunities.closed_opportunities(),
+    };
+    Json(ApiResponse::success(opportunities))
+}
+
+/// Persisted opportunity events filtered by time range, symbol and venue, most recent first
+pub async fn get_arbitrage_history(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<OpportunityHistoryQuery>,
+) -> AppResult<Json<ApiResponse<Page<OpportunityHistoryEntry>>>> {
+    let Some(history) = state.opportunity_history.clone() else {
+        return Err(AppError::NotFound("Opportunity history is disabled, set DATABASE_PATH to enable it".to_string()));
+    };
+
+    let page = tokio::task::spawn_blocking(move || history.query(&query))
+        .await
+        .map_err(|e| AppError::InternalServerError(format!("History query failed: {}", e)))??;
+    Ok(Json(ApiResponse::success(page)))
+}
+
+/// Stored ticks and top of book of a symbol on every venue, oldest first
+pub async fn get_market_history(
+    State(state): State<Arc<AppState>>,
+    Path(symbol): Path<String>,
+    Query(query): Query<QuoteHistoryQuery>,
+) -> AppResult<Json<ApiResponse<Page<QuoteSnapshot>>>> {
+    let Some(history) = state.quote_history.clone() else {
+        return Err(AppError::NotFound("Quote history is disabled, set DATABASE_PATH to enable it".to_string()));
+    };
+
+    let page = tokio::task::spawn_blocking(move || history.query(&symbol, &query))
+        .await
+        .map_err(|e| AppError::InternalServerError(format!("History query failed: {}", e)))??;
+    Ok(Json(ApiResponse::success(page)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    /// `csv` (default) or `parquet`
+    pub format: Option<ExportFormat>,
+    /// Symbol of the exported ticks, all of them when unset
+    pub symbol: Option<String>,
+}
+
+/// Opportunity events in `[from, to)` as a CSV or Parquet file, oldest first
+pub async fn export_opportunities(
+    State(state): State<Arc<AppState>>,
+    Query(export): Query<ExportQuery>,
+    Query(query): Query<OpportunityHistoryQuery>,
+) -> AppResult<Response> {
+    let Some(history) = state.opportunity_history.clone() else {
+        return Err(AppError::NotFound("Opportunity history is disabled, set DATABASE_PATH to enable it".to_string()));
+    };
+    export_range(query.from, query.to)?;
+
+    let format = export.format.unwrap_or_default();
+    let name = export::file_name("opportunities", query.from, query.to, format);
+    Ok(stream_export(format, name, move |body| {
+        let mut writer = ExportWriter::opportunities(format, body)?;
+        history.export(&query, |batch| writer.write(batch))?;
+        writer.finish()
+    }))
+}
+
+/// Stored ticks in `[from, to)` as a CSV or Parquet file, oldest first
+pub async fn export_ticks(
+    State(state): State<Arc<AppState>>,
+    Query(export): Query<ExportQuery>,
+    Query(query): Query<QuoteHistoryQuery>,
+) -> AppResult<Response> {
+    let Some(history) = state.quote_history.clone() else {
+        return Err(AppError::NotFound("Quote history is disabled, set DATABASE_PATH to enable it".to_string()));
+    };
+    export_range(query.from, query.to)?;
+
+    let format = export.format.unwrap_or_default();
+    let name = export::file_name("ticks", query.from, query.to, format);
+    Ok(stream_export(format, name, move |body| {
+        let mut writer = ExportWriter::ticks(format, body)?;
+        history.export(export.symbol.as_deref(), &query, |batch| writer.write(batch))?;
+        writer.finish()
+    }))
+}
+
+/// Exports cover an explicit time range, so a forgotten bound cannot dump the whole database
+fn export_range(from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> AppResult<()> {
+    match (from, to) {
+        (Some(from), Some(to)) if from < to => Ok(()),
+        (Some(_), Some(_)) => Err(AppError::BadRequest("`from` must be before `to`".to_string())),
+        _ => Err(AppError::BadRequest("Exports need both `from` and `to`".to_string())),
+    }
+}
+
+/// Respond with the file `write` produces on a blocking thread while it is
+/// being written. A failure past the first chunk aborts the response.
+fn stream_export<F>(format: ExportFormat, name: String, write: F) -> Response
+where
+    F: FnOnce(&mut BodyWriter) -> Result<usize, String> + Send + 'static,
+{
+    let disposition = format!("attachment; filename=\"{}\"", name);
+    let (sender, receiver) = mpsc::channel(EXPORT_CHUNKS_IN_FLIGHT);
+    tokio::task::spawn_blocking(move || {
+        let mut body = BodyWriter { sender: sender.clone(), buffer: Vec::new() };
+        let written = write(&mut body).and_then(|rows| body.flush().map(|_| rows).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            log_warn!(name = %name, error = %e, "Export aborted");
+            let _ = sender.blocking_send(Err(io::Error::other(e)));
+        }
+    });
+
+    let chunks = futures::stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|chunk| (chunk, receiver))
+    });
+    (
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        Body::from_stream(chunks),
+    )
+        .into_response()
+}
+
+/// Hands what an export writes to the response body in chunks, waiting while
+/// the client is slower than the database
+struct BodyWriter {
+    sender: mpsc::Sender<io::Result<Vec<u8>>>,
+    buffer: Vec<u8>,
+}
+
+impl Write for BodyWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(data);
+        if self.buffer.len() >= EXPORT_CHUNK_BYTES {
+            self.flush()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::take(&mut self.buffer);
+        self.sender
+            .blocking_send(Ok(chunk))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "client disconnected"))
+    }
+}
+
+/// OHLCV candles of a symbol for one interval, oldest first
+pub async fn get_candles(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<CandleQuery>,
+) -> Json<ApiResponse<Vec<Candle>>> {
+    Json(ApiResponse::success(state.candles.candles(&query)))
+}
+
+/// Last price and 24 hour volume of every market
+pub async fn get_markets(State(state): State<Arc<AppState>>) -> Json<ApiResponse<Vec<Market>>> {
+    Json(ApiResponse::success(state.candles.markets()))
+}
+
+/// Current peg of the monitored stablecoins on every venue
+pub async fn get_stablecoins(State(state): State<Arc<AppState>>) -> Json<ApiResponse<Vec<PegStatus>>> {
+    Json(ApiResponse::success(state.depeg_monitor.pegs()))
+}
+
+/// Counters and histograms in the Prometheus text format
+pub async fn get_metrics() -> AppResult<Response> {
+    let body = metrics().render().map_err(AppError::InternalServerError)?;
+    Ok(([(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)], body).into_response())
+}
+
+/// Observe the duration of every request, labelled by its route rather than its path
+pub async fn record_request_duration(request: Request, next: Next) -> Response {
+    let started = Instant::now();
+    let method = request.method().to_string();
+    let path = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or_else(|| "unmatched".to_string(), |path| path.as_str().to_string());
+    let response = next.run(request).await;
+    metrics()
+        .http_request_duration
+        .with_label_values(&[&method, &path, response.status().as_str()])
+        .observe(started.elapsed().as_secs_f64());
+    response
+}
+
+/// Reject the admin requests without the `ADMIN_TOKEN` bearer token, and all
+/// of them when no token is configured
+pub async fn require_admin_token(State(state): State<Arc<AppState>>, request: Request, next: Next) -> AppResult<Response> {
+    let Some(token) = &state.config.admin_token else {
+        return Err(AppError::Forbidden("The admin API is disabled, set ADMIN_TOKEN to enable it".to_string()));
+    };
+    let bearer = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    // Compared in constant time, so response times do not leak how much of a guess matched
+    let valid = bearer.is_some_and(|bearer| bool::from(bearer.as_bytes().ct_eq(token.expose().as_bytes())));
+    if !valid {
+        return Err(AppError::Unauthorized("Missing or invalid admin token".to_string()));
+    }
+    Ok(next.run(request).await)
+}
+
+/// Alert rules evaluated by the alerter
+pub async fn list_alert_rules(State(state): State<Arc<AppState>>) -> Json<ApiResponse<Vec<AlertRule>>> {
+    Json(ApiResponse::success(state.alert_rules.list()))
+}
+
+pub async fn get_alert_rule(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> AppResult<Json<ApiResponse<AlertRule>>> {
+    let rule = state
+        .alert_rules
+        .get(&id)
+        .ok_or_else(|| AppError::NotFound(format!("No alert rule {}", id)))?;
+    Ok(Json(ApiResponse::success(rule)))
+}
+
+/// Add a rule with a new id
+pub async fn create_alert_rule(
+    State(state): State<Arc<AppState>>,
+    Json(rule): Json<AlertRule>,
+) -> AppResult<(StatusCode, Json<ApiResponse<AlertRule>>)> {
+    state.alert_rules.insert(rule.clone()).map_err(AppError::BadRequest)?;
+    Ok((StatusCode::CREATED, Json(ApiResponse::success(rule))))
+}
+
+/// Replace the rule with the id of the path, or add it
+pub async fn put_alert_rule(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Json(mut rule): Json<AlertRule>,
+) -> AppResult<(StatusCode, Json<ApiResponse<AlertRule>>)> {
+    rule.id = id;
+    let replaced = state.alert_rules.upsert(rule.clone()).map_err(AppError::BadRequest)?;
+    let status = if replaced { StatusCode::OK } else { StatusCode::CREATED };
+    Ok((status, Json(ApiResponse::success(rule))))
+}
+
+pub async fn delete_alert_rule(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> AppResult<Json<ApiResponse<AlertRule>>> {
+    let rule = state
+        .alert_rules
+        .remove(&id)
+        .ok_or_else(|| AppError::NotFound(format!("No alert rule {}", id)))?;
+    Ok(Json(ApiResponse::success(rule)))
+}
+
+/// Server-sent events stream of alerts, one JSON `{type, data}` payload per event
+pub async fn stream_events(
+    State(state): State<Arc<AppState>>,
+) -> Sse<impl Stream<Item = Result<Event, axum::Error>>> {
+    let receiver = state.events.subscribe();
+    let stream = futures::stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => {
+                    let sse_event = Event::default().event(event.name()).json_data(&event);
+                    return Some((sse_event, receiver));
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    log_warn!(skipped, "Stream subscriber lagging, events dropped");
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    use crate::config::{Config, Secret};
+    use crate::routes::create_router;
+
+    /// Serve the API with the given admin token, returns its address
+    async fn serve(admin_token: Option<&str>) -> std::net::SocketAddr {
+        let config = Config { admin_token: admin_token.map(Secret::new), ..Config::default() };
+        let app = create_router(std::sync::Arc::new(crate::AppState::new(config)));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        address
+    }
+
+    /// Status code of a GET of the admin alert rules
+    async fn list_rules(address: std::net::SocketAddr, authorization: Option<&str>) -> u16 {
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let authorization = authorization.map(|value| format!("Authorization: {}\r\n", value)).unwrap_or_default();
+        let request = format!("GET /api/v1/admin/alerts HTTP/1.1\r\nHost: localhost\r\n{}Connection: close\r\n\r\n", authorization);
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response.split(' ').nth(1).unwrap().parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn admin_api_is_closed_without_a_configured_token() {
+        let address = serve(None).await;
+        assert_eq!(list_rules(address, None).await, 403);
+        assert_eq!(list_rules(address, Some("Bearer ")).await, 403);
+    }
+
+    #[tokio::test]
+    async fn admin_api_requires_the_exact_bearer_token() {
+        let address = serve(Some("s3cret")).await;
+        assert_eq!(list_rules(address, None).await, 401);
+        assert_eq!(list_rules(address, Some("Bearer s3cre")).await, 401);
+        assert_eq!(list_rules(address, Some("Bearer s3cret2")).await, 401);
+        assert_eq!(list_rules(address, Some("Basic s3cret")).await, 401);
+        assert_eq!(list_rules(address, Some("Bearer s3cret")).await, 200);
+    }
+}
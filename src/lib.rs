@@ -1,20 +1,51 @@
+pub mod alerting;
+pub mod candles;
 pub mod config;
+pub mod detector;
 pub mod error;
+pub mod events;
+pub mod export;
 pub mod handlers;
+pub mod metrics;
 pub mod routes;
 pub mod models;
 pub mod socket;
 pub mod logger;
+pub mod orderbook;
+pub mod recorder;
+pub mod storage;
 
+use alerting::rules::AlertRules;
+use candles::CandleAggregator;
 use config::Config;
+use detector::{depeg::DepegMonitor, lifecycle::OpportunityTracker, quote_conversion::QuoteConverter};
+use events::EventBus;
 use logger::Logger;
+use orderbook::OrderBookStore;
+use socket::tick_filter::TickFilter;
+use storage::{opportunities::OpportunityHistory, quotes::QuoteHistory};
 use std::sync::Arc;
+use std::time::Duration;
 
 /// Shared application state
 #[derive(Debug, Clone)]
 pub struct AppState {
     pub config: Config,
     pub logger: Logger,
+    pub order_books: OrderBookStore,
+    pub quote_converter: QuoteConverter,
+    pub depeg_monitor: DepegMonitor,
+    pub opportunities: OpportunityTracker,
+    pub tick_filter: TickFilter,
+    pub candles: CandleAggregator,
+    /// Events pushed to the streaming API
+    pub events: EventBus,
+    /// Persisted opportunity events, `None` when no database is configured
+    pub opportunity_history: Option<OpportunityHistory>,
+    /// Persisted ticks and top of book, `None` when no database is configured
+    pub quote_history: Option<QuoteHistory>,
+    /// Rules of the alerter, edited through the admin API
+    pub alert_rules: AlertRules,
     // Add other shared state like database connections, HTTP clients, etc.
     // pub db: Arc<Database>,
     // pub http_client: reqwest::Client,
@@ -22,17 +53,65 @@ pub struct AppState {
 
 impl AppState {
     pub fn new(config: Config) -> Self {
+        let events = EventBus::new();
+        let depeg_monitor = DepegMonitor::new(config.depeg_threshold_percentage).with_events(events.clone());
+        let opportunities = OpportunityTracker::new()
+            .with_thresholds(config.opportunity_enter_threshold, config.opportunity_exit_threshold)
+            .with_min_ticks(config.opportunity_min_ticks)
+            .with_min_persistence(Duration::from_millis(config.opportunity_min_persistence_ms))
+            .with_events(events.clone());
+        let tick_filter = TickFilter::new()
+            .with_window(Duration::from_secs(config.tick_filter_window_secs))
+            .with_max_deviation_percentage(Some(config.tick_filter_max_deviation_percentage).filter(|max| *max > 0.0))
+            .with_max_sigmas(config.tick_filter_max_sigmas)
+            .with_reanchor_ticks(config.tick_filter_reanchor_ticks);
+        let quote_converter =
+            QuoteConverter::default().with_max_age(Duration::from_secs(config.quote_conversion_max_age_secs));
+
         Self {
             config,
             logger: Logger::new(),
+            order_books: OrderBookStore::new(),
+            quote_converter,
+            depeg_monitor,
+            opportunities,
+            tick_filter,
+            candles: CandleAggregator::new(),
+            events,
+            opportunity_history: None,
+            quote_history: None,
+            alert_rules: AlertRules::default(),
             // Initialize other state here
         }
     }
+
+    /// Serve the opportunity history from the given store
+    pub fn with_opportunity_history(mut self, history: OpportunityHistory) -> Self {
+        self.opportunity_history = Some(history);
+        self
+    }
+
+    /// Serve the quote history from the given store
+    pub fn with_quote_history(mut self, history: QuoteHistory) -> Self {
+        self.quote_history = Some(history);
+        self
+    }
+
+    /// Expose the rules evaluated by the alerter to the admin API
+    pub fn with_alert_rules(mut self, rules: AlertRules) -> Self {
+        self.alert_rules = rules;
+        self
+    }
 }
 
 /// Create the application with all dependencies
 pub async fn create_app(config: Config) -> Result<axum::Router, error::AppError> {
     let state = Arc::new(AppState::new(config));
+    create_app_with_state(state).await
+}
+
+/// Create the application around state shared with the background workers
+pub async fn create_app_with_state(state: Arc<AppState>) -> Result<axum::Router, error::AppError> {
     let app = routes::create_router(state);
     Ok(app)
 }
\ No newline at end of file
@@ -1,64 +1,208 @@
+use std::sync::{Once, OnceLock};
+
 use tracing::{info, warn, error, debug, trace};
-use tracing_subscriber::EnvFilter;
-use std::sync::Once;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer, Registry};
 
 static INIT: Once = Once::new();
 
+/// Keeps the background writer of the log file flushing until the process exits
+static FILE_GUARD: OnceLock<WorkerGuard> = OnceLock::new();
+
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+/// Layout of every log line
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human readable lines, with the span fields before the message
+    #[default]
+    Text,
+    /// One JSON object per line with the event fields, the current span and the span list
+    Json,
+}
+
+impl LogFormat {
+    pub fn parse(format: &str) -> Result<Self, String> {
+        match format.to_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("Unknown log format {}, expected text or json", format)),
+        }
+    }
+}
+
+/// How often the log file is rolled over
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogRotation {
+    Minutely,
+    Hourly,
+    #[default]
+    Daily,
+    Never,
+}
+
+impl LogRotation {
+    pub fn parse(rotation: &str) -> Result<Self, String> {
+        match rotation.to_lowercase().as_str() {
+            "minutely" => Ok(LogRotation::Minutely),
+            "hourly" => Ok(LogRotation::Hourly),
+            "daily" => Ok(LogRotation::Daily),
+            "never" => Ok(LogRotation::Never),
+            _ => Err(format!("Unknown log rotation {}, expected minutely, hourly, daily or never", rotation)),
+        }
+    }
+
+    fn rotation(&self) -> Rotation {
+        match self {
+            LogRotation::Minutely => Rotation::MINUTELY,
+            LogRotation::Hourly => Rotation::HOURLY,
+            LogRotation::Daily => Rotation::DAILY,
+            LogRotation::Never => Rotation::NEVER,
+        }
+    }
+}
+
+/// Rolling log file, `<prefix>.<date>.log` in `directory`
+#[derive(Debug, Clone)]
+pub struct LogFile {
+    pub directory: String,
+    pub prefix: String,
+    pub rotation: LogRotation,
+    /// Rotated files kept, all of them when 0
+    pub max_files: usize,
+}
+
+/// Level, format and destinations of the logs
+#[derive(Debug, Clone, Default)]
+pub struct LogOptions {
+    level: Option<String>,
+    format: LogFormat,
+    file: Option<LogFile>,
+}
+
+impl LogOptions {
+    /// Plain text logs on stdout at `level`, `info` when unset; `RUST_LOG` takes precedence
+    pub fn new(level: Option<&str>) -> Self {
+        LogOptions {
+            level: level.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_format(mut self, format: LogFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Also write the logs, without colors, to a rolling file
+    pub fn with_file(mut self, file: LogFile) -> Self {
+        self.file = Some(file);
+        self
+    }
+}
+
 /// Initialize the global logger - call this once at application startup
 pub fn init_logger(log_level: Option<&str>) {
-    INIT.call_once(|| {
-        let env_filter = EnvFilter::try_from_default_env()
-            .or_else(|_| {
-                let level = log_level.unwrap_or("info");
-                EnvFilter::try_new(level)
-            })
-            .unwrap_or_else(|_| EnvFilter::new("info"));
-
-        tracing_subscriber::fmt()
-            .with_env_filter(env_filter)
-            // .with_target(true)
-            // .with_thread_ids(false)
-            // .with_file(true)
-            // .with_line_number(true)
-            .init();
-        
-        info!("Logger initialized successfully");
-    });
+    // Stdout only, which cannot fail
+    let _ = init(LogOptions::new(log_level));
+}
+
+/// Initialize the global logger with the given options, only the first call has an effect
+pub fn init(options: LogOptions) -> Result<(), String> {
+    let mut result = Ok(());
+    INIT.call_once(|| result = install(options));
+    result
+}
+
+fn install(options: LogOptions) -> Result<(), String> {
+    let env_filter = EnvFilter::try_from_default_env()
+        .or_else(|_| {
+            let level = options.level.as_deref().unwrap_or("info");
+            EnvFilter::try_new(level)
+        })
+        .unwrap_or_else(|_| EnvFilter::new("info"));
+
+    let mut layers = vec![layer(options.format, std::io::stdout, true)];
+    if let Some(file) = &options.file {
+        // The appender looks for old files to prune before creating the directory
+        std::fs::create_dir_all(&file.directory).map_err(|e| format!("Cannot log to {}: {}", file.directory, e))?;
+        let mut appender = RollingFileAppender::builder()
+            .rotation(file.rotation.rotation())
+            .filename_prefix(&file.prefix)
+            .filename_suffix("log");
+        if file.max_files > 0 {
+            appender = appender.max_log_files(file.max_files);
+        }
+        let appender = appender
+            .build(&file.directory)
+            .map_err(|e| format!("Cannot log to {}: {}", file.directory, e))?;
+        let (writer, guard) = tracing_appender::non_blocking(appender);
+        let _ = FILE_GUARD.set(guard);
+        layers.push(layer(options.format, writer, false));
+    }
+
+    tracing_subscriber::registry()
+        .with(layers)
+        .with(env_filter)
+        .try_init()
+        .map_err(|e| format!("Cannot install the logger: {}", e))?;
+
+    info!("Logger initialized successfully");
+    Ok(())
+}
+
+fn layer<W>(format: LogFormat, writer: W, ansi: bool) -> BoxedLayer
+where
+    W: for<'writer> MakeWriter<'writer> + Send + Sync + 'static,
+{
+    match format {
+        LogFormat::Text => tracing_subscriber::fmt::layer().with_writer(writer).with_ansi(ansi).boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .with_writer(writer)
+            .boxed(),
+    }
 }
 
 /// Macro for easy logging throughout the application
 #[macro_export]
 macro_rules! log_info {
     ($($arg:tt)*) => {
-        tracing::info!($($arg)*);
+        tracing::info!($($arg)*)
     };
 }
 
 #[macro_export]
 macro_rules! log_error {
     ($($arg:tt)*) => {
-        tracing::error!($($arg)*);
+        tracing::error!($($arg)*)
     };
 }
 
 #[macro_export]
 macro_rules! log_warn {
     ($($arg:tt)*) => {
-        tracing::warn!($($arg)*);
+        tracing::warn!($($arg)*)
     };
 }
 
 #[macro_export]
 macro_rules! log_debug {
     ($($arg:tt)*) => {
-        tracing::debug!($($arg)*);
+        tracing::debug!($($arg)*)
     };
 }
 
 #[macro_export]
 macro_rules! log_trace {
     ($($arg:tt)*) => {
-        tracing::trace!($($arg)*);
+        tracing::trace!($($arg)*)
     };
 }
 
@@ -1,59 +1,238 @@
-use std::thread;
+use std::{collections::HashSet, sync::Arc, thread, time::Duration};
 
 use dotenvy::dotenv;
-use arbitrage_detector::{config::Config, create_app, error::AppError, log_info, logger, socket::{socket_consumer::{self, SocketConsumer}, socket_container::coinbase_container::CoinBaseContainer}};
+use arbitrage_detector::{
+    alerting::{
+        email::{DigestInterval, EmailDigest},
+        rules::{AlertRule, AlertRules},
+        webhook::WebhookConfig,
+        Alerter, NotifierConfig,
+    },
+    config::Config,
+    create_app_with_state,
+    detector::{negative_cycle::NegativeCycleDetector, triangular::TriangularDetector, ArbitrageDetector},
+    error::AppError,
+    export::command::{ExportCommand, USAGE as EXPORT_USAGE},
+    log_error,
+    log_info,
+    logger::{self, LogFile, LogFormat, LogOptions, LogRotation},
+    recorder::FrameRecorder,
+    models::QuoteResolution,
+    storage::{opportunities::OpportunityHistory, quotes::QuoteHistory, Database},
+    AppState,
+    socket::{
+        socket_consumer::SocketConsumer,
+        socket_container::{
+            binance_container::BinanceContainer,
+            bitstamp_container::BitstampContainer,
+            coinbase_container::CoinBaseContainer,
+            gemini_container::GeminiContainer,
+            replay_container::{ReplaySpeed, Replayer},
+            socket_container::ISocketContainer,
+        },
+    },
+};
 
 #[tokio::main]
 async fn main() -> Result<(), AppError> {
     // Initialize environment variables
     dotenv().ok();
-    
+
+    // `arbitrage_detector export ...` dumps the stored history instead of serving
+    let mut args = std::env::args().skip(1).peekable();
+    if args.peek().map(String::as_str) == Some("export") {
+        logger::init_logger(Some("warn"));
+        let command = ExportCommand::parse(args.skip(1)).unwrap_or_else(|e| {
+            eprintln!("{}\n\n{}", e, EXPORT_USAGE);
+            std::process::exit(2);
+        });
+        match command.run() {
+            Ok((output, rows)) => eprintln!("Exported {} rows to {}", rows, output),
+            Err(e) => {
+                eprintln!("Export failed: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
+
     // Load configuration first to get log level
     let config = Config::from_env()
         .map_err(|e| AppError::ConfigError(format!("Failed to load config: {}", e)))?;
-    
+
     // Initialize singleton logger
-    logger::init_logger(config.log_level.as_deref());
-    
-    log_info!("Starting server with config: {:?}", config);
+    let mut log_options = LogOptions::new(config.log_level.as_deref())
+        .with_format(LogFormat::parse(&config.log_format).map_err(AppError::ConfigError)?);
+    if let Some(directory) = &config.log_directory {
+        log_options = log_options.with_file(LogFile {
+            directory: directory.clone(),
+            prefix: config.log_file_prefix.clone(),
+            rotation: LogRotation::parse(&config.log_rotation).map_err(AppError::ConfigError)?,
+            max_files: config.log_max_files,
+        });
+    }
+    logger::init(log_options).map_err(AppError::ConfigError)?;
+
+    log_info!(config = ?config, "Starting server");
 
     // Create the application
-    let app = create_app(config.clone()).await?;
+    let mut state = AppState::new(config.clone());
+    if let Some(path) = &config.database_path {
+        let database = Database::open(path)
+            .map_err(|e| AppError::ConfigError(format!("Cannot open database {}: {}", path, e)))?;
+        let history = OpportunityHistory::new(database.clone());
+        history.record_events(&state.events);
+        let quotes = QuoteHistory::new(database, state.order_books.clone())
+            .with_retention(QuoteResolution::Raw, Duration::from_secs(config.quote_history_raw_retention_secs))
+            .with_retention(QuoteResolution::OneSecond, Duration::from_secs(config.quote_history_second_retention_secs))
+            .with_retention(QuoteResolution::OneMinute, Duration::from_secs(config.quote_history_minute_retention_secs));
+        quotes.start();
+        state = state.with_opportunity_history(history).with_quote_history(quotes);
+    }
+    let mut sinks: Vec<NotifierConfig> = Vec::new();
+    if let Some(webhooks) = &config.alert_webhooks {
+        let webhooks: Vec<WebhookConfig> = serde_json::from_str(webhooks.expose())
+            .map_err(|e| AppError::ConfigError(format!("Invalid ALERT_WEBHOOKS: {}", e)))?;
+        sinks.extend(webhooks.into_iter().map(NotifierConfig::Webhook));
+    }
+    if let Some(notifiers) = &config.alert_notifiers {
+        let notifiers: Vec<NotifierConfig> = serde_json::from_str(notifiers.expose())
+            .map_err(|e| AppError::ConfigError(format!("Invalid ALERT_NOTIFIERS: {}", e)))?;
+        sinks.extend(notifiers);
+    }
+    let mut alerter = Alerter::new()
+        .with_retries(config.alert_max_retries, Duration::from_millis(config.alert_retry_backoff_ms));
+    let mut forwarding = Vec::new();
+    for (index, sink) in sinks.iter().enumerate() {
+        let name = sink.sink_name(index + 1);
+        if alerter.sink_names().contains(&name) {
+            return Err(AppError::ConfigError(format!("Duplicate alert sink name {}", name)));
+        }
+        forwarding.push(AlertRule::forward(&name, sink.filter().clone()));
+        alerter = alerter.with_sink(name, sink.notifier(Duration::from_millis(config.alert_timeout_ms)));
+    }
+    let rules = match &config.alert_rules {
+        Some(rules) => serde_json::from_str(rules).map_err(|e| AppError::ConfigError(format!("Invalid ALERT_RULES: {}", e)))?,
+        None => forwarding,
+    };
+    let rules = AlertRules::new(alerter.sink_names())
+        .with_rules(rules)
+        .map_err(|e| AppError::ConfigError(format!("Invalid ALERT_RULES: {}", e)))?;
+    if !alerter.is_empty() {
+        alerter.start(rules.clone(), &state.events, state.candles.clone(), state.depeg_monitor.clone());
+    }
+    state = state.with_alert_rules(rules);
+    if let Some(smtp_url) = &config.digest_smtp_url {
+        let recipients: Vec<String> = config.digest_to.iter().flat_map(|to| to.split(',')).map(str::to_string).collect();
+        let interval = DigestInterval::parse(&config.digest_interval).map_err(AppError::ConfigError)?;
+        EmailDigest::new(smtp_url.expose(), &config.digest_from, &recipients)
+            .map_err(|e| AppError::ConfigError(format!("Invalid email digest settings: {}", e)))?
+            .with_interval(interval)
+            .with_top_opportunities(config.digest_top_opportunities)
+            .with_retries(config.alert_max_retries, Duration::from_millis(config.alert_retry_backoff_ms))
+            .start(&state.events, state.candles.clone(), state.depeg_monitor.clone());
+    }
+    let state = Arc::new(state);
+    let app = create_app_with_state(state.clone()).await?;
+
+    // Start the exchange connectors in the background
+    let order_books = state.order_books.clone();
+    let binance_symbols = vec!["btcusdt", "ethusdt", "ethbtc", "btcusdc", "usdcusdt"];
+    let binance_pairs: Vec<String> = binance_symbols.iter().map(|symbol| symbol.to_uppercase()).collect();
+    let mut socket_consumer = SocketConsumer::new()
+        .with_detector(
+            ArbitrageDetector::new(order_books.clone())
+                .with_quote_converter(state.quote_converter.clone())
+                .with_tick_filter(state.tick_filter.clone())
+                .with_min_profit_percentage(state.opportunities.exit_threshold()),
+        )
+        .with_depeg_monitor(state.depeg_monitor.clone())
+        .with_opportunity_tracker(state.opportunities.clone())
+        .with_tick_filter(state.tick_filter.clone())
+        .with_candle_aggregator(state.candles.clone())
+        .with_events(state.events.clone())
+        .with_triangular_detector(TriangularDetector::new(BinanceContainer::EXCHANGE, &binance_pairs, order_books.clone()));
+    if let Some(quotes) = &state.quote_history {
+        socket_consumer = socket_consumer.with_quote_history(quotes.clone());
+    }
+    let subscriptions = vec![
+        (BinanceContainer::EXCHANGE, binance_symbols),
+        (CoinBaseContainer::EXCHANGE, vec!["BTC-USD", "ETH-USD", "USDT-USD", "DAI-USD"]),
+        (GeminiContainer::EXCHANGE, vec!["BTCUSD", "ETHUSD"]),
+        (BitstampContainer::EXCHANGE, vec!["btcusd", "ethusd", "usdtusd", "usdcusd"]),
+    ];
+    let markets: Vec<(String, String)> = subscriptions
+        .iter()
+        .flat_map(|(exchange, symbols)| symbols.iter().map(|symbol| (exchange.to_string(), symbol.replace("-", "").to_uppercase())))
+        .collect();
+
+    let mut socket_consumer = socket_consumer.with_cycle_detector(NegativeCycleDetector::new(&markets, order_books.clone()));
+    match &config.replay_path {
+        Some(path) => {
+            let speed = ReplaySpeed::parse(&config.replay_speed).map_err(AppError::ConfigError)?;
+            let mut replayer = Replayer::new(path)
+                .map_err(AppError::ConfigError)?
+                .with_speed(speed)
+                .with_order_books(order_books.clone());
+            log_info!(files = replayer.files().len(), path = %path, speed = ?speed, "Replaying recordings");
+            let symbols: HashSet<String> = markets.iter().map(|(_, symbol)| symbol.clone()).collect();
+            // Each price update is processed before the next frame is parsed, so the
+            // books never run ahead of the detectors and every run gives the same result
+            thread::spawn(move || {
+                let replayed = replayer.run(|message| {
+                    if symbols.contains(&message.symbol) {
+                        socket_consumer.process_message(message);
+                    }
+                    Ok(())
+                });
+                match replayed {
+                    Ok(stats) => log_info!(frames = stats.frames, messages = stats.messages, "Replay finished"),
+                    Err(e) => log_error!(error = %e, "Replay failed"),
+                }
+            });
+        }
+        None => {
+            let recorder = match &config.recorder_directory {
+                Some(directory) => Some(
+                    FrameRecorder::new(directory)
+                        .map_err(|e| AppError::ConfigError(format!("Cannot record frames to {}: {}", directory, e)))?
+                        .with_max_file_bytes(config.recorder_max_file_mb * 1024 * 1024)
+                        .with_rotation_interval(Duration::from_secs(config.recorder_rotation_secs)),
+                ),
+                None => None,
+            };
+            for (exchange, symbols) in subscriptions {
+                let mut container: Box<dyn ISocketContainer> = match exchange {
+                    BinanceContainer::EXCHANGE => Box::new(BinanceContainer::new_with_sender(socket_consumer.sender()).with_order_books(order_books.clone())),
+                    CoinBaseContainer::EXCHANGE => Box::new(CoinBaseContainer::new_with_sender(socket_consumer.sender()).with_order_books(order_books.clone())),
+                    GeminiContainer::EXCHANGE => Box::new(GeminiContainer::new_with_sender(socket_consumer.sender()).with_order_books(order_books.clone())),
+                    _ => Box::new(BitstampContainer::new_with_sender(socket_consumer.sender()).with_order_books(order_books.clone())),
+                };
+                if let Some(recorder) = &recorder {
+                    container.set_recorder(recorder.clone());
+                }
+                for symbol in symbols {
+                    if let Err(e) = container.add_symbol(symbol) {
+                        log_error!(symbol = %symbol, exchange = container.exchange(), error = %e, "Cannot add symbol");
+                    }
+                }
+                socket_consumer.add_container(container);
+            }
+            thread::spawn(move || socket_consumer.start_price_monitoring());
+        }
+    }
 
     // Start the server
     let server_address = config.server_address();
-    log_info!("Server starting on {}", server_address);
-    
-    // let listener = tokio::net::TcpListener::bind(&server_address)
-    //     .await
-    //     .map_err(|e| AppError::InternalServerError(format!("Failed to bind to {}: {}", server_address, e)))?;
-    
-    // axum::serve(listener, app)
-    //     .await
-    //     .map_err(|e| AppError::InternalServerError(format!("Server error: {}", e)))?;
-
-    // let mut socket_consumer = SocketConsumer::new();
-    // socket_consumer.add_symbol("btcusdt".to_string());
-    // let handle = thread::spawn(move|| {
-    //     socket_consumer.start_binance_price_monitoring();
-    // });
-    // handle.join();
-
-    // let mut coinbase_container = CoinBaseContainer::new();
-    // coinbase_container.add_symbol("ETH-USDT");
-    // coinbase_container.add_symbol("BTC-USDT");
-    // let handle = thread::spawn(move || {
-    //     coinbase_container.start_monitoring();
-
-    //     let handle = thread::spawn(move || {
-    //         coinbase_container.on_symbol_update(|symbol, price| {
-    //             println!("Got {} - ${}", symbol, price);
-    //         });
-    //     });
-
-    //     handle.join();
-    // });
-
-    // handle.join();
+    log_info!(address = %server_address, "Server starting");
+
+    let listener = tokio::net::TcpListener::bind(&server_address)
+        .await
+        .map_err(|e| AppError::InternalServerError(format!("Failed to bind to {}: {}", server_address, e)))?;
+
+    axum::serve(listener, app)
+        .await
+        .map_err(|e| AppError::InternalServerError(format!("Server error: {}", e)))?;
+
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,126 @@
+use std::sync::OnceLock;
+
+use prometheus::{
+    exponential_buckets, Encoder, Histogram, HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry, TextEncoder,
+};
+
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+/// Process wide metrics, created on first use
+pub fn metrics() -> &'static Metrics {
+    METRICS.get_or_init(Metrics::new)
+}
+
+/// Counters and histograms exposed on `GET /metrics` in the Prometheus text format
+pub struct Metrics {
+    registry: Registry,
+    /// Text frames received, per exchange
+    pub messages_received: IntCounterVec,
+    /// Frames or prices that could not be parsed, per exchange
+    pub parse_failures: IntCounterVec,
+    /// Reconnection attempts, per exchange
+    pub reconnects: IntCounterVec,
+    /// Age of a tick when the detection loop picks it up, per exchange
+    pub feed_latency: HistogramVec,
+    /// Time from the exchange event to receiving it, per exchange
+    pub exchange_latency: HistogramVec,
+    /// Time from receiving a tick to the opportunities found on it, per exchange
+    pub opportunity_latency: HistogramVec,
+    /// Cross-exchange opportunities found, per symbol and pair of venues
+    pub opportunities_detected: IntCounterVec,
+    /// Time the detection loop spends on one tick
+    pub detection_duration: Histogram,
+    /// Time spent serving an HTTP request, per method, route and status
+    pub http_request_duration: HistogramVec,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+        // 100µs to ~13s
+        let latency_buckets = exponential_buckets(0.0001, 2.0, 18).unwrap();
+        let metrics = Metrics {
+            messages_received: IntCounterVec::new(
+                Opts::new("exchange_messages_received_total", "Text frames received from the exchange"),
+                &["exchange"],
+            )
+            .unwrap(),
+            parse_failures: IntCounterVec::new(
+                Opts::new("exchange_parse_failures_total", "Frames or prices from the exchange that could not be parsed"),
+                &["exchange"],
+            )
+            .unwrap(),
+            reconnects: IntCounterVec::new(
+                Opts::new("exchange_reconnects_total", "Attempts to reconnect to the exchange"),
+                &["exchange"],
+            )
+            .unwrap(),
+            feed_latency: HistogramVec::new(
+                HistogramOpts::new("feed_latency_seconds", "Time from receiving a tick to the detection loop picking it up")
+                    .buckets(latency_buckets.clone()),
+                &["exchange"],
+            )
+            .unwrap(),
+            exchange_latency: HistogramVec::new(
+                HistogramOpts::new("exchange_to_receive_latency_seconds", "Time from the exchange event time to receiving the tick")
+                    .buckets(latency_buckets.clone()),
+                &["exchange"],
+            )
+            .unwrap(),
+            opportunity_latency: HistogramVec::new(
+                HistogramOpts::new("receive_to_opportunity_latency_seconds", "Time from receiving a tick to detecting opportunities on it")
+                    .buckets(latency_buckets.clone()),
+                &["exchange"],
+            )
+            .unwrap(),
+            opportunities_detected: IntCounterVec::new(
+                Opts::new("arbitrage_opportunities_detected_total", "Cross-exchange opportunities found on a tick"),
+                &["symbol", "buy_exchange", "sell_exchange"],
+            )
+            .unwrap(),
+            detection_duration: Histogram::with_opts(
+                HistogramOpts::new("detection_loop_duration_seconds", "Time the detection loop spends on one tick")
+                    .buckets(latency_buckets.clone()),
+            )
+            .unwrap(),
+            http_request_duration: HistogramVec::new(
+                HistogramOpts::new("http_request_duration_seconds", "Time spent serving an HTTP request").buckets(latency_buckets),
+                &["method", "path", "status"],
+            )
+            .unwrap(),
+            registry,
+        };
+
+        metrics.registry.register(Box::new(metrics.messages_received.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.parse_failures.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.reconnects.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.feed_latency.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.exchange_latency.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.opportunity_latency.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.opportunities_detected.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.detection_duration.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.http_request_duration.clone())).unwrap();
+        metrics
+    }
+
+    /// Every metric in the Prometheus text exposition format
+    pub fn render(&self) -> Result<String, String> {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .map_err(|e| format!("Cannot encode metrics: {}", e))?;
+        String::from_utf8(buffer).map_err(|e| format!("Cannot encode metrics: {}", e))
+    }
+
+    pub fn message_received(&self, exchange: &str) {
+        self.messages_received.with_label_values(&[exchange]).inc();
+    }
+
+    pub fn parse_failed(&self, exchange: &str) {
+        self.parse_failures.with_label_values(&[exchange]).inc();
+    }
+
+    pub fn reconnecting(&self, exchange: &str) {
+        self.reconnects.with_label_values(&[exchange]).inc();
+    }
+}
@@ -26,36 +26,444 @@ impl<T> ApiResponse<T> {
     }
 }
 
-/// Arbitrage opportunity model (for future use)
+/// Cross-exchange arbitrage opportunity sized against both order books
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ArbitrageOpportunity {
     pub id: String,
     pub symbol: String,
     pub buy_exchange: String,
     pub sell_exchange: String,
+    /// Best ask on the buy venue
     pub buy_price: f64,
+    /// Best bid on the sell venue
     pub sell_price: f64,
+    /// Net profit after fees relative to the buy leg cost
     pub profit_percentage: f64,
+    /// Maximum executable base quantity that remains profitable
+    pub quantity: f64,
+    /// Volume weighted average price of the buy leg
+    pub buy_vwap: f64,
+    /// Volume weighted average price of the sell leg
+    pub sell_vwap: f64,
+    /// Quote notional of the buy leg
+    pub buy_notional: f64,
+    /// Quote notional of the sell leg
+    pub sell_notional: f64,
+    /// Expected profit in quote currency after fees
+    pub expected_profit: f64,
+    /// Market bought on the buy venue, e.g. `BTCUSDT` when `symbol` is normalized to `BTCUSD`
+    pub buy_symbol: String,
+    /// Market sold on the sell venue
+    pub sell_symbol: String,
+    /// Rate applied to the buy venue prices to express them in the quote currency of `symbol`
+    pub buy_conversion: Option<ConversionRate>,
+    /// Rate applied to the sell venue prices to express them in the quote currency of `symbol`
+    pub sell_conversion: Option<ConversionRate>,
     pub timestamp: chrono::DateTime<chrono::Utc>,
 }
 
-/// Market data model (for future use)
+/// Live rate used to express a quote currency in the reference currency
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ConversionRate {
+    pub currency: String,
+    pub reference_currency: String,
+    /// Units of reference currency per unit of `currency`
+    pub rate: f64,
+    /// Market(s) the rate was derived from, e.g. `coinbase:USDTUSD`
+    pub source: String,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Last price and traded volume of one market
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Market {
     pub exchange: String,
     pub symbol: String,
     pub price: f64,
-    pub volume: f64,
+    /// Base quantity traded over the last 24 hours, as reported by the feed (Binance)
+    /// or summed from its trades; unset when the feed reports neither
+    pub volume: Option<f64>,
     pub timestamp: chrono::DateTime<chrono::Utc>,
 }
 
+/// Share of a period an exchange feed delivered ticks
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FeedUptime {
+    pub exchange: String,
+    /// Minutes of the period with at least one tick on any market of the exchange
+    pub active_minutes: u64,
+    pub minutes: u64,
+    pub uptime_percentage: f64,
+    pub last_tick_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Width of a candle
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum CandleInterval {
+    #[serde(rename = "1s")]
+    OneSecond,
+    #[serde(rename = "1m")]
+    OneMinute,
+    #[serde(rename = "5m")]
+    FiveMinutes,
+    #[serde(rename = "1h")]
+    OneHour,
+}
+
+impl CandleInterval {
+    pub const ALL: [CandleInterval; 4] = [
+        CandleInterval::OneSecond,
+        CandleInterval::OneMinute,
+        CandleInterval::FiveMinutes,
+        CandleInterval::OneHour,
+    ];
+
+    pub fn millis(&self) -> i64 {
+        match self {
+            CandleInterval::OneSecond => 1_000,
+            CandleInterval::OneMinute => 60_000,
+            CandleInterval::FiveMinutes => 300_000,
+            CandleInterval::OneHour => 3_600_000,
+        }
+    }
+}
+
+/// OHLCV bar of one market over one interval
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Candle {
+    pub exchange: String,
+    pub symbol: String,
+    pub interval: CandleInterval,
+    pub open_time: chrono::DateTime<chrono::Utc>,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    /// Base quantity traded, unset on feeds that do not report trade sizes
+    pub volume: Option<f64>,
+    /// Ticks aggregated into the candle
+    pub ticks: u64,
+    /// Time of the last tick
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
 pub struct SymbolMessage {
+    pub exchange: String,
     pub symbol: String,
-    pub price: f64
+    pub price: f64,
+    /// When the frame carrying the price was received, the recorded time when replaying
+    pub received_at: chrono::DateTime<chrono::Utc>,
+    /// Base quantity traded at `price`, when the feed reports individual trades
+    pub quantity: Option<f64>,
+    /// Base quantity traded over the last 24 hours, when the feed reports it
+    pub volume_24h: Option<f64>,
+    /// Time the exchange stamped the event with, when the feed reports it
+    pub exchange_time: Option<chrono::DateTime<chrono::Utc>>,
+    /// Monotonic time the frame carrying the price was read from the socket
+    pub read_at: std::time::Instant,
+    /// Monotonic time the frame was parsed into this update
+    pub normalized_at: std::time::Instant,
 }
 
 impl SymbolMessage {
-    pub fn new(symbol: String, price: f64) -> Self {
-        SymbolMessage { symbol, price }
+    pub fn new(exchange: &str, symbol: String, price: f64) -> Self {
+        let now = std::time::Instant::now();
+        SymbolMessage {
+            exchange: exchange.to_string(),
+            symbol,
+            price,
+            received_at: chrono::Utc::now(),
+            quantity: None,
+            volume_24h: None,
+            exchange_time: None,
+            read_at: now,
+            normalized_at: now,
+        }
+    }
+
+    pub fn with_quantity(mut self, quantity: Option<f64>) -> Self {
+        self.quantity = quantity;
+        self
+    }
+
+    pub fn with_volume_24h(mut self, volume_24h: Option<f64>) -> Self {
+        self.volume_24h = volume_24h;
+        self
+    }
+
+    pub fn with_exchange_time(mut self, exchange_time: Option<chrono::DateTime<chrono::Utc>>) -> Self {
+        self.exchange_time = exchange_time;
+        self
+    }
+
+    /// Stamp the read of the frame, taken before parsing it
+    pub fn with_read_at(mut self, read_at: std::time::Instant) -> Self {
+        self.read_at = read_at;
+        self
+    }
+}
+/// Base / quote split of an exchange symbol such as `BTCUSDT` or `BTC-USD`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct CurrencyPair {
+    pub base: String,
+    pub quote: String,
+}
+
+impl CurrencyPair {
+    /// Known quote currencies, longer codes sharing a suffix with shorter ones come first
+    pub const QUOTES: [&'static str; 11] = ["USDT", "USDC", "BUSD", "FDUSD", "USD", "DAI", "EUR", "GBP", "BTC", "ETH", "BNB"];
+
+    pub fn new(base: &str, quote: &str) -> Self {
+        CurrencyPair { base: base.to_uppercase(), quote: quote.to_uppercase() }
+    }
+
+    pub fn parse(symbol: &str) -> Option<Self> {
+        let normalized = symbol.to_uppercase().replace(['-', '/', '_'], "");
+        Self::QUOTES.iter().find_map(|quote| {
+            let base = normalized.strip_suffix(quote)?;
+            (!base.is_empty()).then(|| CurrencyPair::new(base, quote))
+        })
+    }
+
+    /// Symbol as used for order books and messages, e.g. `BTCUSDT`
+    pub fn symbol(&self) -> String {
+        format!("{}{}", self.base, self.quote)
+    }
+}
+
+/// Direction of a trade leg
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum TradeSide {
+    Buy,
+    Sell,
+    /// Move the currency to another exchange
+    Transfer,
+}
+
+/// One conversion step of a multi-leg opportunity
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OpportunityLeg {
+    pub exchange: String,
+    pub symbol: String,
+    pub side: TradeSide,
+    pub from_currency: String,
+    pub to_currency: String,
+    /// Best ask when buying, best bid when selling
+    pub price: f64,
+    /// Units of `to_currency` received per unit of `from_currency` after fees
+    pub rate: f64,
+    /// Receiving exchange of a `Transfer` leg
+    pub destination_exchange: Option<String>,
+}
+
+/// Kind of detector that produced a multi-leg opportunity
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MultiLegKind {
+    Triangular,
+    NegativeCycle,
+}
+
+/// Arbitrage loop converting `start_currency` through several legs back into itself
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MultiLegOpportunity {
+    pub id: String,
+    pub kind: MultiLegKind,
+    pub start_currency: String,
+    pub legs: Vec<OpportunityLeg>,
+    /// Net profit after fees of one full loop, in percent of the start amount
+    pub profit_percentage: f64,
+    /// Largest start amount the top of book of every leg can absorb, if known
+    pub max_start_amount: Option<f64>,
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+}
+
+/// Latest peg of a stablecoin against the reference currency on one venue
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PegStatus {
+    pub exchange: String,
+    /// Market the peg is read from, e.g. `USDTUSD`
+    pub symbol: String,
+    pub currency: String,
+    pub reference_currency: String,
+    /// Units of reference currency per unit of `currency`
+    pub price: f64,
+    /// Signed distance from 1.0, in percent
+    pub deviation_percentage: f64,
+    /// Whether the deviation is currently beyond the alert threshold
+    pub depegged: bool,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Transition of a stablecoin peg across the alert threshold
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DepegAlertKind {
+    Depegged,
+    Recovered,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DepegAlert {
+    pub kind: DepegAlertKind,
+    pub threshold_percentage: f64,
+    pub peg: PegStatus,
+}
+
+/// Lifecycle state of a tracked opportunity
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OpportunityStatus {
+    Open,
+    Closed,
+}
+
+/// Opportunity window of one (symbol, buy venue, sell venue) combination, from
+/// the first tick it is detected on until the first tick it no longer is
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TrackedOpportunity {
+    /// Stable for the whole window
+    pub id: String,
+    pub symbol: String,
+    pub buy_exchange: String,
+    pub sell_exchange: String,
+    pub status: OpportunityStatus,
+    pub opened_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    pub closed_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Time the window has been (or was) open, in milliseconds
+    pub duration_ms: i64,
+    /// Number of ticks the opportunity was detected on
+    pub tick_count: u64,
+    pub current_profit_percentage: f64,
+    pub peak_profit_percentage: f64,
+    /// Sell venue best bid minus buy venue best ask
+    pub current_spread: f64,
+    pub peak_spread: f64,
+    /// Last detection of the window
+    pub latest: ArbitrageOpportunity,
+}
+
+/// Lifecycle transition of a tracked opportunity
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OpportunityEventKind {
+    Opened,
+    Updated,
+    Closed,
+}
+
+impl OpportunityEventKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            OpportunityEventKind::Opened => "opened",
+            OpportunityEventKind::Updated => "updated",
+            OpportunityEventKind::Closed => "closed",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OpportunityEvent {
+    pub kind: OpportunityEventKind,
+    pub opportunity: TrackedOpportunity,
+}
+
+/// Persisted lifecycle event of an opportunity window
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OpportunityHistoryEntry {
+    pub id: i64,
+    pub kind: OpportunityEventKind,
+    /// Time of the transition: the close time for `closed`, the last update otherwise
+    pub event_at: chrono::DateTime<chrono::Utc>,
+    pub opportunity: TrackedOpportunity,
+}
+
+/// Granularity of stored quotes
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QuoteResolution {
+    /// Every tick as received
+    #[serde(rename = "raw")]
+    Raw,
+    #[serde(rename = "1s")]
+    OneSecond,
+    #[serde(rename = "1m")]
+    OneMinute,
+}
+
+impl QuoteResolution {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "raw" => Some(QuoteResolution::Raw),
+            "1s" => Some(QuoteResolution::OneSecond),
+            "1m" => Some(QuoteResolution::OneMinute),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            QuoteResolution::Raw => "raw",
+            QuoteResolution::OneSecond => "1s",
+            QuoteResolution::OneMinute => "1m",
+        }
+    }
+
+    /// Bucket width in milliseconds, 0 for raw ticks
+    pub fn millis(&self) -> i64 {
+        match self {
+            QuoteResolution::Raw => 0,
+            QuoteResolution::OneSecond => 1_000,
+            QuoteResolution::OneMinute => 60_000,
+        }
+    }
+}
+
+/// Last traded price and top of book of one market, either as ticked or as
+/// the last quote of a downsampled bucket
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct QuoteSnapshot {
+    pub exchange: String,
+    pub symbol: String,
+    pub resolution: QuoteResolution,
+    /// Tick time, or bucket start for downsampled quotes
+    pub at: chrono::DateTime<chrono::Utc>,
+    pub price: f64,
+    pub bid: Option<f64>,
+    pub bid_quantity: Option<f64>,
+    pub ask: Option<f64>,
+    pub ask_quantity: Option<f64>,
+    /// Ticks summarized by this quote
+    pub samples: u64,
+}
+
+/// One page of a paginated listing
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Number of items matching the filters across all pages
+    pub total: u64,
+    pub limit: u32,
+    pub offset: u64,
+}
+
+/// Event pushed to the clients of the streaming API
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum StreamEvent {
+    DepegAlert(DepegAlert),
+    Opportunity(Box<OpportunityEvent>),
+    MultiLegOpportunity(Box<MultiLegOpportunity>),
+}
+
+impl StreamEvent {
+    /// Name of the event as sent to the clients
+    pub fn name(&self) -> &'static str {
+        match self {
+            StreamEvent::DepegAlert(_) => "depeg_alert",
+            StreamEvent::Opportunity(_) => "opportunity",
+            StreamEvent::MultiLegOpportunity(_) => "multi_leg_opportunity",
+        }
     }
-}
\ No newline at end of file
+}
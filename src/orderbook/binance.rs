@@ -0,0 +1,227 @@
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+
+use crate::orderbook::{integrity::IntegrityEvent, parse_levels, Level, OrderBookStore, Side};
+use crate::{log_debug, log_info, log_warn};
+
+const BINANCE_DEPTH_SNAPSHOT_URL: &str = "https://api.binance.com/api/v3/depth";
+const SNAPSHOT_LIMIT: u32 = 1000;
+const SNAPSHOT_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// `depthUpdate` event from the `<symbol>@depth` stream
+#[derive(Debug, Clone)]
+pub struct DepthUpdate {
+    /// First update id in the event (`U`)
+    pub first_update_id: u64,
+    /// Final update id in the event (`u`)
+    pub final_update_id: u64,
+    pub bids: Vec<Level>,
+    pub asks: Vec<Level>,
+}
+
+impl DepthUpdate {
+    pub fn from_json(json: &Value) -> Option<Self> {
+        Some(DepthUpdate {
+            first_update_id: json["U"].as_u64()?,
+            final_update_id: json["u"].as_u64()?,
+            bids: parse_levels(&json["b"]),
+            asks: parse_levels(&json["a"]),
+        })
+    }
+}
+
+/// REST depth snapshot from `/api/v3/depth`
+#[derive(Debug, Clone)]
+pub struct DepthSnapshot {
+    pub last_update_id: u64,
+    pub bids: Vec<Level>,
+    pub asks: Vec<Level>,
+}
+
+impl DepthSnapshot {
+    pub fn from_json(json: &Value) -> Option<Self> {
+        Some(DepthSnapshot {
+            last_update_id: json["lastUpdateId"].as_u64()?,
+            bids: parse_levels(&json["bids"]),
+            asks: parse_levels(&json["asks"]),
+        })
+    }
+}
+
+/// Fetch a depth snapshot for a symbol such as `BTCUSDT`
+pub fn fetch_snapshot(symbol: &str) -> Result<DepthSnapshot, String> {
+    let json = fetch_snapshot_json(symbol)?;
+    DepthSnapshot::from_json(&json)
+        .ok_or_else(|| format!("Malformed Binance depth snapshot for {}", symbol))
+}
+
+/// Fetch the raw JSON depth snapshot of a symbol
+pub fn fetch_snapshot_json(symbol: &str) -> Result<Value, String> {
+    let url = format!("{}?symbol={}&limit={}", BINANCE_DEPTH_SNAPSHOT_URL, symbol.to_uppercase(), SNAPSHOT_LIMIT);
+    ureq::get(&url)
+        .call()
+        .map_err(|e| format!("Cannot fetch Binance depth snapshot for {}: {}", symbol, e))?
+        .body_mut()
+        .read_json()
+        .map_err(|e| format!("Invalid Binance depth snapshot for {}: {}", symbol, e))
+}
+
+/// Keeps a local Binance book in sync with the diff stream following the
+/// documented procedure: buffer diffs, apply a REST snapshot, drop diffs older
+/// than the snapshot and then require every diff to continue the `U`/`u` sequence.
+pub struct BinanceBookSync {
+    exchange: String,
+    symbol: String,
+    store: OrderBookStore,
+    buffer: Vec<DepthUpdate>,
+    synced: bool,
+    /// Set when the book was dropped because of a gap or a crossed book
+    recovering: bool,
+    last_snapshot_attempt: Option<Instant>,
+    /// Disabled when replaying, the recorded snapshots are applied instead
+    fetch_snapshots: bool,
+}
+
+impl BinanceBookSync {
+    pub fn new(exchange: &str, symbol: &str, store: OrderBookStore) -> Self {
+        BinanceBookSync {
+            exchange: exchange.to_string(),
+            symbol: symbol.to_uppercase(),
+            store,
+            buffer: Vec::new(),
+            synced: false,
+            recovering: false,
+            last_snapshot_attempt: None,
+            fetch_snapshots: true,
+        }
+    }
+
+    /// Never request REST snapshots, they have to be provided through `apply_snapshot`
+    pub fn without_snapshot_fetch(mut self) -> Self {
+        self.fetch_snapshots = false;
+        self
+    }
+
+    pub fn symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    pub fn is_synced(&self) -> bool {
+        self.synced
+    }
+
+    /// Drop the local state after a reconnection, the next diffs trigger a new snapshot
+    pub fn reset(&mut self) {
+        self.synced = false;
+        self.buffer.clear();
+    }
+
+    /// Whether a REST snapshot should be requested now
+    pub fn should_fetch_snapshot(&self) -> bool {
+        self.fetch_snapshots
+            && !self.synced
+            && !self.buffer.is_empty()
+            && self
+                .last_snapshot_attempt
+                .is_none_or(|attempt| attempt.elapsed() >= SNAPSHOT_RETRY_INTERVAL)
+    }
+
+    /// Feed a diff event from the stream
+    pub fn on_depth_update(&mut self, update: DepthUpdate) {
+        if !self.synced {
+            self.buffer.push(update);
+            return;
+        }
+
+        let last_update_id = self.store.update(&self.exchange, &self.symbol, |book| book.last_update_id);
+        if update.final_update_id <= last_update_id {
+            return;
+        }
+
+        if update.first_update_id != last_update_id + 1 {
+            log_warn!(
+                symbol = %self.symbol,
+                expected = last_update_id + 1,
+                first_update_id = update.first_update_id,
+                "Sequence gap, resyncing"
+            );
+            self.store.record_integrity(&self.exchange, IntegrityEvent::SequenceGap);
+            self.invalidate();
+            self.buffer.push(update);
+            return;
+        }
+
+        self.apply(&update);
+        if self.store.check_crossed(&self.exchange, &self.symbol) {
+            log_warn!(symbol = %self.symbol, update_id = update.final_update_id, "Crossed book, resyncing");
+            self.invalidate();
+        }
+    }
+
+    /// Drop the local book after an integrity failure so it is rebuilt from a snapshot
+    fn invalidate(&mut self) {
+        self.synced = false;
+        self.recovering = true;
+        self.buffer.clear();
+    }
+
+    /// Fetch a snapshot over REST and apply it, returns the raw snapshot so it can be recorded
+    pub fn resync(&mut self) -> Result<Value, String> {
+        self.last_snapshot_attempt = Some(Instant::now());
+        let json = fetch_snapshot_json(&self.symbol)?;
+        let snapshot = DepthSnapshot::from_json(&json)
+            .ok_or_else(|| format!("Malformed Binance depth snapshot for {}", self.symbol))?;
+        self.apply_snapshot(snapshot);
+        Ok(json)
+    }
+
+    /// Apply a snapshot and replay the buffered diffs on top of it
+    pub fn apply_snapshot(&mut self, snapshot: DepthSnapshot) {
+        let last_update_id = snapshot.last_update_id;
+        self.buffer.retain(|update| update.final_update_id > last_update_id);
+
+        if let Some(first) = self.buffer.first()
+            && first.first_update_id > last_update_id + 1
+        {
+            log_debug!(symbol = %self.symbol, "Snapshot older than the buffered diffs, retrying");
+            return;
+        }
+
+        self.store.update(&self.exchange, &self.symbol, |book| {
+            book.apply_snapshot(&snapshot.bids, &snapshot.asks, last_update_id);
+        });
+
+        let buffered = std::mem::take(&mut self.buffer);
+        let mut previous_update_id = None;
+        for update in &buffered {
+            if previous_update_id.is_some_and(|previous: u64| update.first_update_id != previous + 1) {
+                log_warn!(symbol = %self.symbol, "Sequence gap in the buffered diffs, resyncing");
+                self.store.record_integrity(&self.exchange, IntegrityEvent::SequenceGap);
+                self.invalidate();
+                return;
+            }
+            self.apply(update);
+            previous_update_id = Some(update.final_update_id);
+        }
+
+        self.synced = true;
+        if self.recovering {
+            self.recovering = false;
+            self.store.record_integrity(&self.exchange, IntegrityEvent::Resync);
+        }
+        log_info!(symbol = %self.symbol, update_id = last_update_id, "Order book synced");
+    }
+
+    fn apply(&self, update: &DepthUpdate) {
+        self.store.update(&self.exchange, &self.symbol, |book| {
+            for level in &update.bids {
+                book.set_level(Side::Bid, level.price, level.quantity);
+            }
+            for level in &update.asks {
+                book.set_level(Side::Ask, level.price, level.quantity);
+            }
+            book.last_update_id = update.final_update_id;
+        });
+    }
+}
@@ -0,0 +1,67 @@
+use std::collections::{HashMap, HashSet};
+
+use serde_json::Value;
+
+use crate::orderbook::{integrity::IntegrityEvent, parse_levels, OrderBookStore};
+use crate::log_warn;
+
+/// Maintains Bitstamp books from the `order_book_*` channels. Every message is a
+/// full top 100 snapshot, so integrity is checked through the monotonic
+/// `microtimestamp` and the crossed book test; a rejected book is rebuilt by
+/// the next valid snapshot.
+pub struct BitstampBookSync {
+    exchange: String,
+    store: OrderBookStore,
+    last_microtimestamp: HashMap<String, u64>,
+    recovering: HashSet<String>,
+}
+
+impl BitstampBookSync {
+    pub fn new(exchange: &str, store: OrderBookStore) -> Self {
+        BitstampBookSync {
+            exchange: exchange.to_string(),
+            store,
+            last_microtimestamp: HashMap::new(),
+            recovering: HashSet::new(),
+        }
+    }
+
+    /// Apply an order book `data` event, returns `false` for any other message
+    pub fn on_message(&mut self, json: &Value) -> bool {
+        let Some(pair) = json["channel"].as_str().and_then(|channel| channel.strip_prefix("order_book_")) else {
+            return false;
+        };
+        if json["event"] != "data" {
+            return false;
+        }
+
+        let symbol = pair.to_uppercase();
+        let data = &json["data"];
+        let microtimestamp = data["microtimestamp"]
+            .as_str()
+            .and_then(|timestamp| timestamp.parse::<u64>().ok())
+            .unwrap_or_default();
+
+        let last = self.last_microtimestamp.get(&symbol).copied().unwrap_or_default();
+        if microtimestamp <= last {
+            log_warn!(symbol = %symbol, microtimestamp, last_microtimestamp = last, "Out of order snapshot, ignoring");
+            self.store.record_integrity(&self.exchange, IntegrityEvent::SequenceGap);
+            return true;
+        }
+        self.last_microtimestamp.insert(symbol.clone(), microtimestamp);
+
+        let bids = parse_levels(&data["bids"]);
+        let asks = parse_levels(&data["asks"]);
+        self.store.update(&self.exchange, &symbol, |book| {
+            book.apply_snapshot(&bids, &asks, microtimestamp);
+        });
+
+        if self.store.check_crossed(&self.exchange, &symbol) {
+            log_warn!(symbol = %symbol, "Crossed snapshot, waiting for the next one");
+            self.recovering.insert(symbol);
+        } else if self.recovering.remove(&symbol) {
+            self.store.record_integrity(&self.exchange, IntegrityEvent::Resync);
+        }
+        true
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::{HashMap, HashSet};
+
+use serde_json::Value;
+
+use crate::orderbook::{integrity::IntegrityEvent, parse_levels, parse_number, resync::BackgroundResync, OrderBookStore, Side};
+use crate::{log_debug, log_info, log_warn};
+
+const COINBASE_BOOK_SNAPSHOT_URL: &str = "https://api.exchange.coinbase.com/products";
+/// Updates kept per product while its snapshot is fetched, the snapshot is
+/// fetched again when more arrive
+const MAX_BUFFERED_UPDATES: usize = 50_000;
+
+/// Maintains Coinbase books from the `level2` channel, which starts with a
+/// `snapshot` message per product followed by `l2update` messages carrying
+/// absolute sizes for the changed price levels.
+///
+/// Coinbase sequences are shared by every channel of a product, so on level2
+/// they grow with gaps and only order the messages: an update carrying a
+/// `sequence` at or below the sequence of the book is older than the book, e.g.
+/// queued while a snapshot was fetched, and is skipped. A crossed book is
+/// dropped and rebuilt from the REST level 2 snapshot on a background thread.
+/// Updates of the product are buffered until then and replayed on top of the
+/// snapshot, skipping those at or below its `sequence`.
+pub struct CoinbaseBookSync {
+    exchange: String,
+    store: OrderBookStore,
+    /// None when replaying, a dropped book then stays empty until the next snapshot
+    resync: Option<BackgroundResync>,
+    /// Messages of the products waiting for their REST snapshot
+    buffered: HashMap<String, Vec<Value>>,
+    /// Products whose buffer overflowed, resynced again once the pending snapshot is applied
+    overflowed: HashSet<String>,
+}
+
+impl CoinbaseBookSync {
+    pub fn new(exchange: &str, store: OrderBookStore) -> Self {
+        let resync = {
+            let exchange = exchange.to_string();
+            let store = store.clone();
+            BackgroundResync::spawn(move |product_id| fetch_snapshot(&exchange, &store, product_id))
+        };
+        CoinbaseBookSync {
+            exchange: exchange.to_string(),
+            store,
+            resync: Some(resync),
+            buffered: HashMap::new(),
+            overflowed: HashSet::new(),
+        }
+    }
+
+    /// Never rebuild dropped books over REST
+    pub fn without_resync(mut self) -> Self {
+        self.resync = None;
+        self
+    }
+
+    /// Forget the buffered updates after a reconnection, the server resends a snapshot of every product
+    pub fn reset(&mut self) {
+        self.buffered.clear();
+        self.overflowed.clear();
+    }
+
+    /// Apply a level2 message, returns `false` if the message is not a book message
+    pub fn on_message(&mut self, json: &Value) -> bool {
+        let Some(product_id) = json["product_id"].as_str() else {
+            return false;
+        };
+        if !matches!(json["type"].as_str(), Some("snapshot") | Some("l2update")) {
+            return false;
+        }
+        if self.is_pending(product_id) {
+            self.buffer(product_id, json);
+            return true;
+        }
+
+        if self.overflowed.remove(product_id) {
+            // The snapshot is older than the first dropped update
+            self.buffered.remove(product_id);
+            self.request_resync(product_id);
+            self.buffer(product_id, json);
+            return true;
+        }
+        if let Some(buffered) = self.buffered.remove(product_id) {
+            log_debug!(product_id = %product_id, updates = buffered.len(), "Replaying buffered updates over the snapshot");
+            for (index, message) in buffered.iter().enumerate() {
+                if self.is_pending(product_id) {
+                    self.buffered.insert(product_id.to_string(), buffered[index..].to_vec());
+                    self.buffer(product_id, json);
+                    return true;
+                }
+                self.apply(product_id, message);
+            }
+        }
+        if self.is_pending(product_id) {
+            self.buffer(product_id, json);
+        } else {
+            self.apply(product_id, json);
+        }
+        true
+    }
+
+    fn is_pending(&self, product_id: &str) -> bool {
+        self.resync.as_ref().is_some_and(|resync| resync.is_pending(product_id))
+    }
+
+    /// Keep a message of a product waiting for its snapshot
+    fn buffer(&mut self, product_id: &str, json: &Value) {
+        if self.overflowed.contains(product_id) {
+            return;
+        }
+        let buffered = self.buffered.entry(product_id.to_string()).or_default();
+        if buffered.len() >= MAX_BUFFERED_UPDATES {
+            log_warn!(product_id = %product_id, "Too many updates while resyncing, fetching the snapshot again");
+            self.buffered.remove(product_id);
+            self.overflowed.insert(product_id.to_string());
+            return;
+        }
+        buffered.push(json.clone());
+    }
+
+    fn request_resync(&self, product_id: &str) -> bool {
+        self.resync.as_ref().is_some_and(|resync| resync.request(product_id))
+    }
+
+    /// Apply a snapshot or an update, dropping and resyncing the book when it crosses
+    fn apply(&self, product_id: &str, json: &Value) {
+        let symbol = product_id.trim().replace("-", "");
+        let sequence = json["sequence"].as_u64();
+        if json["type"] == "snapshot" {
+            let bids = parse_levels(&json["bids"]);
+            let asks = parse_levels(&json["asks"]);
+            self.store.update(&self.exchange, &symbol, |book| {
+                book.apply_snapshot(&bids, &asks, sequence.unwrap_or_default());
+            });
+        } else {
+            let Some(changes) = json["changes"].as_array() else {
+                log_warn!(product_id = %product_id, "l2update without changes");
+                return;
+            };
+
+            let applied = self.store.update(&self.exchange, &symbol, |book| {
+                if let Some(sequence) = sequence {
+                    if sequence <= book.last_update_id {
+                        return false;
+                    }
+                    book.last_update_id = sequence;
+                }
+                for change in changes {
+                    let side = match change[0].as_str() {
+                        Some("buy") => Side::Bid,
+                        Some("sell") => Side::Ask,
+                        _ => continue,
+                    };
+                    if let (Some(price), Some(size)) = (parse_number(&change[1]), parse_number(&change[2])) {
+                        book.set_level(side, price, size);
+                    }
+                }
+                true
+            });
+
+            if !applied {
+                log_debug!(product_id = %product_id, sequence = ?sequence, "Skipping an update older than the book");
+                return;
+            }
+        }
+
+        if self.store.check_crossed(&self.exchange, &symbol) && self.request_resync(product_id) {
+            log_warn!(product_id = %product_id, "Crossed book, resyncing");
+        }
+    }
+}
+
+/// Rebuild a book from the REST level 2 snapshot of a product such as `BTC-USD`
+fn fetch_snapshot(exchange: &str, store: &OrderBookStore, product_id: &str) -> Result<(), String> {
+    let url = format!("{}/{}/book?level=2", COINBASE_BOOK_SNAPSHOT_URL, product_id);
+    let json: Value = ureq::get(&url)
+        .call()
+        .map_err(|e| format!("Cannot fetch Coinbase book snapshot for {}: {}", product_id, e))?
+        .body_mut()
+        .read_json()
+        .map_err(|e| format!("Invalid Coinbase book snapshot for {}: {}", product_id, e))?;
+
+    let symbol = product_id.trim().replace("-", "");
+    let sequence = json["sequence"].as_u64().unwrap_or_default();
+    let bids = parse_levels(&json["bids"]);
+    let asks = parse_levels(&json["asks"]);
+    store.update(exchange, &symbol, |book| {
+        book.apply_snapshot(&bids, &asks, sequence);
+    });
+    store.record_integrity(exchange, IntegrityEvent::Resync);
+
+    log_info!(product_id = %product_id, sequence, "Order book resynced");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::orderbook::Level;
+
+    #[test]
+    fn skips_updates_older_than_the_book() {
+        let store = OrderBookStore::new();
+        let mut sync = CoinbaseBookSync::new("coinbase", store.clone()).without_resync();
+        sync.on_message(&json!({
+            "type": "snapshot",
+            "product_id": "BTC-USD",
+            "sequence": 10,
+            "bids": [["100", "1"]],
+            "asks": [["101", "1"]]
+        }));
+        let l2update = |sequence: u64, price: &str| {
+            json!({ "type": "l2update", "product_id": "BTC-USD", "sequence": sequence, "changes": [["buy", price, "2"]] })
+        };
+
+        assert!(sync.on_message(&l2update(9, "100.2")));
+        assert_eq!(store.top_of_book("coinbase", "BTCUSD").unwrap().0.price, 100.0);
+
+        sync.on_message(&l2update(12, "100.4"));
+        let book = store.get("coinbase", "BTCUSD").unwrap();
+        assert_eq!(book.best_bid().unwrap().price, 100.4);
+        assert_eq!(book.last_update_id, 12);
+    }
+
+    #[test]
+    fn replays_the_updates_buffered_during_a_resync_over_the_snapshot() {
+        let store = OrderBookStore::new();
+        let (started, started_rx) = mpsc::channel();
+        let (release, release_rx) = mpsc::channel::<()>();
+        let mut sync = CoinbaseBookSync::new("coinbase", store.clone()).without_resync();
+        let snapshot_store = store.clone();
+        sync.resync = Some(BackgroundResync::spawn(move |product_id| {
+            started.send(product_id.to_string()).unwrap();
+            release_rx.recv().unwrap();
+            snapshot_store.update("coinbase", "BTCUSD", |book| {
+                book.apply_snapshot(&[Level::new(100.0, 1.0)], &[Level::new(105.0, 1.0)], 20);
+            });
+            Ok(())
+        }));
+        let l2update = |sequence: u64, side: &str, price: &str, size: &str| {
+            json!({ "type": "l2update", "product_id": "BTC-USD", "sequence": sequence, "changes": [[side, price, size]] })
+        };
+
+        sync.on_message(&json!({
+            "type": "snapshot",
+            "product_id": "BTC-USD",
+            "sequence": 10,
+            "bids": [["100", "1"]],
+            "asks": [["101", "1"]]
+        }));
+        // Crosses the book, which is dropped and fetched again
+        sync.on_message(&l2update(11, "buy", "102", "1"));
+        assert_eq!(started_rx.recv_timeout(Duration::from_secs(1)).unwrap(), "BTC-USD");
+        assert!(store.get("coinbase", "BTCUSD").unwrap().is_empty());
+
+        sync.on_message(&l2update(15, "buy", "99", "5"));
+        sync.on_message(&l2update(21, "buy", "99.5", "2"));
+        sync.on_message(&l2update(22, "sell", "104", "3"));
+        assert!(store.get("coinbase", "BTCUSD").unwrap().is_empty());
+
+        release.send(()).unwrap();
+        for _ in 0..100 {
+            if !sync.is_pending("BTC-USD") {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        sync.on_message(&l2update(23, "buy", "100", "4"));
+
+        let book = store.get("coinbase", "BTCUSD").unwrap();
+        // The update at 15 is older than the snapshot
+        assert_eq!(book.depth(Side::Bid, 3), vec![Level::new(100.0, 4.0), Level::new(99.5, 2.0)]);
+        assert_eq!(book.best_ask().unwrap(), Level::new(104.0, 3.0));
+        assert_eq!(book.last_update_id, 23);
+    }
+
+    #[test]
+    fn ignores_other_messages() {
+        let mut sync = CoinbaseBookSync::new("coinbase", OrderBookStore::new()).without_resync();
+        assert!(!sync.on_message(&json!({ "type": "ticker", "product_id": "BTC-USD", "price": "100" })));
+    }
+}
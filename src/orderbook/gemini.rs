@@ -0,0 +1,205 @@
+use std::collections::HashSet;
+
+use serde_json::Value;
+
+use crate::orderbook::{integrity::IntegrityEvent, parse_number, Level, OrderBookStore, Side};
+use crate::log_warn;
+
+/// Maintains Gemini books from the v2 `l2` subscription. The first
+/// `l2_updates` message of a symbol carries the full book, later ones carry
+/// absolute quantities of the changed levels.
+///
+/// Messages numbered with a `socket_sequence` must follow each other on the
+/// connection; after a gap every book of the connection is dropped. A dropped
+/// or crossed book stays empty until the connection is reopened and the server
+/// sends the full book again: the REST book carries no sequence, so it cannot
+/// be aligned with the updates of the socket.
+pub struct GeminiBookSync {
+    exchange: String,
+    store: OrderBookStore,
+    initialized: HashSet<String>,
+    /// `socket_sequence` of the last message of the connection
+    last_sequence: Option<u64>,
+    /// Books waiting for the next full book
+    dropped: HashSet<String>,
+    /// Books dropped on the previous connection, counted as resynced on their first full book
+    rebuilding: HashSet<String>,
+    /// Set when a book was dropped, until `take_reconnect`
+    reconnect: bool,
+}
+
+impl GeminiBookSync {
+    pub fn new(exchange: &str, store: OrderBookStore) -> Self {
+        GeminiBookSync {
+            exchange: exchange.to_string(),
+            store,
+            initialized: HashSet::new(),
+            last_sequence: None,
+            dropped: HashSet::new(),
+            rebuilding: HashSet::new(),
+            reconnect: false,
+        }
+    }
+
+    /// Forget every book after a reconnection, the server resends the full books
+    pub fn reset(&mut self) {
+        self.rebuilding.extend(self.dropped.drain());
+        self.initialized.clear();
+        self.last_sequence = None;
+        self.reconnect = false;
+    }
+
+    /// Whether a book was dropped since the last call, the connection then
+    /// has to be reopened for the server to send the full books again
+    pub fn take_reconnect(&mut self) -> bool {
+        std::mem::take(&mut self.reconnect)
+    }
+
+    /// Apply an `l2_updates` message, returns `false` for any other message.
+    /// Pass every message of the connection so sequence gaps are noticed.
+    pub fn on_message(&mut self, json: &Value) -> bool {
+        if let Some(sequence) = json["socket_sequence"].as_u64() {
+            if let Some(last) = self.last_sequence
+                && sequence != last + 1
+            {
+                log_warn!(last_sequence = last, sequence, "Sequence gap, dropping every book");
+                self.store.record_integrity(&self.exchange, IntegrityEvent::SequenceGap);
+                let symbols: Vec<String> = self.initialized.iter().cloned().collect();
+                for symbol in symbols {
+                    self.drop_book(&symbol);
+                }
+            }
+            self.last_sequence = Some(sequence);
+        }
+
+        if json["type"] != "l2_updates" {
+            return false;
+        }
+        let Some(symbol) = json["symbol"].as_str() else {
+            return false;
+        };
+        if self.dropped.contains(symbol) {
+            return true;
+        }
+
+        let mut bids = Vec::new();
+        let mut asks = Vec::new();
+        for change in json["changes"].as_array().into_iter().flatten() {
+            let (Some(price), Some(quantity)) = (parse_number(&change[1]), parse_number(&change[2])) else {
+                continue;
+            };
+            match change[0].as_str() {
+                Some("buy") => bids.push(Level::new(price, quantity)),
+                Some("sell") => asks.push(Level::new(price, quantity)),
+                _ => {}
+            }
+        }
+
+        let first_message = self.initialized.insert(symbol.to_string());
+        self.store.update(&self.exchange, symbol, |book| {
+            if first_message {
+                book.apply_snapshot(&bids, &asks, 0);
+            } else {
+                for level in &bids {
+                    book.set_level(Side::Bid, level.price, level.quantity);
+                }
+                for level in &asks {
+                    book.set_level(Side::Ask, level.price, level.quantity);
+                }
+            }
+        });
+        if first_message && self.rebuilding.remove(symbol) {
+            self.store.record_integrity(&self.exchange, IntegrityEvent::Resync);
+        }
+
+        if self.store.check_crossed(&self.exchange, symbol) {
+            log_warn!(symbol = %symbol, "Crossed book, waiting for a full book");
+            self.drop_book(symbol);
+        }
+        true
+    }
+
+    /// Clear a book and skip its updates until the next full book
+    fn drop_book(&mut self, symbol: &str) {
+        self.store.update(&self.exchange, symbol, |book| book.clear());
+        self.dropped.insert(symbol.to_string());
+        self.reconnect = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn full_book(sequence: u64) -> Value {
+        json!({
+            "type": "l2_updates",
+            "symbol": "BTCUSD",
+            "socket_sequence": sequence,
+            "changes": [["buy", "100", "1"], ["sell", "101", "2"]]
+        })
+    }
+
+    fn update(sequence: u64, price: &str, quantity: &str) -> Value {
+        json!({
+            "type": "l2_updates",
+            "symbol": "BTCUSD",
+            "socket_sequence": sequence,
+            "changes": [["buy", price, quantity]]
+        })
+    }
+
+    #[test]
+    fn applies_contiguous_updates() {
+        let store = OrderBookStore::new();
+        let mut sync = GeminiBookSync::new("gemini", store.clone());
+        assert!(sync.on_message(&full_book(0)));
+        assert!(sync.on_message(&update(1, "100.5", "3")));
+
+        let (bid, ask) = store.top_of_book("gemini", "BTCUSD").unwrap();
+        assert_eq!(bid, Level::new(100.5, 3.0));
+        assert_eq!(ask, Level::new(101.0, 2.0));
+        assert_eq!(store.integrity_counters().get("gemini").map(|counters| counters.sequence_gaps), None);
+    }
+
+    #[test]
+    fn drops_books_after_a_sequence_gap_until_reset() {
+        let store = OrderBookStore::new();
+        let mut sync = GeminiBookSync::new("gemini", store.clone());
+        sync.on_message(&full_book(0));
+        sync.on_message(&update(2, "100.5", "3"));
+
+        assert!(store.get("gemini", "BTCUSD").unwrap().is_empty());
+        assert_eq!(store.integrity_counters()["gemini"].sequence_gaps, 1);
+
+        // Diffs are not applied to the dropped book
+        sync.on_message(&update(3, "100.6", "1"));
+        assert!(store.get("gemini", "BTCUSD").unwrap().is_empty());
+
+        // The connection is reopened and the server resends the full book
+        assert!(sync.take_reconnect());
+        assert!(!sync.take_reconnect());
+        sync.reset();
+        sync.on_message(&full_book(0));
+        assert_eq!(store.top_of_book("gemini", "BTCUSD").unwrap().0, Level::new(100.0, 1.0));
+        assert_eq!(store.integrity_counters()["gemini"].resyncs, 1);
+    }
+
+    #[test]
+    fn drops_crossed_books() {
+        let store = OrderBookStore::new();
+        let mut sync = GeminiBookSync::new("gemini", store.clone());
+        sync.on_message(&full_book(0));
+        sync.on_message(&update(1, "102", "1"));
+
+        assert!(store.get("gemini", "BTCUSD").unwrap().is_empty());
+        assert_eq!(store.integrity_counters()["gemini"].crossed_books, 1);
+        assert!(sync.take_reconnect());
+
+        // Later diffs are not patched onto the dropped book
+        sync.on_message(&update(2, "100.2", "1"));
+        assert!(store.get("gemini", "BTCUSD").unwrap().is_empty());
+    }
+}
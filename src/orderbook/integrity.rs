@@ -0,0 +1,30 @@
+use serde::Serialize;
+
+/// Kind of corruption detected by a book maintainer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntegrityEvent {
+    /// A diff did not continue the exchange sequence, or arrived out of order
+    SequenceGap,
+    /// Best bid at or above best ask
+    CrossedBook,
+    /// The book was rebuilt from a fresh snapshot after one of the above
+    Resync,
+}
+
+/// How often the books of one exchange were found corrupt
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct IntegrityCounters {
+    pub sequence_gaps: u64,
+    pub crossed_books: u64,
+    pub resyncs: u64,
+}
+
+impl IntegrityCounters {
+    pub fn record(&mut self, event: IntegrityEvent) {
+        match event {
+            IntegrityEvent::SequenceGap => self.sequence_gaps += 1,
+            IntegrityEvent::CrossedBook => self.crossed_books += 1,
+            IntegrityEvent::Resync => self.resyncs += 1,
+        }
+    }
+}
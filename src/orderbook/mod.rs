@@ -0,0 +1,335 @@
+pub mod binance;
+pub mod bitstamp;
+pub mod coinbase;
+pub mod gemini;
+pub mod integrity;
+pub mod resync;
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use integrity::{IntegrityCounters, IntegrityEvent};
+
+/// Side of the order book
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Bid,
+    Ask,
+}
+
+/// Price wrapper giving `f64` a total order so it can key a `BTreeMap`
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct PriceKey(f64);
+
+impl Eq for PriceKey {}
+
+impl PartialOrd for PriceKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PriceKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// A single aggregated price level
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Level {
+    pub price: f64,
+    pub quantity: f64,
+}
+
+impl Level {
+    pub fn new(price: f64, quantity: f64) -> Self {
+        Level { price, quantity }
+    }
+}
+
+/// In-memory level 2 order book of one symbol on one exchange
+#[derive(Debug, Clone)]
+pub struct OrderBook {
+    pub exchange: String,
+    pub symbol: String,
+    bids: BTreeMap<PriceKey, f64>,
+    asks: BTreeMap<PriceKey, f64>,
+    /// Exchange sequence / update id of the last applied event, 0 if the venue has none
+    pub last_update_id: u64,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl OrderBook {
+    pub fn new(exchange: &str, symbol: &str) -> Self {
+        OrderBook {
+            exchange: exchange.to_string(),
+            symbol: symbol.to_string(),
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+            last_update_id: 0,
+            updated_at: Utc::now(),
+        }
+    }
+
+    /// Replace the whole book with a snapshot
+    pub fn apply_snapshot(&mut self, bids: &[Level], asks: &[Level], update_id: u64) {
+        self.bids.clear();
+        self.asks.clear();
+        for level in bids {
+            self.set_level(Side::Bid, level.price, level.quantity);
+        }
+        for level in asks {
+            self.set_level(Side::Ask, level.price, level.quantity);
+        }
+        self.last_update_id = update_id;
+        self.updated_at = Utc::now();
+    }
+
+    /// Set the absolute quantity of a price level, a zero quantity removes the level.
+    /// Returns `false` and leaves the book untouched for a price that is not a
+    /// positive number or a quantity that is not a number.
+    pub fn set_level(&mut self, side: Side, price: f64, quantity: f64) -> bool {
+        if !price.is_finite() || price <= 0.0 || !quantity.is_finite() {
+            return false;
+        }
+
+        let levels = match side {
+            Side::Bid => &mut self.bids,
+            Side::Ask => &mut self.asks,
+        };
+
+        if quantity <= 0.0 {
+            levels.remove(&PriceKey(price));
+        } else {
+            levels.insert(PriceKey(price), quantity);
+        }
+        self.updated_at = Utc::now();
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.bids.clear();
+        self.asks.clear();
+        self.last_update_id = 0;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bids.is_empty() && self.asks.is_empty()
+    }
+
+    /// Highest bid
+    pub fn best_bid(&self) -> Option<Level> {
+        self.levels(Side::Bid).next()
+    }
+
+    /// Lowest ask
+    pub fn best_ask(&self) -> Option<Level> {
+        self.levels(Side::Ask).next()
+    }
+
+    pub fn mid_price(&self) -> Option<f64> {
+        Some((self.best_bid()?.price + self.best_ask()?.price) / 2.0)
+    }
+
+    pub fn spread(&self) -> Option<f64> {
+        Some(self.best_ask()?.price - self.best_bid()?.price)
+    }
+
+    /// Whether the best bid is at or above the best ask, which a healthy book never is
+    pub fn is_crossed(&self) -> bool {
+        matches!((self.best_bid(), self.best_ask()), (Some(bid), Some(ask)) if bid.price >= ask.price)
+    }
+
+    /// Iterate over the levels of one side, best price first
+    pub fn levels(&self, side: Side) -> Box<dyn Iterator<Item = Level> + '_> {
+        let to_level = |(price, quantity): (&PriceKey, &f64)| Level::new(price.0, *quantity);
+        match side {
+            Side::Bid => Box::new(self.bids.iter().rev().map(to_level)),
+            Side::Ask => Box::new(self.asks.iter().map(to_level)),
+        }
+    }
+
+    /// The `count` best levels of one side
+    pub fn depth(&self, side: Side, count: usize) -> Vec<Level> {
+        self.levels(side).take(count).collect()
+    }
+
+    /// Total quantity available on one side at prices at least as good as `limit_price`
+    pub fn cumulative_quantity(&self, side: Side, limit_price: f64) -> f64 {
+        self.levels(side)
+            .take_while(|level| match side {
+                Side::Bid => level.price >= limit_price,
+                Side::Ask => level.price <= limit_price,
+            })
+            .map(|level| level.quantity)
+            .sum()
+    }
+
+    /// Total quote notional available on one side at prices at least as good as `limit_price`
+    pub fn cumulative_notional(&self, side: Side, limit_price: f64) -> f64 {
+        self.levels(side)
+            .take_while(|level| match side {
+                Side::Bid => level.price >= limit_price,
+                Side::Ask => level.price <= limit_price,
+            })
+            .map(|level| level.price * level.quantity)
+            .sum()
+    }
+}
+
+/// Thread-safe registry of order books keyed by (exchange, symbol), shared
+/// between the connectors that maintain the books and the consumers querying them.
+#[derive(Debug, Clone, Default)]
+pub struct OrderBookStore {
+    books: Arc<RwLock<HashMap<(String, String), OrderBook>>>,
+    integrity: Arc<RwLock<HashMap<String, IntegrityCounters>>>,
+}
+
+impl OrderBookStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mutate the book of a symbol, creating it on first use
+    pub fn update<F, R>(&self, exchange: &str, symbol: &str, f: F) -> R
+    where
+        F: FnOnce(&mut OrderBook) -> R,
+    {
+        let mut books = self.books.write().unwrap();
+        let book = books
+            .entry((exchange.to_string(), symbol.to_string()))
+            .or_insert_with(|| OrderBook::new(exchange, symbol));
+        f(book)
+    }
+
+    /// Snapshot copy of a book
+    pub fn get(&self, exchange: &str, symbol: &str) -> Option<OrderBook> {
+        self.books
+            .read()
+            .unwrap()
+            .get(&(exchange.to_string(), symbol.to_string()))
+            .cloned()
+    }
+
+    /// Best bid and best ask of a book
+    pub fn top_of_book(&self, exchange: &str, symbol: &str) -> Option<(Level, Level)> {
+        let books = self.books.read().unwrap();
+        let book = books.get(&(exchange.to_string(), symbol.to_string()))?;
+        Some((book.best_bid()?, book.best_ask()?))
+    }
+
+    /// (exchange, symbol) pairs of every known book
+    pub fn keys(&self) -> Vec<(String, String)> {
+        self.books.read().unwrap().keys().cloned().collect()
+    }
+
+    pub fn remove(&self, exchange: &str, symbol: &str) {
+        self.books
+            .write()
+            .unwrap()
+            .remove(&(exchange.to_string(), symbol.to_string()));
+    }
+
+    /// Count an integrity problem detected on one of the exchange's books
+    pub fn record_integrity(&self, exchange: &str, event: IntegrityEvent) {
+        self.integrity
+            .write()
+            .unwrap()
+            .entry(exchange.to_string())
+            .or_default()
+            .record(event);
+    }
+
+    /// Integrity counters per exchange
+    pub fn integrity_counters(&self) -> HashMap<String, IntegrityCounters> {
+        self.integrity.read().unwrap().clone()
+    }
+
+    /// Check a book for a crossed top of book, counting it and clearing the
+    /// book so it is not used until the maintainer resyncs it
+    pub fn check_crossed(&self, exchange: &str, symbol: &str) -> bool {
+        let crossed = self.update(exchange, symbol, |book| {
+            let crossed = book.is_crossed();
+            if crossed {
+                book.clear();
+            }
+            crossed
+        });
+
+        if crossed {
+            self.record_integrity(exchange, IntegrityEvent::CrossedBook);
+        }
+        crossed
+    }
+}
+
+/// Parse `[["price", "quantity"], ...]` arrays as published by most exchanges
+pub(crate) fn parse_levels(value: &serde_json::Value) -> Vec<Level> {
+    value
+        .as_array()
+        .map(|levels| {
+            levels
+                .iter()
+                .filter_map(|level| {
+                    let price = parse_number(&level[0])?;
+                    let quantity = parse_number(&level[1])?;
+                    Some(Level::new(price, quantity))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Exchanges publish numbers either as JSON strings or as JSON numbers
+pub(crate) fn parse_number(value: &serde_json::Value) -> Option<f64> {
+    match value {
+        serde_json::Value::String(s) => s.parse::<f64>().ok(),
+        other => other.as_f64(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_level_rejects_invalid_prices_and_quantities() {
+        let mut book = OrderBook::new("test", "BTCUSD");
+        assert!(!book.set_level(Side::Bid, 0.0, 1.0));
+        assert!(!book.set_level(Side::Bid, -1.0, 1.0));
+        assert!(!book.set_level(Side::Ask, f64::NAN, 1.0));
+        assert!(!book.set_level(Side::Ask, f64::INFINITY, 1.0));
+        assert!(!book.set_level(Side::Ask, 100.0, f64::NAN));
+        assert!(book.is_empty());
+
+        assert!(book.set_level(Side::Bid, 99.0, 1.0));
+        assert!(book.set_level(Side::Ask, 101.0, 2.0));
+        assert_eq!(book.best_bid(), Some(Level::new(99.0, 1.0)));
+        assert_eq!(book.best_ask(), Some(Level::new(101.0, 2.0)));
+
+        // A zero quantity removes the level
+        assert!(book.set_level(Side::Bid, 99.0, 0.0));
+        assert_eq!(book.best_bid(), None);
+    }
+
+    #[test]
+    fn levels_are_sorted_best_first() {
+        let mut book = OrderBook::new("test", "BTCUSD");
+        book.apply_snapshot(
+            &[Level::new(98.0, 1.0), Level::new(99.0, 2.0)],
+            &[Level::new(102.0, 1.0), Level::new(101.0, 3.0)],
+            7,
+        );
+        assert_eq!(book.depth(Side::Bid, 2), vec![Level::new(99.0, 2.0), Level::new(98.0, 1.0)]);
+        assert_eq!(book.depth(Side::Ask, 2), vec![Level::new(101.0, 3.0), Level::new(102.0, 1.0)]);
+        assert_eq!(book.cumulative_quantity(Side::Ask, 101.5), 3.0);
+        assert_eq!(book.spread(), Some(2.0));
+        assert!(!book.is_crossed());
+    }
+}
@@ -0,0 +1,92 @@
+use std::collections::HashSet;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::log_error;
+
+/// Rebuilds books from REST snapshots on a background thread, so the websocket
+/// reader keeps applying the other symbols of its connection while a snapshot
+/// is fetched. The thread exits once every handle is dropped.
+#[derive(Clone)]
+pub struct BackgroundResync {
+    requests: Sender<String>,
+    /// Symbols requested and not rebuilt yet
+    pending: Arc<Mutex<HashSet<String>>>,
+}
+
+impl BackgroundResync {
+    /// Start the worker, `fetch` rebuilds the book of the given symbol
+    pub fn spawn<F>(fetch: F) -> Self
+    where
+        F: Fn(&str) -> Result<(), String> + Send + 'static,
+    {
+        let (requests, receiver) = mpsc::channel::<String>();
+        let pending = Arc::new(Mutex::new(HashSet::new()));
+
+        let worker_pending = Arc::clone(&pending);
+        thread::spawn(move || {
+            for symbol in receiver {
+                if let Err(e) = fetch(&symbol) {
+                    log_error!(symbol = %symbol, error = %e, "Resync failed");
+                }
+                worker_pending.lock().unwrap().remove(&symbol);
+            }
+        });
+
+        BackgroundResync { requests, pending }
+    }
+
+    /// Queue a rebuild of the book of `symbol`, returns `false` if one is already pending
+    pub fn request(&self, symbol: &str) -> bool {
+        if !self.pending.lock().unwrap().insert(symbol.to_string()) {
+            return false;
+        }
+        if self.requests.send(symbol.to_string()).is_err() {
+            self.pending.lock().unwrap().remove(symbol);
+            return false;
+        }
+        true
+    }
+
+    /// Whether the book of `symbol` is waiting for its snapshot, updates are then dropped
+    pub fn is_pending(&self, symbol: &str) -> bool {
+        self.pending.lock().unwrap().contains(symbol)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn rebuilds_each_pending_symbol_once_off_the_caller_thread() {
+        let (started, started_rx) = mpsc::channel();
+        let (release, release_rx) = mpsc::channel::<()>();
+        let resync = BackgroundResync::spawn(move |symbol| {
+            started.send(symbol.to_string()).unwrap();
+            release_rx.recv().unwrap();
+            Ok(())
+        });
+
+        assert!(resync.request("BTCUSD"));
+        assert!(!resync.request("BTCUSD"));
+        assert_eq!(started_rx.recv_timeout(Duration::from_secs(1)).unwrap(), "BTCUSD");
+        assert!(resync.is_pending("BTCUSD"));
+        assert!(!resync.is_pending("ETHUSD"));
+
+        release.send(()).unwrap();
+        for _ in 0..100 {
+            if !resync.is_pending("BTCUSD") {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(!resync.is_pending("BTCUSD"));
+        assert!(resync.request("BTCUSD"));
+        release.send(()).unwrap();
+    }
+}
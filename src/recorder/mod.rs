@@ -0,0 +1,257 @@
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{log_error, log_info};
+
+/// Extension of the recording files
+pub const RECORDING_EXTENSION: &str = "ndjson.gz";
+
+/// Interval at which buffered frames are flushed to disk, bounding what a crash can lose
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Origin of a recorded frame
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FrameKind {
+    /// Text frame read from the WebSocket
+    #[default]
+    WebSocket,
+    /// REST snapshot fetched by the connector, `frame` is `{"symbol": ..., "data": <snapshot>}`
+    Snapshot,
+}
+
+/// One raw frame as received from an exchange
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedFrame {
+    pub exchange: String,
+    /// Identifies one WebSocket connection, a reconnect gets a new id
+    pub connection_id: String,
+    pub received_at: DateTime<Utc>,
+    #[serde(default)]
+    pub kind: FrameKind,
+    pub frame: String,
+}
+
+struct RecordingFile {
+    path: PathBuf,
+    encoder: GzEncoder<BufWriter<File>>,
+    opened_at: Instant,
+    last_flush: Instant,
+    /// Uncompressed bytes written
+    written: u64,
+}
+
+impl RecordingFile {
+    fn create(directory: &Path) -> io::Result<Self> {
+        let now = Utc::now();
+        let path = directory.join(format!("frames-{}.{}", now.format("%Y%m%dT%H%M%S%.3fZ"), RECORDING_EXTENSION));
+        let file = File::create(&path)?;
+        log_info!(path = %path.display(), "Recording frames");
+
+        Ok(RecordingFile {
+            path,
+            encoder: GzEncoder::new(BufWriter::new(file), Compression::default()),
+            opened_at: Instant::now(),
+            last_flush: Instant::now(),
+            written: 0,
+        })
+    }
+
+    fn finish(self) -> io::Result<()> {
+        self.encoder.finish()?.flush()
+    }
+}
+
+struct RecorderState {
+    directory: PathBuf,
+    file: Option<RecordingFile>,
+}
+
+/// Writes every raw frame received by the connectors, with its exchange,
+/// connection id and receive timestamp, to gzip compressed newline-delimited
+/// JSON files. A new file is started when the current one reaches
+/// `max_file_bytes` of uncompressed frames or is older than `rotation_interval`.
+#[derive(Clone)]
+pub struct FrameRecorder {
+    state: Arc<Mutex<RecorderState>>,
+    max_file_bytes: u64,
+    rotation_interval: Duration,
+    next_connection: Arc<AtomicU64>,
+}
+
+impl FrameRecorder {
+    /// Record into `directory`, creating it if needed
+    pub fn new(directory: impl AsRef<Path>) -> io::Result<Self> {
+        let directory = directory.as_ref().to_path_buf();
+        fs::create_dir_all(&directory)?;
+
+        Ok(FrameRecorder {
+            state: Arc::new(Mutex::new(RecorderState { directory, file: None })),
+            max_file_bytes: 256 * 1024 * 1024,
+            rotation_interval: Duration::from_secs(3600),
+            next_connection: Arc::new(AtomicU64::new(1)),
+        })
+    }
+
+    /// Rotate once this many uncompressed bytes were written to a file
+    pub fn with_max_file_bytes(mut self, max_file_bytes: u64) -> Self {
+        self.max_file_bytes = max_file_bytes;
+        self
+    }
+
+    /// Rotate files older than this
+    pub fn with_rotation_interval(mut self, rotation_interval: Duration) -> Self {
+        self.rotation_interval = rotation_interval;
+        self
+    }
+
+    /// Recorder of a new connection to `exchange`
+    pub fn connection(&self, exchange: &str) -> ConnectionRecorder {
+        ConnectionRecorder {
+            recorder: self.clone(),
+            exchange: exchange.to_string(),
+            connection_id: self.next_connection_id(exchange),
+        }
+    }
+
+    /// Append a frame to the current file, errors are logged and the frame dropped
+    pub fn record(&self, frame: &RecordedFrame) {
+        if let Err(e) = self.write(frame) {
+            log_error!(exchange = %frame.exchange, error = %e, "Cannot record frame");
+        }
+    }
+
+    /// Finish the current file so that it is a complete gzip stream
+    pub fn close(&self) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(file) = state.file.take() {
+            let path = file.path.clone();
+            if let Err(e) = file.finish() {
+                log_error!(path = %path.display(), error = %e, "Cannot finish recording");
+            }
+        }
+    }
+
+    fn write(&self, frame: &RecordedFrame) -> io::Result<()> {
+        let mut line = serde_json::to_vec(frame)?;
+        line.push(b'\n');
+
+        let mut state = self.state.lock().unwrap();
+        let rotate = state.file.as_ref().is_some_and(|file| {
+            file.written >= self.max_file_bytes || file.opened_at.elapsed() >= self.rotation_interval
+        });
+        if rotate && let Some(file) = state.file.take() {
+            file.finish()?;
+        }
+        if state.file.is_none() {
+            state.file = Some(RecordingFile::create(&state.directory)?);
+        }
+
+        let file = state.file.as_mut().unwrap();
+        file.encoder.write_all(&line)?;
+        file.written += line.len() as u64;
+        if file.last_flush.elapsed() >= FLUSH_INTERVAL {
+            file.encoder.flush()?;
+            file.last_flush = Instant::now();
+        }
+        Ok(())
+    }
+
+    fn next_connection_id(&self, exchange: &str) -> String {
+        let sequence = self.next_connection.fetch_add(1, Ordering::Relaxed);
+        format!("{}-{}-{}", exchange, Utc::now().timestamp_millis(), sequence)
+    }
+}
+
+impl std::fmt::Debug for FrameRecorder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FrameRecorder")
+            .field("directory", &self.state.lock().unwrap().directory)
+            .field("max_file_bytes", &self.max_file_bytes)
+            .field("rotation_interval", &self.rotation_interval)
+            .finish()
+    }
+}
+
+impl Drop for RecorderState {
+    fn drop(&mut self) {
+        if let Some(file) = self.file.take() {
+            let _ = file.finish();
+        }
+    }
+}
+
+/// Records the frames of one WebSocket connection
+#[derive(Debug, Clone)]
+pub struct ConnectionRecorder {
+    recorder: FrameRecorder,
+    exchange: String,
+    connection_id: String,
+}
+
+impl ConnectionRecorder {
+    pub fn connection_id(&self) -> &str {
+        &self.connection_id
+    }
+
+    pub fn record(&self, frame: &str) {
+        self.record_kind(FrameKind::WebSocket, frame.to_string());
+    }
+
+    /// Record a REST snapshot the connector fetched for `symbol`
+    pub fn record_snapshot(&self, symbol: &str, snapshot: &Value) {
+        self.record_kind(FrameKind::Snapshot, json!({ "symbol": symbol, "data": snapshot }).to_string());
+    }
+
+    fn record_kind(&self, kind: FrameKind, frame: String) {
+        self.recorder.record(&RecordedFrame {
+            exchange: self.exchange.clone(),
+            connection_id: self.connection_id.clone(),
+            received_at: Utc::now(),
+            kind,
+            frame,
+        });
+    }
+
+    /// Switch to a new connection id after the connector reconnected
+    pub fn reconnected(&mut self) {
+        self.connection_id = self.recorder.next_connection_id(&self.exchange);
+    }
+}
+
+/// Recording files of a directory, oldest first, or the file itself if `path` is a file
+pub fn recording_files(path: impl AsRef<Path>) -> io::Result<Vec<PathBuf>> {
+    let path = path.as_ref();
+    if path.is_file() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let mut files: Vec<PathBuf> = fs::read_dir(path)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|file| file.to_string_lossy().ends_with(RECORDING_EXTENSION))
+        .collect();
+    // File names start with their creation time
+    files.sort();
+    Ok(files)
+}
+
+/// Iterate over the frames of a recording file. A file whose recorder did not
+/// shut down cleanly ends with a truncated line or gzip member, which ends the
+/// iteration with an error after the last complete frame.
+pub fn read_frames(path: impl AsRef<Path>) -> io::Result<impl Iterator<Item = io::Result<RecordedFrame>>> {
+    let file = File::open(path)?;
+    let reader = BufReader::new(MultiGzDecoder::new(BufReader::new(file)));
+    Ok(reader
+        .lines()
+        .filter(|line| line.as_ref().map_or(true, |line| !line.trim().is_empty()))
+        .map(|line| line.and_then(|line| serde_json::from_str(&line).map_err(io::Error::from))))
+}
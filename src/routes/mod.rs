@@ -1,4 +1,5 @@
 use axum::{
+    middleware,
     routing::get,
     Router,
 };
@@ -11,13 +12,38 @@ pub fn create_router(state: Arc<AppState>) -> Router {
         .route("/health", get(handlers::health))
         .route("/hello", get(handlers::hello))
         .route("/info", get(handlers::app_info))
+        .route("/metrics", get(handlers::get_metrics))
         .nest("/api/v1", api_v1_routes())
+        .nest("/api/v1/admin", admin_routes(state.clone()))
+        // Applied to every route above
+        .layer(middleware::from_fn(handlers::record_request_duration))
         .with_state(state)
 }
 
 fn api_v1_routes() -> Router<Arc<AppState>> {
     Router::new()
+        .route("/orderbooks/integrity", get(handlers::get_orderbook_integrity))
+        .route("/ticks/rejections", get(handlers::get_tick_rejections))
+        .route("/arbitrage", get(handlers::get_arbitrage_opportunities))
+        .route("/arbitrage/history", get(handlers::get_arbitrage_history))
+        .route("/markets", get(handlers::get_markets))
+        .route("/markets/{symbol}/history", get(handlers::get_market_history))
+        .route("/candles", get(handlers::get_candles))
+        .route("/export/opportunities", get(handlers::export_opportunities))
+        .route("/export/ticks", get(handlers::export_ticks))
+        .route("/stablecoins", get(handlers::get_stablecoins))
+        .route("/stream", get(handlers::stream_events))
         // Future arbitrage detection endpoints will go here
-        // .route("/arbitrage", get(handlers::get_arbitrage_opportunities))
-        // .route("/markets", get(handlers::get_markets))
+}
+
+fn admin_routes(state: Arc<AppState>) -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/alerts", get(handlers::list_alert_rules).post(handlers::create_alert_rule))
+        .route(
+            "/alerts/{id}",
+            get(handlers::get_alert_rule)
+                .put(handlers::put_alert_rule)
+                .delete(handlers::delete_alert_rule),
+        )
+        .route_layer(middleware::from_fn_with_state(state, handlers::require_admin_token))
 }
\ No newline at end of file
@@ -1,2 +1,3 @@
 pub mod socket_container;
-pub mod socket_consumer;
\ No newline at end of file
+pub mod socket_consumer;
+pub mod tick_filter;
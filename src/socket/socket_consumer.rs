@@ -1,54 +1,304 @@
 use std::collections::HashMap;
-use std::sync::mpsc::{self, Receiver};
+use std::sync::mpsc::{self, Receiver, Sender};
 use std::sync::Arc;
+use std::time::Instant;
 
-use crate::models::SymbolMessage;
-use crate::{log_error, log_info};
-use crate::socket::socket_container::binance_container::BinanceContainer;
+use crate::candles::CandleAggregator;
+use crate::detector::{
+    depeg::DepegMonitor, lifecycle::OpportunityTracker, negative_cycle::NegativeCycleDetector,
+    triangular::TriangularDetector, ArbitrageDetector,
+};
+use crate::events::EventBus;
+use crate::metrics::metrics;
+use crate::models::{MultiLegOpportunity, StreamEvent, SymbolMessage};
+use crate::{log_debug, log_error, log_info};
+use crate::socket::socket_container::socket_container::ISocketContainer;
+use crate::socket::tick_filter::TickFilter;
+use crate::storage::quotes::QuoteHistory;
 
+/// Drives every registered exchange connector and collects their price
+/// updates from a single shared channel.
 pub struct SocketConsumer {
-    symbols: Vec<String>, // ["btcusdt", ...]
-    binance_container: BinanceContainer,
-    binance_symbol_map: HashMap<String, f64>,
-    binance_receiver: Receiver<SymbolMessage>
+    containers: Vec<Box<dyn ISocketContainer>>,
+    sender: Arc<Sender<SymbolMessage>>,
+    receiver: Receiver<SymbolMessage>,
+    symbol_map: HashMap<(String, String), f64>, // (exchange, symbol) => last price
+    detector: Option<ArbitrageDetector>,
+    triangular_detectors: Vec<TriangularDetector>,
+    cycle_detector: Option<NegativeCycleDetector>,
+    depeg_monitor: Option<DepegMonitor>,
+    tracker: Option<OpportunityTracker>,
+    tick_filter: Option<TickFilter>,
+    quote_history: Option<QuoteHistory>,
+    candles: Option<CandleAggregator>,
+    events: Option<EventBus>,
 }
 
 impl SocketConsumer {
     pub fn new() -> Self {
-        let (binance_sender, binance_receiver) = mpsc::channel();
-        let binance_sender = Arc::new(binance_sender);
+        let (sender, receiver) = mpsc::channel();
 
-        SocketConsumer { 
-            symbols: vec![],
-            binance_symbol_map: HashMap::new(),
-            binance_container: BinanceContainer::new_with_sender(binance_sender),
-            binance_receiver
+        SocketConsumer {
+            containers: vec![],
+            sender: Arc::new(sender),
+            receiver,
+            symbol_map: HashMap::new(),
+            detector: None,
+            triangular_detectors: vec![],
+            cycle_detector: None,
+            depeg_monitor: None,
+            tracker: None,
+            tick_filter: None,
+            quote_history: None,
+            candles: None,
+            events: None,
         }
     }
 
-    pub fn add_symbol(&mut self, symbol: String) {
-        self.symbols.push(symbol);
+    /// Run the detector for the updated symbol on every received message
+    pub fn with_detector(mut self, detector: ArbitrageDetector) -> Self {
+        self.detector = Some(detector);
+        self
     }
 
-    pub fn start_binance_price_monitoring(&mut self) -> Result<(), String> {
-        for symbol in &self.symbols {
-            // Start monitoring in a separate thread or task
-            match self.binance_container.add_symbol(symbol) {
+    /// Evaluate the triangles of the detector's venue on every message from that venue
+    pub fn with_triangular_detector(mut self, detector: TriangularDetector) -> Self {
+        self.triangular_detectors.push(detector);
+        self
+    }
+
+    /// Refresh the updated market in the currency graph and look for negative cycles on every message
+    pub fn with_cycle_detector(mut self, detector: NegativeCycleDetector) -> Self {
+        self.cycle_detector = Some(detector);
+        self
+    }
+
+    /// Feed every price to the stablecoin peg monitor
+    pub fn with_depeg_monitor(mut self, monitor: DepegMonitor) -> Self {
+        self.depeg_monitor = Some(monitor);
+        self
+    }
+
+    /// Follow the detector's opportunities from open to close
+    pub fn with_opportunity_tracker(mut self, tracker: OpportunityTracker) -> Self {
+        self.tracker = Some(tracker);
+        self
+    }
+
+    /// Drop bad ticks before they reach the monitors and detectors
+    pub fn with_tick_filter(mut self, tick_filter: TickFilter) -> Self {
+        self.tick_filter = Some(tick_filter);
+        self
+    }
+
+    /// Store every accepted tick with the top of book of its market
+    pub fn with_quote_history(mut self, history: QuoteHistory) -> Self {
+        self.quote_history = Some(history);
+        self
+    }
+
+    /// Aggregate every accepted tick into OHLCV candles
+    pub fn with_candle_aggregator(mut self, candles: CandleAggregator) -> Self {
+        self.candles = Some(candles);
+        self
+    }
+
+    /// Publish the triangular and negative cycle opportunities to the streaming API
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Channel that connectors should publish to, see `new_with_sender` on each container
+    pub fn sender(&self) -> Arc<Sender<SymbolMessage>> {
+        Arc::clone(&self.sender)
+    }
+
+    pub fn add_container(&mut self, container: Box<dyn ISocketContainer>) {
+        self.containers.push(container);
+    }
+
+    /// Last known price of a symbol on the given exchange
+    pub fn last_price(&self, exchange: &str, symbol: &str) -> Option<f64> {
+        self.symbol_map.get(&(exchange.to_string(), symbol.to_string())).copied()
+    }
+
+    pub fn start_price_monitoring(&mut self) -> Result<(), String> {
+        for container in &mut self.containers {
+            // Each container spawns its own streaming thread(s)
+            match container.start_monitoring() {
                 Ok(_) => {
-                    log_info!("Start monitoring {symbol}");
+                    log_info!(exchange = container.exchange(), symbols = %container.symbols().join(","), "Start monitoring");
                 }
                 Err(e) => {
-                    log_error!("Error monitoring {symbol}: {}", e);
+                    log_error!(exchange = container.exchange(), error = %e, "Error monitoring");
                 }
             }
         }
 
         // Receive messages in the main thread
-        for message in self.binance_receiver.iter() {
-            println!("Received: {} => {}", message.symbol, message.price);
-
-            self.binance_symbol_map.insert(message.symbol.to_owned(), message.price);
+        while let Ok(message) = self.receiver.recv() {
+            self.process_message(message);
         }
         Ok(())
     }
-}
\ No newline at end of file
+
+    /// Run one price update through the filter, the monitors and the detectors
+    pub fn process_message(&mut self, message: SymbolMessage) {
+        log_debug!(exchange = %message.exchange, symbol = %message.symbol, price = message.price, "Received");
+        let _timer = metrics().detection_duration.start_timer();
+        // Ticks stamped ahead of the local clock are skew, not latency
+        let exchange_latency = message.exchange_time.and_then(|exchange_time| (message.received_at - exchange_time).to_std().ok());
+        if let Some(latency) = exchange_latency {
+            metrics().exchange_latency.with_label_values(&[&message.exchange]).observe(latency.as_secs_f64());
+        }
+        metrics().feed_latency.with_label_values(&[&message.exchange]).observe(message.read_at.elapsed().as_secs_f64());
+
+        if let Some(filter) = &self.tick_filter
+            && filter.check(&message).is_err()
+        {
+            return;
+        }
+
+        if let Some(history) = &self.quote_history {
+            history.record(&message);
+        }
+
+        if let Some(candles) = &self.candles {
+            candles.on_tick(&message);
+        }
+
+        if let Some(monitor) = &self.depeg_monitor {
+            monitor.on_price(&message.exchange, &message.symbol, message.price);
+        }
+
+        if let Some(detector) = &self.detector {
+            if let Some(converter) = detector.quote_converter() {
+                converter.on_price(&message.exchange, &message.symbol, message.price, message.received_at);
+            }
+            let opportunities = detector.detect_at(&message.symbol, message.received_at);
+            if !opportunities.is_empty() {
+                let detected_at = Instant::now();
+                metrics()
+                    .opportunity_latency
+                    .with_label_values(&[&message.exchange])
+                    .observe((detected_at - message.read_at).as_secs_f64());
+                log_debug!(
+                    exchange = %message.exchange,
+                    symbol = %message.symbol,
+                    exchange_to_receive = ?exchange_latency,
+                    receive_to_normalize = ?(message.normalized_at - message.read_at),
+                    normalize_to_detect = ?(detected_at - message.normalized_at),
+                    "Latency"
+                );
+            }
+            for opportunity in &opportunities {
+                metrics()
+                    .opportunities_detected
+                    .with_label_values(&[&opportunity.symbol, &opportunity.buy_exchange, &opportunity.sell_exchange])
+                    .inc();
+                log_debug!(
+                    symbol = %opportunity.symbol,
+                    quantity = opportunity.quantity,
+                    buy_symbol = %opportunity.buy_symbol,
+                    buy_exchange = %opportunity.buy_exchange,
+                    buy_vwap = opportunity.buy_vwap,
+                    sell_symbol = %opportunity.sell_symbol,
+                    sell_exchange = %opportunity.sell_exchange,
+                    sell_vwap = opportunity.sell_vwap,
+                    expected_profit = opportunity.expected_profit,
+                    profit_percentage = opportunity.profit_percentage,
+                    "Opportunity"
+                );
+            }
+            if let Some(tracker) = &self.tracker {
+                tracker.update_at(&detector.normalized_symbol(&message.symbol), opportunities, message.received_at);
+            }
+        }
+
+        for detector in self.triangular_detectors.iter().filter(|detector| detector.exchange() == message.exchange) {
+            for opportunity in detector.detect_for_symbol(&message.symbol, message.received_at) {
+                Self::log_multi_leg(&opportunity);
+                if let Some(events) = &self.events {
+                    events.publish(StreamEvent::MultiLegOpportunity(Box::new(opportunity)));
+                }
+            }
+        }
+
+        if let Some(detector) = self.cycle_detector.as_mut() {
+            detector.refresh_market(&message.exchange, &message.symbol);
+            for opportunity in detector.detect_at(message.received_at) {
+                Self::log_multi_leg(&opportunity);
+                if let Some(events) = &self.events {
+                    events.publish(StreamEvent::MultiLegOpportunity(Box::new(opportunity)));
+                }
+            }
+        }
+
+        self.symbol_map.insert((message.exchange, message.symbol), message.price);
+    }
+
+    fn log_multi_leg(opportunity: &MultiLegOpportunity) {
+        let route: Vec<String> = opportunity.legs
+            .iter()
+            .map(|leg| match &leg.destination_exchange {
+                Some(destination) => format!("transfer {} {} -> {}", leg.symbol, leg.exchange, destination),
+                None => format!("{:?} {} on {} @ {:.8}", leg.side, leg.symbol, leg.exchange, leg.price),
+            })
+            .collect();
+        log_info!(
+            kind = ?opportunity.kind,
+            start_currency = %opportunity.start_currency,
+            route = %route.join(" -> "),
+            profit_percentage = opportunity.profit_percentage,
+            "Multi-leg opportunity"
+        );
+    }
+}
+
+impl Default for SocketConsumer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::detector::FeeSchedule;
+    use crate::models::MultiLegKind;
+    use crate::orderbook::{Level, OrderBookStore};
+
+    #[test]
+    fn publishes_the_multi_leg_opportunities() {
+        let store = OrderBookStore::new();
+        let set_top = |symbol: &str, bid: f64, ask: f64| {
+            store.update("binance", symbol, |book| {
+                book.apply_snapshot(&[Level::new(bid, 10.0)], &[Level::new(ask, 10.0)], 1)
+            });
+        };
+        set_top("BTCUSDT", 49_990.0, 50_000.0);
+        set_top("ETHBTC", 0.0599, 0.06);
+        set_top("ETHUSDT", 3_100.0, 3_101.0);
+        let symbols = ["BTCUSDT", "ETHBTC", "ETHUSDT"].map(str::to_string);
+        let events = EventBus::new();
+        let mut receiver = events.subscribe();
+        let mut consumer = SocketConsumer::new()
+            .with_triangular_detector(TriangularDetector::new("binance", &symbols, store).with_fees(FeeSchedule::new(0.001)))
+            .with_events(events);
+
+        let message = SymbolMessage::new("binance", "ETHBTC".to_string(), 0.06);
+        let received_at = message.received_at;
+        consumer.process_message(message);
+
+        let Ok(StreamEvent::MultiLegOpportunity(opportunity)) = receiver.try_recv() else {
+            panic!("expected a multi-leg opportunity");
+        };
+        assert_eq!(opportunity.kind, MultiLegKind::Triangular);
+        assert_eq!(opportunity.timestamp, received_at);
+        assert!(receiver.try_recv().is_err());
+
+        consumer.process_message(SymbolMessage::new("kraken", "ETHBTC".to_string(), 0.06));
+        assert!(receiver.try_recv().is_err());
+    }
+}
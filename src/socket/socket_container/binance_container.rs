@@ -1,26 +1,42 @@
-use std::{collections::HashMap, net::TcpStream, sync::{mpsc::Receiver, Arc}, thread::{self, JoinHandle}};
+use std::{collections::HashMap, net::TcpStream, sync::Arc, thread::{self, JoinHandle}, time::Instant};
 use url::Url;
 use tungstenite::{connect, stream::MaybeTlsStream, WebSocket, Message};
+use chrono::DateTime;
 use serde_json::Value;
+use tracing::Span;
 use crate::{log_debug, log_error, log_info, log_warn, models::SymbolMessage};
+use crate::metrics::metrics;
+use crate::recorder::{ConnectionRecorder, FrameRecorder};
+use crate::orderbook::{binance::{BinanceBookSync, DepthUpdate}, OrderBookStore};
+use crate::socket::socket_container::socket_container::{
+    connection_span, connector_span, is_read_timeout, set_read_timeout, symbol_span, ISocketContainer, READ_TIMEOUT,
+};
 use std::sync::mpsc::{Sender};
 use std::sync::{atomic::AtomicBool, atomic::Ordering};
 
 pub struct BinanceContainer {
     sockets: HashMap<String, WebSocket<MaybeTlsStream<TcpStream>>>,
     sender: Arc<Sender<SymbolMessage>>,
-    sender: Receiver<SymbolMessage>,
     socket_threads: HashMap<String, JoinHandle<Result<(), String>>>,
     symbols: Vec<String>,
     shutdown: Arc<AtomicBool>,
     max_reconnect_attempts: u32,
+    order_books: Option<OrderBookStore>,
+    recorder: Option<FrameRecorder>,
+    /// Parent span of the connections
+    span: Span,
 }
 
 impl BinanceContainer {
+    pub const EXCHANGE: &'static str = "binance";
+
     pub fn new() -> Self {
         let (sender, _receiver) = std::sync::mpsc::channel();
-        let sender = Arc::new(sender);
+        Self::new_with_sender(Arc::new(sender))
+    }
 
+    /// Create a container that publishes every price update to the given channel
+    pub fn new_with_sender(sender: Arc<Sender<SymbolMessage>>) -> Self {
         BinanceContainer { 
             sockets: HashMap::new(),
             sender,
@@ -28,17 +44,38 @@ impl BinanceContainer {
             symbols: Vec::new(),
             shutdown: Arc::new(AtomicBool::new(false)),
             max_reconnect_attempts: 5,
+            order_books: None,
+            recorder: None,
+            span: connector_span(Self::EXCHANGE),
         }
     }
 
-    pub fn add_symbol(&mut self, symbol: &str) -> Result<(), String> {
+    /// Also stream `@depth` diffs and maintain the L2 book of every symbol in the given store
+    pub fn with_order_books(mut self, order_books: OrderBookStore) -> Self {
+        self.order_books = Some(order_books);
+        self
+    }
+
+    /// Record every raw frame received on the connection(s)
+    pub fn with_recorder(mut self, recorder: FrameRecorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    fn endpoint(symbol: &str, with_depth: bool) -> String {
+        if with_depth {
+            format!("wss://stream.binance.com:9443/stream?streams={0}@ticker/{0}@depth@100ms", symbol)
+        } else {
+            format!("wss://stream.binance.com:9443/ws/{}@ticker", symbol)
+        }
+    }
+
+    /// Register a Binance symbol such as `btcusdt`, streamed from the next `start_monitoring`
+    pub fn add_symbol(&mut self, symbol: &str) {
         // Add symbol to tracking list if not already present
         if !self.symbols.contains(&symbol.to_string()) {
             self.symbols.push(symbol.to_string());
         }
-        
-        self.init_socket_connection(symbol);
-        return self.get_data(symbol);
     }
 
     fn init_socket_connection(&mut self, symbol: &str) {
@@ -47,20 +84,20 @@ impl BinanceContainer {
             return;
         }
 
-        let endpoint = format!("wss://stream.binance.com:9443/ws/{}@ticker", symbol);
+        let _connector = self.span.clone().entered();
+        let endpoint = Self::endpoint(symbol, self.order_books.is_some());
         match connect(Url::parse(&endpoint).unwrap()) {
             Ok((socket, _response)) => {
+                set_read_timeout(&socket, READ_TIMEOUT);
                 self.sockets.insert(symbol.to_owned(), socket);
-                let success_message = format!("[BinanceContainer] successfully connected to socket for symbol ({})", symbol);
-                log_info!("{}", success_message);
+                log_info!(symbol = %symbol, "Connected");
             }
             Err(err) => {
-                let error_log = format!("Cannot connect to Binance Websocket for symbol ({}), details: {}", symbol, err);
-                log_error!("{}", error_log); 
+                log_error!(symbol = %symbol, error = %err, "Cannot connect");
             }
         };
         
-        log_info!("Finished connected to Binance WebStocke");
+        log_info!("Finished connecting to Binance WebSocket");
     }
     
     pub fn disconnect(&self) {
@@ -73,134 +110,157 @@ impl BinanceContainer {
         self.sockets.contains_key(symbol)
     }
 
-    /// Start monitoring multiple cryptocurrency symbols
-    pub async fn start_monitoring(&mut self, symbols: Vec<String>) -> Result<(), String> {
-        for symbol in symbols {
+    /// Start monitoring every registered symbol that is not streaming yet, one connection each.
+    /// A symbol that cannot connect does not prevent the others from streaming.
+    pub fn start_monitoring(&mut self) -> Result<(), String> {
+        let pending: Vec<String> = self.symbols
+            .iter()
+            .filter(|symbol| !self.socket_threads.contains_key(*symbol))
+            .cloned()
+            .collect();
+
+        let mut failed = Vec::new();
+        for symbol in pending {
             self.init_socket_connection(&symbol);
-            self.get_data(&symbol)?;
+            if let Err(e) = self.get_data(&symbol) {
+                log_error!(symbol = %symbol, error = %e, "Cannot stream");
+                failed.push(symbol);
+            }
+        }
+
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("Cannot stream {}", failed.join(", ")))
         }
-        Ok(())
     }
 
     fn get_data(&mut self, symbol: &str) -> Result<(), String>
     {
-        log_info!("Starting data stream for symbol: {}", symbol);
+        log_info!(symbol = %symbol, "Starting data stream");
         let socket = self.sockets.remove(symbol).ok_or_else(|| format!("No socket found for symbol: {}", symbol))?;
         let symbol_owned = symbol.to_owned();
+        let normalized_symbol = symbol.to_uppercase();
         let sender = Arc::clone(&self.sender);
         let shutdown = Arc::clone(&self.shutdown);
         let max_attempts = self.max_reconnect_attempts;
+        let mut book_sync = self.order_books
+            .clone()
+            .map(|store| BinanceBookSync::new(Self::EXCHANGE, symbol, store));
         
+        let mut frames = self.recorder.as_ref().map(|recorder| recorder.connection(Self::EXCHANGE));
+        let connection = connection_span(&self.span, std::slice::from_ref(&symbol_owned));
+
         let handle = thread::spawn(move || {
+            let _connection = connection.entered();
             let mut socket = socket;
             let mut reconnect_attempts = 0;
             
             loop {
                 // Check if shutdown is requested
                 if shutdown.load(Ordering::Relaxed) {
-                    log_info!("Shutdown requested for {}, stopping data stream", symbol_owned);
+                    log_info!("Shutdown requested, stopping data stream");
                     break;
                 }
                 
                 match socket.read() {
                     Ok(Message::Text(text)) => {
+                        let read_at = Instant::now();
                         // Reset reconnect attempts on successful read
                         reconnect_attempts = 0;
-                        match serde_json::from_str::<Value>(&text) {
-                            Ok(json) => {
-                                if let Some(price) = json["c"].as_str() {
-                                    if let Ok(price) = price.parse::<f64>() {
-                                        let message = SymbolMessage::new(symbol_owned.clone(), price);
-                                        
-                                        // Send to channel
-                                        if let Err(e) = sender.send(message) {
-                                            log_error!("Failed to send message to channel: {}", e);
-                                            return Err(format!("Channel send error: {}", e));
-                                        }
-                                    } else {
-                                        let error = format!("Failed to parse price: {}", price);
-                                        log_error!("{}", error);
-                                    }
-                                } else {
-                                    log_warn!("No price field 'c' found in message for {}", symbol_owned);
-                                }
-                            }
-                            Err(e) => {
-                                log_warn!("Failed to parse JSON for {}: {}", symbol_owned, e);
-                            }
+                        if let Some(frames) = &frames {
+                            frames.record(&text);
                         }
+                        Self::on_message(&text, read_at, &normalized_symbol, &sender, book_sync.as_mut(), frames.as_ref())?;
                     }
                     Ok(Message::Close(_)) => {
-                        log_info!("WebSocket connection closed for {}", symbol_owned);
+                        log_info!("WebSocket connection closed");
                         
                         // Attempt to reconnect automatically
                         if reconnect_attempts < max_attempts {
                             reconnect_attempts += 1;
-                            log_info!("Attempting reconnection {} of {} for {}", reconnect_attempts, max_attempts, symbol_owned);
+                            metrics().reconnecting(Self::EXCHANGE);
+                            log_info!(attempt = reconnect_attempts, max_attempts, "Reconnecting");
                             
                             // Wait before reconnecting (exponential backoff)
                             let delay = std::time::Duration::from_millis(1000 * reconnect_attempts as u64);
                             std::thread::sleep(delay);
                             
                             // Try to create a new connection
-                            let endpoint = format!("wss://stream.binance.com:9443/ws/{}@ticker", symbol_owned);
+                            let endpoint = Self::endpoint(&symbol_owned, book_sync.is_some());
                             match connect(Url::parse(&endpoint).unwrap()) {
                                 Ok((new_socket, _response)) => {
+                                    set_read_timeout(&new_socket, READ_TIMEOUT);
                                     socket = new_socket;
-                                    log_info!("✅ Successfully reconnected to {} (attempt {})", symbol_owned, reconnect_attempts);
+                                    if let Some(frames) = frames.as_mut() {
+                                        frames.reconnected();
+                                    }
+                                    if let Some(sync) = book_sync.as_mut() {
+                                        sync.reset();
+                                    }
+                                    log_info!(attempt = reconnect_attempts, "Reconnected");
                                     continue;
                                 }
                                 Err(reconnect_error) => {
-                                    log_error!("Reconnection attempt {} failed for {}: {}", reconnect_attempts, symbol_owned, reconnect_error);
+                                    log_error!(attempt = reconnect_attempts, error = %reconnect_error, "Reconnection failed");
                                     if reconnect_attempts >= max_attempts {
-                                        log_error!("Max reconnection attempts reached for {}, giving up", symbol_owned);
-                                        return Err(format!("Max reconnection attempts reached"));
+                                        log_error!(max_attempts, "Max reconnection attempts reached, giving up");
+                                        return Err("Max reconnection attempts reached".to_string());
                                     }
                                 }
                             }
                         } else {
-                            log_error!("Max reconnection attempts reached for {}, giving up", symbol_owned);
-                            return Err(format!("Max reconnection attempts reached"));
+                            log_error!(max_attempts, "Max reconnection attempts reached, giving up");
+                            return Err("Max reconnection attempts reached".to_string());
                         }
                     }
                     Ok(Message::Binary(_)) => {
-                        log_debug!("Received binary message for {} (ignoring)", symbol_owned);
+                        log_debug!("Ignoring a binary message");
                     }
+                    Err(e) if is_read_timeout(&e) => continue,
                     Err(e) => {
-                        log_error!("WebSocket error for {}: {}", symbol_owned, e);
+                        log_error!(error = %e, "WebSocket error");
                         
                         // Attempt to reconnect automatically on error
                         if reconnect_attempts < max_attempts {
                             reconnect_attempts += 1;
-                            log_info!("Attempting reconnection {} of {} for {} due to error", reconnect_attempts, max_attempts, symbol_owned);
+                            metrics().reconnecting(Self::EXCHANGE);
+                            log_info!(attempt = reconnect_attempts, max_attempts, "Reconnecting after an error");
                             
                             // Wait before reconnecting (exponential backoff)
                             let delay = std::time::Duration::from_millis(1000 * reconnect_attempts as u64);
                             std::thread::sleep(delay);
                             
                             // Try to create a new connection
-                            let endpoint = format!("wss://stream.binance.com:9443/ws/{}@ticker", symbol_owned);
+                            let endpoint = Self::endpoint(&symbol_owned, book_sync.is_some());
                             match connect(Url::parse(&endpoint).unwrap()) {
                                 Ok((new_socket, _response)) => {
+                                    set_read_timeout(&new_socket, READ_TIMEOUT);
                                     socket = new_socket;
-                                    log_info!("✅ Successfully reconnected to {} (attempt {})", symbol_owned, reconnect_attempts);
+                                    if let Some(frames) = frames.as_mut() {
+                                        frames.reconnected();
+                                    }
+                                    if let Some(sync) = book_sync.as_mut() {
+                                        sync.reset();
+                                    }
+                                    log_info!(attempt = reconnect_attempts, "Reconnected");
                                     continue;
                                 }
                                 Err(reconnect_error) => {
-                                    log_error!("Reconnection attempt {} failed for {}: {}", reconnect_attempts, symbol_owned, reconnect_error);
+                                    log_error!(attempt = reconnect_attempts, error = %reconnect_error, "Reconnection failed");
                                     if reconnect_attempts >= max_attempts {
-                                        log_error!("Max reconnection attempts reached for {}, giving up", symbol_owned);
-                                        return Err(format!("Max reconnection attempts reached"));
+                                        log_error!(max_attempts, "Max reconnection attempts reached, giving up");
+                                        return Err("Max reconnection attempts reached".to_string());
                                     }
                                 }
                             }
                         } else {
-                            log_error!("Max reconnection attempts reached for {}, giving up", symbol_owned);
-                            return Err(format!("Max reconnection attempts reached"));
+                            log_error!(max_attempts, "Max reconnection attempts reached, giving up");
+                            return Err("Max reconnection attempts reached".to_string());
                         }
                     }
                     _ => {
-                        log_warn!("Unknown message type for {}", symbol_owned);
+                        log_warn!("Unknown message type");
                     }
                 }
             }
@@ -216,43 +276,143 @@ impl BinanceContainer {
 // Helper methods to keep the main function clean
 impl BinanceContainer {
 
+    /// Handle a text frame of the stream of `symbol` read at `read_at`, depth diffs go to the book sync if any
+    pub(crate) fn on_message(
+        text: &str,
+        read_at: Instant,
+        symbol: &str,
+        sender: &Arc<Sender<SymbolMessage>>,
+        book_sync: Option<&mut BinanceBookSync>,
+        frames: Option<&ConnectionRecorder>,
+    ) -> Result<(), String> {
+        let _symbol = symbol_span(symbol).entered();
+        metrics().message_received(Self::EXCHANGE);
+        let json = match serde_json::from_str::<Value>(text) {
+            Ok(json) => json,
+            Err(e) => {
+                metrics().parse_failed(Self::EXCHANGE);
+                log_warn!(error = %e, text = %text, "Failed to parse JSON");
+                return Ok(());
+            }
+        };
+
+        // Combined streams wrap the event payload in `data`
+        let json = json.get("data").unwrap_or(&json);
+        if json["e"] == "depthUpdate" {
+            if let Some(sync) = book_sync {
+                Self::on_depth_update(sync, json, frames);
+            }
+        } else if let Some(price) = json["c"].as_str() {
+            if let Ok(price) = price.parse::<f64>() {
+                // `E` is the event time in milliseconds, `v` the 24 hour base volume
+                let message = SymbolMessage::new(Self::EXCHANGE, symbol.to_string(), price)
+                    .with_volume_24h(json["v"].as_str().and_then(|volume| volume.parse().ok()))
+                    .with_exchange_time(json["E"].as_i64().and_then(DateTime::from_timestamp_millis))
+                    .with_read_at(read_at);
+
+                // Send to channel
+                if let Err(e) = sender.send(message) {
+                    log_error!(error = %e, "Failed to send message to channel");
+                    return Err(format!("Channel send error: {}", e));
+                }
+            } else {
+                metrics().parse_failed(Self::EXCHANGE);
+                log_error!(price = %price, "Failed to parse price");
+            }
+        } else {
+            log_warn!("No price field 'c' found in message");
+        }
+        Ok(())
+    }
+
+    fn on_depth_update(sync: &mut BinanceBookSync, json: &Value, frames: Option<&ConnectionRecorder>) {
+        let Some(update) = DepthUpdate::from_json(json) else {
+            log_warn!(event = %json, "Malformed depthUpdate event");
+            return;
+        };
+
+        sync.on_depth_update(update);
+        if sync.should_fetch_snapshot() {
+            match sync.resync() {
+                Ok(snapshot) => {
+                    if let Some(frames) = frames {
+                        frames.record_snapshot(sync.symbol(), &snapshot);
+                    }
+                }
+                Err(e) => log_error!(error = %e, "Cannot resync the order book"),
+            }
+        }
+    }
+
     /// Gracefully shutdown all connections and threads
     pub fn shutdown(&mut self) {
-        log_info!("Initiating graceful shutdown of BinanceContainer");
+        let _connector = self.span.clone().entered();
+        log_info!("Initiating graceful shutdown");
         
         // Set shutdown flag
         self.shutdown.store(true, Ordering::Relaxed);
         
         // Close all WebSocket connections
-        log_info!("Closing {} WebSocket connections", self.sockets.len());
+        log_info!(connections = self.sockets.len(), "Closing WebSocket connections");
         for (symbol, mut socket) in self.sockets.drain() {
-            log_debug!("Closing socket for symbol: {}", symbol);
+            log_debug!(symbol = %symbol, "Closing socket");
             if let Err(e) = socket.close(None) {
-                log_warn!("Error closing socket for {}: {}", symbol, e);
+                log_warn!(symbol = %symbol, error = %e, "Error closing socket");
             }
         }
         
         // Wait for all socket threads to complete
-        log_info!("Waiting for {} socket threads to complete", self.socket_threads.len());
+        log_info!(threads = self.socket_threads.len(), "Waiting for the socket threads to complete");
         for (symbol, handle) in self.socket_threads.drain() {
-            log_debug!("Waiting for thread to complete: {}", symbol);
+            log_debug!(symbol = %symbol, "Waiting for thread to complete");
             match handle.join() {
                 Ok(result) => {
                     match result {
-                        Ok(_) => log_debug!("Thread for {} completed successfully", symbol),
-                        Err(e) => log_warn!("Thread for {} ended with error: {}", symbol, e),
+                        Ok(_) => log_debug!(symbol = %symbol, "Thread completed successfully"),
+                        Err(e) => log_warn!(symbol = %symbol, error = %e, "Thread ended with error"),
                     }
                 }
                 Err(e) => {
-                    log_error!("Error joining thread for {}: {:?}", symbol, e);
+                    log_error!(symbol = %symbol, error = ?e, "Error joining thread");
                 }
             }
         }
         
-        log_info!("BinanceContainer shutdown completed");
+        log_info!("Shutdown completed");
     }
+}
+
+impl Default for BinanceContainer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
+impl ISocketContainer for BinanceContainer {
+    fn exchange(&self) -> &'static str {
+        Self::EXCHANGE
+    }
+
+    fn add_symbol(&mut self, symbol: &str) -> Result<(), String> {
+        BinanceContainer::add_symbol(self, symbol);
+        Ok(())
+    }
 
+    fn set_recorder(&mut self, recorder: FrameRecorder) {
+        self.recorder = Some(recorder);
+    }
+
+    fn symbols(&self) -> Vec<String> {
+        self.symbols.clone()
+    }
+
+    fn start_monitoring(&mut self) -> Result<(), String> {
+        BinanceContainer::start_monitoring(self)
+    }
+
+    fn shutdown(&mut self) {
+        BinanceContainer::shutdown(self)
+    }
 }
 
 /// Implement Drop trait for graceful cleanup
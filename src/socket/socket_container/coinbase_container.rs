@@ -1,272 +1,376 @@
-use std::{collections::HashMap, fmt::Binary, net::TcpStream, sync::{mpsc::Receiver, Arc}, thread::{self, JoinHandle}};
-use url::Url;
-use tungstenite::{connect, stream::MaybeTlsStream, WebSocket, Message};
-use serde_json::{json, Value};
-use crate::{log_debug, log_error, log_info, log_warn, models::SymbolMessage, socket};
-use std::sync::mpsc::{Sender};
-use std::sync::{atomic::AtomicBool, atomic::Ordering};
-
-pub struct CoinBaseContainer {
-    socket: Option<WebSocket<MaybeTlsStream<TcpStream>>>,
-    sender: Arc<Sender<SymbolMessage>>,
-    socket_thread: Option<JoinHandle<Result<(), String>>>,
-    symbols: Vec<String>,
-    shutdown: Arc<AtomicBool>,
-    max_reconnect_attempts: u32,
-    reconnect_attempts: u32,
-    receiver: Receiver<SymbolMessage>
-}
-
-impl CoinBaseContainer {
-    pub fn new() -> Self {
-        let (sender, receiver) = std::sync::mpsc::channel();
-        let sender = Arc::new(sender);
-
-        CoinBaseContainer { 
-            socket: None,
-            sender,
-            receiver,
-            socket_thread: None,
-            symbols: Vec::new(),
-            shutdown: Arc::new(AtomicBool::new(false)),
-            max_reconnect_attempts: 5,
-            reconnect_attempts: 0,
-        }
-    }
-
-    pub fn add_symbol(&mut self, symbol: &str) {
-        // Add symbol to tracking list if not already present
-        if !self.symbols.contains(&symbol.to_string()) {
-            self.symbols.push(symbol.to_string());
-        }
-    }
-
-    pub fn on_symbol_update<T>(&mut self, callback: T)
-    where
-        T: Fn(&str, f64)
-    {
-        let received_messages = self.receiver.iter();
-        for received in received_messages {
-            callback(&received.symbol, received.price);
-        }
-    }
-
-    fn init_socket_connection(&mut self) -> Result<(), String> {
-        let url = Url::parse("wss://ws-feed.exchange.coinbase.com").unwrap();
-        println!("Connecting to {}", url);
-
-        let subscribe_message = json!({
-            "type": "subscribe",
-            "channels": [{
-                "name": "ticker",
-                "product_ids": self.symbols
-            }]
-        });
-
-        match connect(url) {
-            Ok((mut socket, _response)) => {
-                socket
-                    .send(Message::Text(subscribe_message.to_string()))
-                    .unwrap();
-                self.socket = Some(socket);
-                let success_message = format!("[CoinBaseContainer] successfully connected to socket for {} symbols", self.symbols.len());
-                log_info!("{}", success_message);
-            }
-            Err(err) => {
-                let error_log = format!("Cannot connect to CoinBase Websocket for {} symbols, details: {}", self.symbols.len(), err);
-                log_error!("{}", error_log); 
-                return Err(error_log);
-            }
-        };
-        
-        log_info!("Finished connected to Binance WebStocke");
-        Ok(())
-    }
-    
-    pub fn disconnect(&self) {
-        log_info!("Disconnecting from CoinBase WebSocket");
-        // Disconnect logic here
-    }
-
-    /// Check if a socket connection exists for the given symbol
-    pub fn has_socket_connection(&self) -> bool {
-        if let Some(_) = &self.socket {
-            return true;
-        } else {
-            return false;
-        }
-    }
-
-    /// Start monitoring multiple cryptocurrency symbols
-    pub fn start_monitoring(&mut self) -> Result<JoinHandle<()>, String> {
-        match self.init_socket_connection() {
-            Ok(_) => self.get_data(),
-            Err(msg) => Err(msg)
-        }
-    }
-
-    fn get_data(&mut self) -> Result<JoinHandle<()>, String>
-    {
-        if let None = self.socket {
-            log_error!("[CoinBaseContainer - get_data] There is no socket connection to get data");
-            return Err("[CoinBaseContainer - get_data] There is no socket connection to get data".to_string());
-        }
-        let socket: WebSocket<MaybeTlsStream<TcpStream>> = self.socket.take().unwrap();
-
-        let sender = Arc::clone(&self.sender);
-        let shutdown = Arc::clone(&self.shutdown);
-        
-        let handle = thread::spawn(move || {
-            let mut socket = socket;
-            loop {
-                // Check if shutdown is requested
-                if shutdown.clone().load(Ordering::Relaxed) {
-                    log_info!("[CoinBaseContainer - get_data] Shutdown requested, stopping data stream");
-                    break;
-                }
-                
-                match socket.read() {
-                    Ok(Message::Text(text)) => {
-                        Self::on_message(text, &sender);
-                    }
-                    Ok(Message::Close(_)) => {
-                        Self::on_close(shutdown.clone());
-                    }
-                    Err(e) => {
-                        log_error!("[CoinBaseContainer - get_data] WebSocket error: {}", e);
-                        // self.on_error();
-                    }
-                    _ => {
-                        log_warn!("Unknown message type");
-                    }
-                }
-            }
-        });
-        
-        return Ok(handle);
-    }
-
-    fn on_message(text: String, sender: &Arc<Sender<SymbolMessage>>) {
-        match serde_json::from_str::<Value>(&text) {
-            Ok(json) => {
-                if json["type"] == "ticker" {
-                    let product_id = json["product_id"].as_str().unwrap_or("unknown");
-                    let formatted_symbol = product_id.trim().replace("-", "");
-
-                    if let Some(price) = json["price"].as_str() {
-                        if let Ok(price) = price.parse::<f64>() {
-                            let message = SymbolMessage::new(formatted_symbol, price);
-                            
-                            // Send to channel
-                            if let Err(e) = sender.send(message) {
-                                log_error!("[CoinBaseContainer - on_message] Failed to send message to channel: {}", e);
-                            }
-                        } else {
-                            let error = format!("Failed to parse price: {}", price);
-                            log_error!("{}", error);
-                        }
-                    } else {
-                        log_warn!("[CoinBaseContainer - on_message] No price field 'c' found in message for {}", formatted_symbol);
-                    }
-                }
-            }
-            Err(e) => {
-                log_warn!("[CoinBaseContainer - on_message] Failed to parse JSON for {}: {}", &text, e);
-            }
-        }
-    }
-
-    fn on_close(shutdown: Arc<AtomicBool>) {
-        log_info!("[CoinBaseContainer - on_close] WebSocket connection closed");
-        // Check if shutdown is requested
-        if shutdown.load(Ordering::Relaxed) {
-            log_info!("[CoinBaseContainer - on_close] Shutdown requested, no reconnection");
-        }   
-
-        // Attempt to reconnect automatically
-        // if reconnect_attempts < max_reconnect_attempts {
-        //     reconnect_attempts += 1;
-        //     log_info!("[CoinBaseContainer - get_data] Attempting reconnection {} of {}", reconnect_attempts, max_reconnect_attempts);
-            
-        //     // Wait before reconnecting (exponential backoff)
-        //     let delay = std::time::Duration::from_millis(1000 * reconnect_attempts as u64);
-        //     std::thread::sleep(delay);
-            
-            
-        // } else {
-        //     log_error!("Max reconnection attempts reached, giving up");
-        //     return Err(format!("Max reconnection attempts reached"));
-        // }
-    }
-
-    fn on_error(&mut self) {
-                        
-        // Attempt to reconnect automatically on error
-        if self.reconnect_attempts < self.max_reconnect_attempts {
-            self.reconnect_attempts += 1;
-            log_info!("[CoinBaseContainer - on_error] Attempting reconnection {} of {}", self.reconnect_attempts, self.max_reconnect_attempts);
-            
-            // Wait before reconnecting (exponential backoff)
-            let delay = std::time::Duration::from_millis(1000 * self.reconnect_attempts as u64);
-            std::thread::sleep(delay);
-            
-            // Try to create a new connection
-            self.init_socket_connection();
-            self.get_data();
-        } else {
-            log_error!("Max reconnection attempts reached, giving up");
-        }
-    }
-}
-
-// Helper methods to keep the main function clean
-impl CoinBaseContainer {
-
-    // Gracefully shutdown all connections and threads
-    // pub fn shutdown(&mut self) {
-    //     log_info!("Initiating graceful shutdown of CoinBaseContainer");
-        
-    //     // Set shutdown flag
-    //     self.shutdown.store(true, Ordering::Relaxed);
-        
-    //     // Close all WebSocket connections
-    //     log_info!("Closing {} WebSocket connections", self.sockets.len());
-    //     for (symbol, mut socket) in self.sockets.drain() {
-    //         log_debug!("Closing socket for symbol: {}", symbol);
-    //         if let Err(e) = socket.close(None) {
-    //             log_warn!("Error closing socket for {}: {}", symbol, e);
-    //         }
-    //     }
-        
-    //     // Wait for all socket threads to complete
-    //     log_info!("Waiting for {} socket threads to complete", self.socket_threads.len());
-    //     for (symbol, handle) in self.socket_threads.drain() {
-    //         log_debug!("Waiting for thread to complete: {}", symbol);
-    //         match handle.join() {
-    //             Ok(result) => {
-    //                 match result {
-    //                     Ok(_) => log_debug!("Thread for {} completed successfully", symbol),
-    //                     Err(e) => log_warn!("Thread for {} ended with error: {}", symbol, e),
-    //                 }
-    //             }
-    //             Err(e) => {
-    //                 log_error!("Error joining thread for {}: {:?}", symbol, e);
-    //             }
-    //         }
-    //     }
-        
-    //     log_info!("CoinBaseContainer shutdown completed");
-    // }
-
-
-}
-
-/// Implement Drop trait for graceful cleanup
-impl Drop for CoinBaseContainer {
-    fn drop(&mut self) {
-        log_info!("CoinBaseContainer is being dropped, initiating cleanup");
-        // self.shutdown();
-    }
-}
-
-
+use std::{net::TcpStream, sync::{mpsc::Receiver, Arc}, thread::{self, JoinHandle}, time::Instant};
+use url::Url;
+use tungstenite::{connect, stream::MaybeTlsStream, WebSocket, Message};
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+use tracing::Span;
+use crate::{log_debug, log_error, log_info, log_warn, models::SymbolMessage};
+use crate::metrics::metrics;
+use crate::recorder::FrameRecorder;
+use crate::orderbook::{coinbase::CoinbaseBookSync, OrderBookStore};
+use crate::socket::socket_container::socket_container::{
+    connection_span, connector_span, is_read_timeout, set_read_timeout, symbol_span, ISocketContainer, READ_TIMEOUT,
+};
+use std::sync::mpsc::{Sender};
+use std::sync::{atomic::AtomicBool, atomic::Ordering};
+
+const COINBASE_SOCKET_URL: &str = "wss://ws-feed.exchange.coinbase.com";
+
+pub struct CoinBaseContainer {
+    socket: Option<WebSocket<MaybeTlsStream<TcpStream>>>,
+    sender: Arc<Sender<SymbolMessage>>,
+    socket_thread: Option<JoinHandle<Result<(), String>>>,
+    symbols: Vec<String>,
+    shutdown: Arc<AtomicBool>,
+    max_reconnect_attempts: u32,
+    receiver: Option<Receiver<SymbolMessage>>,
+    order_books: Option<OrderBookStore>,
+    recorder: Option<FrameRecorder>,
+    /// Parent span of the connections
+    span: Span,
+}
+
+impl CoinBaseContainer {
+    pub const EXCHANGE: &'static str = "coinbase";
+
+    pub fn new() -> Self {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let mut container = Self::new_with_sender(Arc::new(sender));
+        container.receiver = Some(receiver);
+        container
+    }
+
+    /// Create a container that publishes every price update to the given channel
+    pub fn new_with_sender(sender: Arc<Sender<SymbolMessage>>) -> Self {
+        CoinBaseContainer {
+            socket: None,
+            sender,
+            receiver: None,
+            socket_thread: None,
+            symbols: Vec::new(),
+            shutdown: Arc::new(AtomicBool::new(false)),
+            max_reconnect_attempts: 5,
+            order_books: None,
+            recorder: None,
+            span: connector_span(Self::EXCHANGE),
+        }
+    }
+
+    /// Also subscribe to level2 updates and maintain the L2 book of every product in the given store
+    pub fn with_order_books(mut self, order_books: OrderBookStore) -> Self {
+        self.order_books = Some(order_books);
+        self
+    }
+
+    /// Record every raw frame received on the connection(s)
+    pub fn with_recorder(mut self, recorder: FrameRecorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    pub fn add_symbol(&mut self, symbol: &str) {
+        // Add symbol to tracking list if not already present
+        if !self.symbols.contains(&symbol.to_string()) {
+            self.symbols.push(symbol.to_string());
+        }
+    }
+
+    pub fn on_symbol_update<T>(&mut self, callback: T)
+    where
+        T: Fn(&str, f64)
+    {
+        let Some(receiver) = &self.receiver else {
+            log_warn!("Messages are published to an external channel");
+            return;
+        };
+
+        for received in receiver.iter() {
+            callback(&received.symbol, received.price);
+        }
+    }
+
+    fn connect_and_subscribe(symbols: &[String], with_depth: bool) -> Result<WebSocket<MaybeTlsStream<TcpStream>>, String> {
+        let url = Url::parse(COINBASE_SOCKET_URL).unwrap();
+
+        let mut channels = vec![json!({ "name": "ticker", "product_ids": symbols })];
+        if with_depth {
+            // `level2_batch` carries the same snapshot / l2update messages as `level2`
+            // without requiring an authenticated connection
+            channels.push(json!({ "name": "level2_batch", "product_ids": symbols }));
+        }
+
+        let subscribe_message = json!({
+            "type": "subscribe",
+            "channels": channels
+        });
+
+        match connect(url) {
+            Ok((mut socket, _response)) => {
+                set_read_timeout(&socket, READ_TIMEOUT);
+                socket
+                    .send(Message::Text(subscribe_message.to_string()))
+                    .map_err(|e| format!("Cannot subscribe to CoinBase ticker channel: {}", e))?;
+                log_info!(symbols = symbols.len(), "Connected and subscribed");
+                Ok(socket)
+            }
+            Err(err) => {
+                log_error!(symbols = symbols.len(), error = %err, "Cannot connect");
+                Err(format!("Cannot connect to CoinBase Websocket for {} symbols, details: {}", symbols.len(), err))
+            }
+        }
+    }
+
+    fn init_socket_connection(&mut self) -> Result<(), String> {
+        let _connector = self.span.clone().entered();
+        self.socket = Some(Self::connect_and_subscribe(&self.symbols, self.order_books.is_some())?);
+        log_info!("Finished connecting to CoinBase WebSocket");
+        Ok(())
+    }
+
+    pub fn disconnect(&self) {
+        log_info!("Disconnecting from CoinBase WebSocket");
+        // Disconnect logic here
+    }
+
+    /// Check if a socket connection exists for the given symbol
+    pub fn has_socket_connection(&self) -> bool {
+        self.socket.is_some() || self.socket_thread.is_some()
+    }
+
+    /// Start monitoring multiple cryptocurrency symbols
+    pub fn start_monitoring(&mut self) -> Result<(), String> {
+        if self.socket_thread.is_some() {
+            log_debug!("Data stream already running");
+            return Ok(());
+        }
+
+        self.init_socket_connection()?;
+        self.get_data()
+    }
+
+    fn get_data(&mut self) -> Result<(), String>
+    {
+        let Some(socket) = self.socket.take() else {
+            log_error!("There is no socket connection to get data");
+            return Err("There is no socket connection to get data".to_string());
+        };
+
+        let sender = Arc::clone(&self.sender);
+        let shutdown = Arc::clone(&self.shutdown);
+        let symbols = self.symbols.clone();
+        let max_attempts = self.max_reconnect_attempts;
+        let mut book_sync = self.order_books
+            .clone()
+            .map(|store| CoinbaseBookSync::new(Self::EXCHANGE, store));
+
+        let mut frames = self.recorder.as_ref().map(|recorder| recorder.connection(Self::EXCHANGE));
+        let connection = connection_span(&self.span, &symbols);
+
+        let handle = thread::spawn(move || {
+            let _connection = connection.entered();
+            let mut socket = socket;
+            let mut reconnect_attempts = 0;
+            loop {
+                // Check if shutdown is requested
+                if shutdown.load(Ordering::Relaxed) {
+                    log_info!("Shutdown requested, stopping data stream");
+                    break;
+                }
+
+                match socket.read() {
+                    Ok(Message::Text(text)) => {
+                        let read_at = Instant::now();
+                        reconnect_attempts = 0;
+                        if let Some(frames) = &frames {
+                            frames.record(&text);
+                        }
+                        Self::on_message(text, read_at, &sender, book_sync.as_mut());
+                    }
+                    Ok(Message::Close(_)) => {
+                        log_info!("WebSocket connection closed");
+                        socket = Self::on_error(&symbols, book_sync.is_some(), &shutdown, &mut reconnect_attempts, max_attempts)?;
+                        if let Some(frames) = frames.as_mut() {
+                            frames.reconnected();
+                        }
+                        if let Some(sync) = book_sync.as_mut() {
+                            sync.reset();
+                        }
+                    }
+                    Err(e) if is_read_timeout(&e) => continue,
+                    Err(e) => {
+                        log_error!(error = %e, "WebSocket error");
+                        socket = Self::on_error(&symbols, book_sync.is_some(), &shutdown, &mut reconnect_attempts, max_attempts)?;
+                        if let Some(frames) = frames.as_mut() {
+                            frames.reconnected();
+                        }
+                        if let Some(sync) = book_sync.as_mut() {
+                            sync.reset();
+                        }
+                    }
+                    _ => {
+                        log_warn!("Unknown message type");
+                    }
+                }
+            }
+
+            Ok(())
+        });
+
+        self.socket_thread = Some(handle);
+        Ok(())
+    }
+
+    pub(crate) fn on_message(text: String, read_at: Instant, sender: &Arc<Sender<SymbolMessage>>, book_sync: Option<&mut CoinbaseBookSync>) {
+        metrics().message_received(Self::EXCHANGE);
+        match serde_json::from_str::<Value>(&text) {
+            Ok(json) => {
+                if let Some(book_sync) = book_sync
+                    && book_sync.on_message(&json)
+                {
+                    return;
+                }
+
+                if json["type"] == "ticker" {
+                    let product_id = json["product_id"].as_str().unwrap_or("unknown");
+                    let formatted_symbol = product_id.trim().replace("-", "");
+                    let _symbol = symbol_span(&formatted_symbol).entered();
+
+                    if let Some(price) = json["price"].as_str() {
+                        if let Ok(price) = price.parse::<f64>() {
+                            // Coinbase publishes a ticker message for every match
+                            let quantity = json["last_size"].as_str().and_then(|size| size.parse::<f64>().ok());
+                            let time = json["time"]
+                                .as_str()
+                                .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+                                .map(|time| time.with_timezone(&Utc));
+                            let message = SymbolMessage::new(Self::EXCHANGE, formatted_symbol, price)
+                                .with_quantity(quantity)
+                                .with_exchange_time(time)
+                                .with_read_at(read_at);
+
+                            // Send to channel
+                            if let Err(e) = sender.send(message) {
+                                log_error!(error = %e, "Failed to send message to channel");
+                            }
+                        } else {
+                            metrics().parse_failed(Self::EXCHANGE);
+                            log_error!(price = %price, "Failed to parse price");
+                        }
+                    } else {
+                        log_warn!(symbol = %formatted_symbol, "No price field 'price' found in message");
+                    }
+                }
+            }
+            Err(e) => {
+                metrics().parse_failed(Self::EXCHANGE);
+                log_warn!(error = %e, text = %text, "Failed to parse JSON");
+            }
+        }
+    }
+
+    fn on_error(
+        symbols: &[String],
+        with_depth: bool,
+        shutdown: &Arc<AtomicBool>,
+        reconnect_attempts: &mut u32,
+        max_reconnect_attempts: u32,
+    ) -> Result<WebSocket<MaybeTlsStream<TcpStream>>, String> {
+        // Check if shutdown is requested
+        if shutdown.load(Ordering::Relaxed) {
+            log_info!("Shutdown requested, no reconnection");
+            return Err("Shutdown requested".to_string());
+        }
+
+        // Attempt to reconnect automatically
+        while *reconnect_attempts < max_reconnect_attempts {
+            *reconnect_attempts += 1;
+            metrics().reconnecting(Self::EXCHANGE);
+            log_info!(attempt = *reconnect_attempts, max_attempts = max_reconnect_attempts, "Reconnecting");
+
+            // Wait before reconnecting (exponential backoff)
+            let delay = std::time::Duration::from_millis(1000 * *reconnect_attempts as u64);
+            std::thread::sleep(delay);
+
+            // Try to create a new connection
+            if let Ok(socket) = Self::connect_and_subscribe(symbols, with_depth) {
+                log_info!(attempt = *reconnect_attempts, "Reconnected");
+                return Ok(socket);
+            }
+        }
+
+        log_error!(max_attempts = max_reconnect_attempts, "Max reconnection attempts reached, giving up");
+        Err("Max reconnection attempts reached".to_string())
+    }
+}
+
+// Helper methods to keep the main function clean
+impl CoinBaseContainer {
+
+    /// Gracefully shutdown the connection and the streaming thread
+    pub fn shutdown(&mut self) {
+        let _connector = self.span.clone().entered();
+        log_info!("Initiating graceful shutdown");
+
+        // Set shutdown flag
+        self.shutdown.store(true, Ordering::Relaxed);
+
+        // Close a connection that was never handed over to a thread
+        if let Some(mut socket) = self.socket.take()
+            && let Err(e) = socket.close(None)
+        {
+            log_warn!(error = %e, "Error closing socket");
+        }
+
+        // Wait for the socket thread to complete
+        if let Some(handle) = self.socket_thread.take() {
+            match handle.join() {
+                Ok(Ok(_)) => log_debug!("Thread completed successfully"),
+                Ok(Err(e)) => log_warn!(error = %e, "Thread ended with error"),
+                Err(e) => log_error!(error = ?e, "Error joining thread"),
+            }
+        }
+
+        log_info!("Shutdown completed");
+    }
+}
+
+impl Default for CoinBaseContainer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ISocketContainer for CoinBaseContainer {
+    fn exchange(&self) -> &'static str {
+        Self::EXCHANGE
+    }
+
+    fn add_symbol(&mut self, symbol: &str) -> Result<(), String> {
+        CoinBaseContainer::add_symbol(self, symbol);
+        Ok(())
+    }
+
+    fn set_recorder(&mut self, recorder: FrameRecorder) {
+        self.recorder = Some(recorder);
+    }
+
+    fn symbols(&self) -> Vec<String> {
+        self.symbols.clone()
+    }
+
+    fn start_monitoring(&mut self) -> Result<(), String> {
+        CoinBaseContainer::start_monitoring(self)
+    }
+
+    fn shutdown(&mut self) {
+        CoinBaseContainer::shutdown(self)
+    }
+}
+
+/// Implement Drop trait for graceful cleanup
+impl Drop for CoinBaseContainer {
+    fn drop(&mut self) {
+        log_info!("CoinBaseContainer is being dropped, initiating cleanup");
+        self.shutdown();
+    }
+}
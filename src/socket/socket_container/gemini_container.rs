@@ -0,0 +1,350 @@
+use std::{net::TcpStream, sync::Arc, thread::{self, JoinHandle}, time::Instant};
+use url::Url;
+use tungstenite::{connect, stream::MaybeTlsStream, WebSocket, Message};
+use chrono::DateTime;
+use serde_json::{json, Value};
+use tracing::Span;
+use crate::{log_debug, log_error, log_info, log_warn, models::SymbolMessage};
+use crate::metrics::metrics;
+use crate::recorder::FrameRecorder;
+use crate::orderbook::{gemini::GeminiBookSync, OrderBookStore};
+use crate::socket::socket_container::socket_container::{
+    connection_span, connector_span, is_read_timeout, set_read_timeout, symbol_span, ISocketContainer, READ_TIMEOUT,
+};
+use std::sync::mpsc::Sender;
+use std::sync::{atomic::AtomicBool, atomic::Ordering};
+
+const GEMINI_SOCKET_URL: &str = "wss://api.gemini.com/v2/marketdata";
+
+/// Gemini market data connector using the v2 `l2` subscription, which carries
+/// order book changes as well as the trades executed on each symbol.
+pub struct GeminiContainer {
+    socket: Option<WebSocket<MaybeTlsStream<TcpStream>>>,
+    sender: Arc<Sender<SymbolMessage>>,
+    socket_thread: Option<JoinHandle<Result<(), String>>>,
+    symbols: Vec<String>,
+    shutdown: Arc<AtomicBool>,
+    max_reconnect_attempts: u32,
+    order_books: Option<OrderBookStore>,
+    recorder: Option<FrameRecorder>,
+    /// Parent span of the connections
+    span: Span,
+}
+
+impl GeminiContainer {
+    pub const EXCHANGE: &'static str = "gemini";
+
+    pub fn new() -> Self {
+        let (sender, _receiver) = std::sync::mpsc::channel();
+        Self::new_with_sender(Arc::new(sender))
+    }
+
+    /// Create a container that publishes every price update to the given channel
+    pub fn new_with_sender(sender: Arc<Sender<SymbolMessage>>) -> Self {
+        GeminiContainer {
+            socket: None,
+            sender,
+            socket_thread: None,
+            symbols: Vec::new(),
+            shutdown: Arc::new(AtomicBool::new(false)),
+            max_reconnect_attempts: 5,
+            order_books: None,
+            recorder: None,
+            span: connector_span(Self::EXCHANGE),
+        }
+    }
+
+    /// Maintain the L2 book of every symbol in the given store
+    pub fn with_order_books(mut self, order_books: OrderBookStore) -> Self {
+        self.order_books = Some(order_books);
+        self
+    }
+
+    /// Record every raw frame received on the connection(s)
+    pub fn with_recorder(mut self, recorder: FrameRecorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Register a Gemini symbol such as `BTCUSD`
+    pub fn add_symbol(&mut self, symbol: &str) {
+        let symbol = symbol.trim().to_uppercase();
+        if !self.symbols.contains(&symbol) {
+            self.symbols.push(symbol);
+        }
+    }
+
+    fn connect_and_subscribe(symbols: &[String]) -> Result<WebSocket<MaybeTlsStream<TcpStream>>, String> {
+        let url = Url::parse(GEMINI_SOCKET_URL).unwrap();
+
+        let subscribe_message = json!({
+            "type": "subscribe",
+            "subscriptions": [{
+                "name": "l2",
+                "symbols": symbols
+            }]
+        });
+
+        match connect(url) {
+            Ok((mut socket, _response)) => {
+                set_read_timeout(&socket, READ_TIMEOUT);
+                socket
+                    .send(Message::Text(subscribe_message.to_string()))
+                    .map_err(|e| format!("Cannot subscribe to Gemini l2 channel: {}", e))?;
+                log_info!(symbols = symbols.len(), "Connected and subscribed");
+                Ok(socket)
+            }
+            Err(err) => {
+                log_error!(symbols = symbols.len(), error = %err, "Cannot connect");
+                Err(format!("Cannot connect to Gemini Websocket for {} symbols, details: {}", symbols.len(), err))
+            }
+        }
+    }
+
+    /// Start streaming every registered symbol over a single connection
+    pub fn start_monitoring(&mut self) -> Result<(), String> {
+        if self.socket_thread.is_some() {
+            log_debug!("Data stream already running");
+            return Ok(());
+        }
+
+        let _connector = self.span.clone().entered();
+        self.socket = Some(Self::connect_and_subscribe(&self.symbols)?);
+        self.get_data()
+    }
+
+    fn get_data(&mut self) -> Result<(), String> {
+        let Some(socket) = self.socket.take() else {
+            log_error!("There is no socket connection to get data");
+            return Err("There is no socket connection to get data".to_string());
+        };
+
+        let sender = Arc::clone(&self.sender);
+        let shutdown = Arc::clone(&self.shutdown);
+        let symbols = self.symbols.clone();
+        let max_attempts = self.max_reconnect_attempts;
+        let mut book_sync = self.order_books
+            .clone()
+            .map(|store| GeminiBookSync::new(Self::EXCHANGE, store));
+
+        let mut frames = self.recorder.as_ref().map(|recorder| recorder.connection(Self::EXCHANGE));
+        let connection = connection_span(&self.span, &symbols);
+
+        let handle = thread::spawn(move || {
+            let _connection = connection.entered();
+            let mut socket = socket;
+            let mut reconnect_attempts = 0;
+            loop {
+                if shutdown.load(Ordering::Relaxed) {
+                    log_info!("Shutdown requested, stopping data stream");
+                    break;
+                }
+
+                match socket.read() {
+                    Ok(Message::Text(text)) => {
+                        let read_at = Instant::now();
+                        reconnect_attempts = 0;
+                        if let Some(frames) = &frames {
+                            frames.record(&text);
+                        }
+                        Self::on_message(&text, read_at, &sender, book_sync.as_mut());
+                        if book_sync.as_mut().is_some_and(|sync| sync.take_reconnect()) {
+                            log_info!("Reconnecting for the full books");
+                            let _ = socket.close(None);
+                            socket = Self::reconnect(&symbols, &shutdown, &mut reconnect_attempts, max_attempts)?;
+                            if let Some(frames) = frames.as_mut() {
+                                frames.reconnected();
+                            }
+                            if let Some(sync) = book_sync.as_mut() {
+                                sync.reset();
+                            }
+                        }
+                    }
+                    Ok(Message::Close(_)) => {
+                        log_info!("WebSocket connection closed");
+                        socket = Self::reconnect(&symbols, &shutdown, &mut reconnect_attempts, max_attempts)?;
+                        if let Some(frames) = frames.as_mut() {
+                            frames.reconnected();
+                        }
+                        if let Some(sync) = book_sync.as_mut() {
+                            sync.reset();
+                        }
+                    }
+                    Err(e) if is_read_timeout(&e) => continue,
+                    Err(e) => {
+                        log_error!(error = %e, "WebSocket error");
+                        socket = Self::reconnect(&symbols, &shutdown, &mut reconnect_attempts, max_attempts)?;
+                        if let Some(frames) = frames.as_mut() {
+                            frames.reconnected();
+                        }
+                        if let Some(sync) = book_sync.as_mut() {
+                            sync.reset();
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            Ok(())
+        });
+
+        self.socket_thread = Some(handle);
+        Ok(())
+    }
+
+    pub(crate) fn on_message(text: &str, read_at: Instant, sender: &Arc<Sender<SymbolMessage>>, book_sync: Option<&mut GeminiBookSync>) {
+        metrics().message_received(Self::EXCHANGE);
+        let json = match serde_json::from_str::<Value>(text) {
+            Ok(json) => json,
+            Err(e) => {
+                metrics().parse_failed(Self::EXCHANGE);
+                log_warn!(error = %e, text = %text, "Failed to parse JSON");
+                return;
+            }
+        };
+
+        if let Some(book_sync) = book_sync {
+            book_sync.on_message(&json);
+        }
+
+        match json["type"].as_str() {
+            // The initial l2 snapshot carries the most recent trades of the symbol
+            Some("l2_updates") => {
+                if let Some(trade) = json["trades"].as_array().and_then(|trades| trades.last()) {
+                    Self::on_trade(trade, read_at, sender, false);
+                }
+            }
+            Some("trade") => Self::on_trade(&json, read_at, sender, true),
+            Some("heartbeat") => {}
+            Some(other) => log_debug!(message_type = %other, "Ignoring message"),
+            None => log_warn!(text = %text, "Message without type"),
+        }
+    }
+
+    /// Publish the price of a trade, with its quantity and time unless it is a past trade replayed by the snapshot
+    fn on_trade(trade: &Value, read_at: Instant, sender: &Arc<Sender<SymbolMessage>>, live: bool) {
+        let symbol = trade["symbol"].as_str().unwrap_or("unknown").to_string();
+        let _symbol = symbol_span(&symbol).entered();
+        let quantity = trade["quantity"]
+            .as_str()
+            .and_then(|quantity| quantity.parse::<f64>().ok())
+            .filter(|_| live);
+        // Milliseconds since the epoch
+        let time = trade["timestamp"].as_i64().and_then(DateTime::from_timestamp_millis).filter(|_| live);
+
+        let Some(price) = trade["price"].as_str() else {
+            log_warn!(symbol = %symbol, "No price field 'price' found in trade");
+            return;
+        };
+
+        match price.parse::<f64>() {
+            Ok(price) => {
+                let message = SymbolMessage::new(Self::EXCHANGE, symbol, price)
+                    .with_quantity(quantity)
+                    .with_exchange_time(time)
+                    .with_read_at(read_at);
+                if let Err(e) = sender.send(message) {
+                    log_error!(error = %e, "Failed to send message to channel");
+                }
+            }
+            Err(_) => {
+                metrics().parse_failed(Self::EXCHANGE);
+                log_error!(price = %price, "Failed to parse price");
+            }
+        }
+    }
+
+    fn reconnect(
+        symbols: &[String],
+        shutdown: &Arc<AtomicBool>,
+        reconnect_attempts: &mut u32,
+        max_reconnect_attempts: u32,
+    ) -> Result<WebSocket<MaybeTlsStream<TcpStream>>, String> {
+        if shutdown.load(Ordering::Relaxed) {
+            log_info!("Shutdown requested, no reconnection");
+            return Err("Shutdown requested".to_string());
+        }
+
+        while *reconnect_attempts < max_reconnect_attempts {
+            *reconnect_attempts += 1;
+            metrics().reconnecting(Self::EXCHANGE);
+            log_info!(attempt = *reconnect_attempts, max_attempts = max_reconnect_attempts, "Reconnecting");
+
+            // Wait before reconnecting (exponential backoff)
+            let delay = std::time::Duration::from_millis(1000 * *reconnect_attempts as u64);
+            std::thread::sleep(delay);
+
+            if let Ok(socket) = Self::connect_and_subscribe(symbols) {
+                log_info!(attempt = *reconnect_attempts, "Reconnected");
+                return Ok(socket);
+            }
+        }
+
+        log_error!(max_attempts = max_reconnect_attempts, "Max reconnection attempts reached, giving up");
+        Err("Max reconnection attempts reached".to_string())
+    }
+
+    /// Gracefully shutdown the connection and the streaming thread
+    pub fn shutdown(&mut self) {
+        let _connector = self.span.clone().entered();
+        log_info!("Initiating graceful shutdown");
+        self.shutdown.store(true, Ordering::Relaxed);
+
+        if let Some(mut socket) = self.socket.take()
+            && let Err(e) = socket.close(None)
+        {
+            log_warn!(error = %e, "Error closing socket");
+        }
+
+        if let Some(handle) = self.socket_thread.take() {
+            match handle.join() {
+                Ok(Ok(_)) => log_debug!("Thread completed successfully"),
+                Ok(Err(e)) => log_warn!(error = %e, "Thread ended with error"),
+                Err(e) => log_error!(error = ?e, "Error joining thread"),
+            }
+        }
+
+        log_info!("Shutdown completed");
+    }
+}
+
+impl Default for GeminiContainer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ISocketContainer for GeminiContainer {
+    fn exchange(&self) -> &'static str {
+        Self::EXCHANGE
+    }
+
+    fn add_symbol(&mut self, symbol: &str) -> Result<(), String> {
+        GeminiContainer::add_symbol(self, symbol);
+        Ok(())
+    }
+
+    fn set_recorder(&mut self, recorder: FrameRecorder) {
+        self.recorder = Some(recorder);
+    }
+
+    fn symbols(&self) -> Vec<String> {
+        self.symbols.clone()
+    }
+
+    fn start_monitoring(&mut self) -> Result<(), String> {
+        GeminiContainer::start_monitoring(self)
+    }
+
+    fn shutdown(&mut self) {
+        GeminiContainer::shutdown(self)
+    }
+}
+
+/// Implement Drop trait for graceful cleanup
+impl Drop for GeminiContainer {
+    fn drop(&mut self) {
+        log_info!("GeminiContainer is being dropped, initiating cleanup");
+        self.shutdown();
+    }
+}
@@ -1,3 +1,7 @@
+#[allow(clippy::module_inception)]
 pub mod socket_container;
 pub mod binance_container;
-pub mod coinbase_container;
\ No newline at end of file
+pub mod coinbase_container;
+pub mod gemini_container;
+pub mod bitstamp_container;
+pub mod replay_container;
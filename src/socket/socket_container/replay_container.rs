@@ -0,0 +1,450 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+use crate::models::SymbolMessage;
+use crate::orderbook::{
+    binance::{BinanceBookSync, DepthSnapshot},
+    bitstamp::BitstampBookSync,
+    coinbase::CoinbaseBookSync,
+    gemini::GeminiBookSync,
+    OrderBookStore,
+};
+use crate::recorder::{read_frames, recording_files, FrameKind, FrameRecorder, RecordedFrame};
+use crate::socket::socket_container::{
+    binance_container::BinanceContainer,
+    bitstamp_container::BitstampContainer,
+    coinbase_container::CoinBaseContainer,
+    gemini_container::GeminiContainer,
+    socket_container::{ISocketContainer, READ_TIMEOUT},
+};
+use crate::{log_debug, log_error, log_info, log_warn};
+
+/// Pace at which recorded frames are re-emitted
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplaySpeed {
+    /// Keep the recorded gaps between frames
+    Original,
+    /// Divide the recorded gaps by the factor, `Scaled(10.0)` replays ten times faster
+    Scaled(f64),
+    /// No waiting at all
+    Max,
+}
+
+impl ReplaySpeed {
+    /// Parse `original`, `max` or a speed-up factor such as `10` or `10x`
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_lowercase().as_str() {
+            "original" | "1" | "1x" => Ok(ReplaySpeed::Original),
+            "max" => Ok(ReplaySpeed::Max),
+            other => other
+                .trim_end_matches('x')
+                .parse::<f64>()
+                .ok()
+                .filter(|factor| *factor > 0.0)
+                .map(ReplaySpeed::Scaled)
+                .ok_or_else(|| format!("Invalid replay speed '{}', expected original, max or a factor such as 10x", value)),
+        }
+    }
+
+    fn factor(&self) -> Option<f64> {
+        match self {
+            ReplaySpeed::Original => Some(1.0),
+            ReplaySpeed::Scaled(factor) => Some(*factor),
+            ReplaySpeed::Max => None,
+        }
+    }
+}
+
+/// Totals of one replay run
+#[derive(Debug, Clone, Default)]
+pub struct ReplayStats {
+    pub frames: u64,
+    pub messages: u64,
+    /// Frames of exchanges the replay has no parser for
+    pub skipped_frames: u64,
+    pub first_frame_at: Option<DateTime<Utc>>,
+    pub last_frame_at: Option<DateTime<Utc>>,
+}
+
+/// Parsing state of one recorded connection, rebuilt for every connection id
+/// just like a live connector resets its books on reconnect
+enum ConnectionParser {
+    /// Book syncs per symbol, Binance streams one symbol per connection
+    Binance(HashMap<String, BinanceBookSync>),
+    Coinbase(Option<CoinbaseBookSync>),
+    Gemini(Option<GeminiBookSync>),
+    Bitstamp(Option<BitstampBookSync>),
+    Unsupported,
+}
+
+impl ConnectionParser {
+    fn new(exchange: &str, order_books: Option<OrderBookStore>) -> Self {
+        match exchange {
+            BinanceContainer::EXCHANGE => ConnectionParser::Binance(HashMap::new()),
+            CoinBaseContainer::EXCHANGE => ConnectionParser::Coinbase(
+                order_books.map(|store| CoinbaseBookSync::new(exchange, store).without_resync()),
+            ),
+            GeminiContainer::EXCHANGE => ConnectionParser::Gemini(
+                order_books.map(|store| GeminiBookSync::new(exchange, store)),
+            ),
+            BitstampContainer::EXCHANGE => ConnectionParser::Bitstamp(
+                order_books.map(|store| BitstampBookSync::new(exchange, store)),
+            ),
+            _ => {
+                log_warn!(exchange = %exchange, "No parser for the frames, skipping them");
+                ConnectionParser::Unsupported
+            }
+        }
+    }
+}
+
+/// Feeds recorded frames back through the connectors' parsing code.
+///
+/// Every price update is stamped with the receive time of the frame carrying
+/// it, and the books are maintained exactly as the live connectors do, except
+/// that no REST request is ever made: Binance depth snapshots are taken from the
+/// recording, and Coinbase and Gemini books that cross stay empty until the
+/// feed sends a new full book.
+pub struct Replayer {
+    files: Vec<PathBuf>,
+    speed: ReplaySpeed,
+    order_books: Option<OrderBookStore>,
+    shutdown: Arc<AtomicBool>,
+    connections: HashMap<String, ConnectionParser>,
+}
+
+impl Replayer {
+    /// Replay the recording files found at `path`, a directory or a single file
+    pub fn new(path: impl Into<PathBuf>) -> Result<Self, String> {
+        let path = path.into();
+        let files = recording_files(&path).map_err(|e| format!("Cannot list recordings in {}: {}", path.display(), e))?;
+        if files.is_empty() {
+            return Err(format!("No recording found in {}", path.display()));
+        }
+
+        Ok(Replayer {
+            files,
+            speed: ReplaySpeed::Max,
+            order_books: None,
+            shutdown: Arc::new(AtomicBool::new(false)),
+            connections: HashMap::new(),
+        })
+    }
+
+    pub fn with_speed(mut self, speed: ReplaySpeed) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    /// Rebuild the order books from the recorded depth frames into the given store
+    pub fn with_order_books(mut self, order_books: OrderBookStore) -> Self {
+        self.order_books = Some(order_books);
+        self
+    }
+
+    /// Stop the replay as soon as the flag is set
+    pub fn with_shutdown(mut self, shutdown: Arc<AtomicBool>) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// Replay every frame in order, handing each price update to `on_message`.
+    /// The run stops early when `on_message` fails or shutdown is requested.
+    pub fn run<F>(&mut self, mut on_message: F) -> Result<ReplayStats, String>
+    where
+        F: FnMut(SymbolMessage) -> Result<(), String>,
+    {
+        let (sender, receiver) = mpsc::channel();
+        let sender = Arc::new(sender);
+        let mut stats = ReplayStats::default();
+        let started_at = Instant::now();
+
+        for file in self.files.clone() {
+            log_info!(file = %file.display(), "Replaying");
+            let frames = read_frames(&file).map_err(|e| format!("Cannot open {}: {}", file.display(), e))?;
+
+            for frame in frames {
+                let frame = match frame {
+                    Ok(frame) => frame,
+                    Err(e) => {
+                        log_warn!(file = %file.display(), error = %e, "Stopping at an unreadable frame");
+                        break;
+                    }
+                };
+                if self.shutdown.load(Ordering::Relaxed) {
+                    log_info!("Shutdown requested, stopping replay");
+                    return Ok(stats);
+                }
+
+                let first_frame_at = *stats.first_frame_at.get_or_insert(frame.received_at);
+                if !self.wait_until(first_frame_at, frame.received_at, started_at) {
+                    return Ok(stats);
+                }
+                stats.frames += 1;
+                stats.last_frame_at = Some(frame.received_at);
+
+                if !self.dispatch(&frame, Instant::now(), &sender) {
+                    stats.skipped_frames += 1;
+                    continue;
+                }
+                // The parsers stamp the wall clock, the exchange latency must compare the recorded times
+                for mut message in receiver.try_iter() {
+                    message.received_at = frame.received_at;
+                    stats.messages += 1;
+                    on_message(message)?;
+                }
+            }
+        }
+
+        log_info!(
+            frames = stats.frames,
+            skipped_frames = stats.skipped_frames,
+            messages = stats.messages,
+            "Replay finished"
+        );
+        Ok(stats)
+    }
+
+    /// Sleep until the frame is due, returns `false` if shutdown was requested meanwhile
+    fn wait_until(&self, first_frame_at: DateTime<Utc>, received_at: DateTime<Utc>, started_at: Instant) -> bool {
+        let Some(factor) = self.speed.factor() else {
+            return true;
+        };
+
+        let offset = (received_at - first_frame_at).to_std().unwrap_or_default();
+        let due = started_at + offset.div_f64(factor);
+        loop {
+            if self.shutdown.load(Ordering::Relaxed) {
+                return false;
+            }
+            let now = Instant::now();
+            if now >= due {
+                return true;
+            }
+            thread::sleep((due - now).min(READ_TIMEOUT));
+        }
+    }
+
+    /// Run a frame read at `read_at` through the parser of its connection, returns `false` if it was skipped
+    fn dispatch(&mut self, frame: &RecordedFrame, read_at: Instant, sender: &Arc<Sender<SymbolMessage>>) -> bool {
+        let order_books = self.order_books.clone();
+        let parser = self.connections
+            .entry(frame.connection_id.clone())
+            .or_insert_with(|| ConnectionParser::new(&frame.exchange, order_books.clone()));
+
+        match parser {
+            ConnectionParser::Binance(syncs) => {
+                let Ok(json) = serde_json::from_str::<Value>(&frame.frame) else {
+                    log_debug!(frame = %frame.frame, "Unparsable Binance frame");
+                    return true;
+                };
+                let Some(symbol) = Self::binance_symbol(frame.kind, &json) else {
+                    log_debug!(frame = %frame.frame, "Binance frame without symbol");
+                    return true;
+                };
+                let sync = order_books.map(|store| {
+                    syncs
+                        .entry(symbol.clone())
+                        .or_insert_with(|| BinanceBookSync::new(BinanceContainer::EXCHANGE, &symbol, store).without_snapshot_fetch())
+                });
+
+                match frame.kind {
+                    FrameKind::Snapshot => {
+                        if let (Some(sync), Some(snapshot)) = (sync, DepthSnapshot::from_json(&json["data"])) {
+                            sync.apply_snapshot(snapshot);
+                        }
+                    }
+                    FrameKind::WebSocket => {
+                        if let Err(e) = BinanceContainer::on_message(&frame.frame, read_at, &symbol, sender, sync, None) {
+                            log_error!(error = %e, "Cannot replay the frame");
+                        }
+                    }
+                }
+            }
+            ConnectionParser::Coinbase(sync) => {
+                CoinBaseContainer::on_message(frame.frame.clone(), read_at, sender, sync.as_mut());
+            }
+            ConnectionParser::Gemini(sync) => {
+                GeminiContainer::on_message(&frame.frame, read_at, sender, sync.as_mut());
+            }
+            ConnectionParser::Bitstamp(sync) => {
+                BitstampContainer::on_message(&frame.frame, read_at, sender, sync.as_mut());
+            }
+            ConnectionParser::Unsupported => return false,
+        }
+        true
+    }
+
+    /// Uppercase symbol of a Binance frame: the combined stream name, the event
+    /// symbol, or the symbol a recorded snapshot was fetched for
+    fn binance_symbol(kind: FrameKind, json: &Value) -> Option<String> {
+        let symbol = match kind {
+            FrameKind::Snapshot => json["symbol"].as_str(),
+            FrameKind::WebSocket => json["stream"]
+                .as_str()
+                .and_then(|stream| stream.split('@').next())
+                .or_else(|| json["data"]["s"].as_str())
+                .or_else(|| json["s"].as_str()),
+        };
+        symbol.map(str::to_uppercase)
+    }
+}
+
+/// Connector replaying a recording instead of connecting to an exchange, so
+/// that the consumer and the detectors run exactly as they do live.
+///
+/// Symbols added to the container restrict the replayed price updates, all of
+/// them are replayed when none is added. Because frames are handed to the
+/// consumer through its channel, the books may run ahead of the consumer at
+/// high speeds; drive `SocketConsumer::process_message` from `Replayer::run`
+/// directly for fully deterministic runs.
+pub struct ReplayContainer {
+    replayer: Option<Replayer>,
+    sender: Arc<Sender<SymbolMessage>>,
+    symbols: Vec<String>,
+    shutdown: Arc<AtomicBool>,
+    socket_thread: Option<JoinHandle<Result<(), String>>>,
+}
+
+impl ReplayContainer {
+    pub const EXCHANGE: &'static str = "replay";
+
+    /// Create a container that publishes the replayed price updates to the given channel
+    pub fn new_with_sender(sender: Arc<Sender<SymbolMessage>>, replayer: Replayer) -> Self {
+        let shutdown = Arc::new(AtomicBool::new(false));
+        ReplayContainer {
+            replayer: Some(replayer.with_shutdown(Arc::clone(&shutdown))),
+            sender,
+            symbols: Vec::new(),
+            shutdown,
+            socket_thread: None,
+        }
+    }
+
+    pub fn add_symbol(&mut self, symbol: &str) {
+        let symbol = symbol.replace("-", "").to_uppercase();
+        if !self.symbols.contains(&symbol) {
+            self.symbols.push(symbol);
+        }
+    }
+
+    pub fn start_monitoring(&mut self) -> Result<(), String> {
+        let Some(mut replayer) = self.replayer.take() else {
+            log_debug!("Replay already started");
+            return Ok(());
+        };
+
+        let sender = Arc::clone(&self.sender);
+        let symbols = self.symbols.clone();
+        let handle = thread::spawn(move || {
+            replayer
+                .run(|message| {
+                    if !symbols.is_empty() && !symbols.contains(&message.symbol) {
+                        return Ok(());
+                    }
+                    sender.send(message).map_err(|e| format!("Channel send error: {}", e))
+                })
+                .map(|_| ())
+        });
+
+        self.socket_thread = Some(handle);
+        Ok(())
+    }
+
+    /// Stop the replay and wait for its thread
+    pub fn shutdown(&mut self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.socket_thread.take() {
+            match handle.join() {
+                Ok(Ok(_)) => log_debug!("Replay thread completed successfully"),
+                Ok(Err(e)) => log_warn!(error = %e, "Replay thread ended with error"),
+                Err(e) => log_error!(error = ?e, "Error joining replay thread"),
+            }
+        }
+    }
+}
+
+impl ISocketContainer for ReplayContainer {
+    fn exchange(&self) -> &'static str {
+        Self::EXCHANGE
+    }
+
+    fn add_symbol(&mut self, symbol: &str) -> Result<(), String> {
+        ReplayContainer::add_symbol(self, symbol);
+        Ok(())
+    }
+
+    fn set_recorder(&mut self, _recorder: FrameRecorder) {
+        log_warn!("Replayed frames are not recorded again");
+    }
+
+    fn symbols(&self) -> Vec<String> {
+        self.symbols.clone()
+    }
+
+    fn start_monitoring(&mut self) -> Result<(), String> {
+        ReplayContainer::start_monitoring(self)
+    }
+
+    fn shutdown(&mut self) {
+        ReplayContainer::shutdown(self)
+    }
+}
+
+impl Drop for ReplayContainer {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+
+    use super::*;
+
+    #[test]
+    fn stamps_replayed_ticks_with_the_recorded_receive_time() {
+        let directory = std::env::temp_dir().join(format!("replay-test-{}", std::process::id()));
+        let recorder = FrameRecorder::new(&directory).unwrap();
+        let received_at = DateTime::from_timestamp_millis(1_767_225_600_250).unwrap();
+        let frame = r#"{"stream":"btcusdt@ticker","data":{"e":"24hrTicker","E":1767225600000,"s":"BTCUSDT","c":"100.5"}}"#;
+        recorder.record(&RecordedFrame {
+            exchange: BinanceContainer::EXCHANGE.to_string(),
+            connection_id: "1".to_string(),
+            received_at,
+            kind: FrameKind::WebSocket,
+            frame: frame.to_string(),
+        });
+        recorder.close();
+
+        let mut messages = Vec::new();
+        let stats = Replayer::new(&directory)
+            .unwrap()
+            .run(|message| {
+                messages.push(message);
+                Ok(())
+            })
+            .unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!((stats.frames, stats.messages), (1, 1));
+        let message = &messages[0];
+        assert_eq!((message.symbol.as_str(), message.price), ("BTCUSDT", 100.5));
+        assert_eq!(message.received_at, received_at);
+        // The exchange latency observed is the recorded one, not the age of the recording
+        assert_eq!(message.received_at - message.exchange_time.unwrap(), TimeDelta::milliseconds(250));
+    }
+}
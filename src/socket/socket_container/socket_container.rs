@@ -1,7 +1,71 @@
-pub trait ISocketContainer {
-    fn intialize(&self);
-    fn connect(&self);
-    fn disconnect(&self);
-    fn add_symbol(&self);
-    fn remove_symbol(&self);
+use std::{io::ErrorKind, net::TcpStream, sync::atomic::{AtomicU64, Ordering}, time::Duration};
+use tracing::{info_span, Span};
+use tungstenite::{stream::MaybeTlsStream, WebSocket};
+use crate::log_warn;
+use crate::recorder::FrameRecorder;
+
+/// Common interface implemented by every exchange connector so that venues can
+/// be driven interchangeably by the consumer and the detector.
+pub trait ISocketContainer: Send {
+    /// Exchange name attached to every `SymbolMessage` emitted by the container
+    fn exchange(&self) -> &'static str;
+
+    /// Register a symbol using the exchange's native notation (e.g. `btcusdt`, `BTC-USD`)
+    fn add_symbol(&mut self, symbol: &str) -> Result<(), String>;
+
+    /// Record every raw frame received from now on, set it before adding symbols
+    /// to capture the first connections
+    fn set_recorder(&mut self, recorder: FrameRecorder);
+
+    /// Symbols currently tracked by the container
+    fn symbols(&self) -> Vec<String>;
+
+    /// Open the connection(s) and start streaming data for every registered symbol
+    fn start_monitoring(&mut self) -> Result<(), String>;
+
+    /// Gracefully close connections and stop the streaming threads
+    fn shutdown(&mut self);
+}
+
+/// How long a blocking read may wait before the streaming thread re-checks its shutdown flag
+pub(crate) const READ_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Make reads on the socket return periodically so streaming threads can observe shutdown
+pub(crate) fn set_read_timeout(socket: &WebSocket<MaybeTlsStream<TcpStream>>, timeout: Duration) {
+    let result = match socket.get_ref() {
+        MaybeTlsStream::Plain(stream) => stream.set_read_timeout(Some(timeout)),
+        MaybeTlsStream::NativeTls(stream) => stream.get_ref().set_read_timeout(Some(timeout)),
+        _ => Ok(()),
+    };
+
+    if let Err(e) = result {
+        log_warn!(error = %e, "Failed to set socket read timeout");
+    }
+}
+
+/// Whether a read error only means the read timeout elapsed without data
+pub(crate) fn is_read_timeout(err: &tungstenite::Error) -> bool {
+    matches!(
+        err,
+        tungstenite::Error::Io(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+    )
+}
+
+/// Ids of the streaming connections, unique in the process
+static CONNECTION_IDS: AtomicU64 = AtomicU64::new(1);
+
+/// Span of a connector, the parent of the spans of its connections
+pub(crate) fn connector_span(exchange: &'static str) -> Span {
+    info_span!("connector", exchange)
+}
+
+/// Span of one streaming connection of a connector and the symbols it carries
+pub(crate) fn connection_span(connector: &Span, symbols: &[String]) -> Span {
+    let id = CONNECTION_IDS.fetch_add(1, Ordering::Relaxed);
+    info_span!(parent: connector, "connection", id, symbols = %symbols.join(","))
+}
+
+/// Span of the handling of one update of a symbol
+pub(crate) fn symbol_span(symbol: &str) -> Span {
+    info_span!("symbol", symbol)
 }
@@ -0,0 +1,440 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use serde::Serialize;
+
+use crate::models::{CurrencyPair, SymbolMessage};
+use crate::orderbook::OrderBook;
+use crate::{log_debug, log_info, log_warn};
+
+/// How close consecutive outliers must be to each other to re-anchor the window
+/// when the percentage check is disabled
+const DEFAULT_AGREEMENT_PERCENTAGE: f64 = 1.0;
+
+/// Why a tick was dropped before reaching the detectors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TickRejection {
+    /// Zero or negative price
+    NonPositive,
+    /// NaN or infinite price
+    NotFinite,
+    /// Further than the allowed percentage from the rolling cross-venue median
+    Deviation,
+    /// More than the allowed number of standard deviations from the rolling cross-venue median
+    Sigma,
+}
+
+/// How many ticks of one exchange were rejected, per reason
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct TickRejectionCounters {
+    pub non_positive: u64,
+    pub not_finite: u64,
+    pub deviation: u64,
+    pub sigma: u64,
+    /// Detector evaluations that left out a book whose top is an outlier
+    pub books: u64,
+}
+
+impl TickRejectionCounters {
+    pub fn record(&mut self, rejection: TickRejection) {
+        match rejection {
+            TickRejection::NonPositive => self.non_positive += 1,
+            TickRejection::NotFinite => self.not_finite += 1,
+            TickRejection::Deviation => self.deviation += 1,
+            TickRejection::Sigma => self.sigma += 1,
+        }
+    }
+
+    /// Rejected ticks, books excluded
+    pub fn total(&self) -> u64 {
+        self.non_positive + self.not_finite + self.deviation + self.sigma
+    }
+}
+
+#[derive(Debug, Default)]
+struct FilterState {
+    /// Instrument (e.g. `BTCUSD`) => accepted prices of every venue, oldest first
+    windows: HashMap<String, VecDeque<(DateTime<Utc>, f64)>>,
+    /// Instrument => outliers rejected since its last accepted price, oldest first
+    outliers: HashMap<String, VecDeque<(DateTime<Utc>, f64)>>,
+    /// Instruments whose window restarted from their outliers and has not refilled to `min_samples` yet
+    reanchored: HashSet<String>,
+    rejections: HashMap<String, TickRejectionCounters>,
+}
+
+/// Drops bad prints before they reach the detectors.
+///
+/// Every accepted price joins a rolling window of its instrument shared by all
+/// venues quoting it; a new price is rejected when it is not a positive finite
+/// number, or when it lies too far from the median of that window, either in
+/// percent or in standard deviations. Rejected prices never enter the window.
+/// After a genuine move every venue is rejected at once: when the last
+/// `reanchor_ticks` outliers of an instrument, with no accepted price in
+/// between, agree with each other, the window restarts from them and the move
+/// is accepted. Until the window refills, prices are checked against those
+/// `reanchor_ticks` outliers. An isolated bad print is followed by accepted
+/// prices from the other venues, which resets the count.
+///
+/// The detectors also check the top of every book with `check_book`, so a bad
+/// quote in a book cannot open an opportunity either.
+#[derive(Debug, Clone)]
+pub struct TickFilter {
+    state: Arc<RwLock<FilterState>>,
+    window: Duration,
+    max_samples: usize,
+    min_samples: usize,
+    max_deviation_percentage: Option<f64>,
+    max_sigmas: Option<f64>,
+    reanchor_ticks: usize,
+}
+
+impl TickFilter {
+    pub fn new() -> Self {
+        TickFilter {
+            state: Arc::new(RwLock::new(FilterState::default())),
+            window: Duration::from_secs(60),
+            max_samples: 500,
+            min_samples: 10,
+            max_deviation_percentage: Some(5.0),
+            max_sigmas: None,
+            reanchor_ticks: 5,
+        }
+    }
+
+    /// History the rolling median is computed over
+    pub fn with_window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Samples required in the window before the deviation checks apply
+    pub fn with_min_samples(mut self, min_samples: usize) -> Self {
+        self.min_samples = min_samples.max(1);
+        self
+    }
+
+    /// Reject prices further than this percentage from the median, `None` disables the check
+    pub fn with_max_deviation_percentage(mut self, max_deviation_percentage: Option<f64>) -> Self {
+        self.max_deviation_percentage = max_deviation_percentage;
+        self
+    }
+
+    /// Reject prices more than this many standard deviations from the median, `None` disables the check
+    pub fn with_max_sigmas(mut self, max_sigmas: Option<f64>) -> Self {
+        self.max_sigmas = max_sigmas;
+        self
+    }
+
+    /// Consecutive agreeing outliers that re-anchor the window on a new price level, 0 never re-anchors
+    pub fn with_reanchor_ticks(mut self, reanchor_ticks: usize) -> Self {
+        self.reanchor_ticks = reanchor_ticks;
+        self
+    }
+
+    /// Check a tick, recording it in its instrument's window when accepted
+    pub fn check(&self, message: &SymbolMessage) -> Result<(), TickRejection> {
+        let result = self.evaluate(message);
+        if let Err(rejection) = result {
+            log_warn!(
+                exchange = %message.exchange,
+                symbol = %message.symbol,
+                price = message.price,
+                rejection = ?rejection,
+                "Rejected tick"
+            );
+            self.state
+                .write()
+                .unwrap()
+                .rejections
+                .entry(message.exchange.clone())
+                .or_default()
+                .record(rejection);
+        }
+        result
+    }
+
+    /// Check the top of a book against its instrument's window without recording
+    /// it. Only the percentage check applies to the mid price, the spread alone
+    /// can be several standard deviations of a quiet market's prices.
+    pub fn check_book(&self, book: &OrderBook) -> Result<(), TickRejection> {
+        let result = self.evaluate_book(book);
+        if let Err(rejection) = result {
+            log_debug!(exchange = %book.exchange, symbol = %book.symbol, rejection = ?rejection, "Skipping the book");
+            self.state
+                .write()
+                .unwrap()
+                .rejections
+                .entry(book.exchange.clone())
+                .or_default()
+                .books += 1;
+        }
+        result
+    }
+
+    /// Rejected tick counters per exchange
+    pub fn rejection_counters(&self) -> HashMap<String, TickRejectionCounters> {
+        self.state.read().unwrap().rejections.clone()
+    }
+
+    fn evaluate(&self, message: &SymbolMessage) -> Result<(), TickRejection> {
+        let price = message.price;
+        Self::check_price(price)?;
+
+        let instrument = Self::instrument(&message.symbol);
+        let now = message.received_at;
+        let mut state = self.state.write().unwrap();
+        let min_samples = self.min_samples(&state, &instrument);
+        let window = state.windows.entry(instrument.clone()).or_default();
+        while window.front().is_some_and(|(received_at, _)| (now - *received_at).to_std().unwrap_or_default() > self.window) {
+            window.pop_front();
+        }
+
+        if window.len() >= min_samples
+            && let Some(rejection) = self.outlier(window, price, true)
+        {
+            if !self.reanchor(&mut state, &instrument, now, price) {
+                return Err(rejection);
+            }
+            log_info!(
+                instrument = %instrument,
+                price,
+                outliers = self.reanchor_ticks,
+                "Re-anchored after consecutive agreeing outliers"
+            );
+            return Ok(());
+        }
+
+        window.push_back((now, price));
+        if window.len() > self.max_samples {
+            window.pop_front();
+        }
+        if window.len() >= self.min_samples {
+            state.reanchored.remove(&instrument);
+        }
+        state.outliers.remove(&instrument);
+        Ok(())
+    }
+
+    fn evaluate_book(&self, book: &OrderBook) -> Result<(), TickRejection> {
+        // An empty side sizes nothing
+        let (Some(bid), Some(ask)) = (book.best_bid(), book.best_ask()) else {
+            return Ok(());
+        };
+        Self::check_price(bid.price)?;
+        Self::check_price(ask.price)?;
+
+        let state = self.state.read().unwrap();
+        let instrument = Self::instrument(&book.symbol);
+        match state.windows.get(&instrument) {
+            Some(window) if window.len() >= self.min_samples(&state, &instrument) => {
+                self.outlier(window, (bid.price + ask.price) / 2.0, false).map_or(Ok(()), Err)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Samples required before checking prices of the instrument, fewer while a re-anchored window refills
+    fn min_samples(&self, state: &FilterState, instrument: &str) -> usize {
+        if state.reanchored.contains(instrument) {
+            self.reanchor_ticks.clamp(1, self.min_samples)
+        } else {
+            self.min_samples
+        }
+    }
+
+    fn check_price(price: f64) -> Result<(), TickRejection> {
+        if !price.is_finite() {
+            return Err(TickRejection::NotFinite);
+        }
+        if price <= 0.0 {
+            return Err(TickRejection::NonPositive);
+        }
+        Ok(())
+    }
+
+    fn instrument(symbol: &str) -> String {
+        CurrencyPair::parse(symbol).map_or_else(|| symbol.to_string(), |pair| pair.symbol())
+    }
+
+    /// Why `price` is too far from the median of the window, if it is
+    fn outlier(&self, window: &VecDeque<(DateTime<Utc>, f64)>, price: f64, with_sigmas: bool) -> Option<TickRejection> {
+        let mut prices: Vec<f64> = window.iter().map(|(_, price)| *price).collect();
+        prices.sort_by(f64::total_cmp);
+        let median = Self::median(&prices);
+        let distance = (price - median).abs();
+
+        if let Some(max_deviation) = self.max_deviation_percentage
+            && distance / median * 100.0 > max_deviation
+        {
+            return Some(TickRejection::Deviation);
+        }
+
+        if let Some(max_sigmas) = self.max_sigmas.filter(|_| with_sigmas) {
+            let mean = prices.iter().sum::<f64>() / prices.len() as f64;
+            let variance = prices.iter().map(|p| (p - mean).powi(2)).sum::<f64>() / prices.len() as f64;
+            let sigma = variance.sqrt();
+            if sigma > 0.0 && distance > max_sigmas * sigma {
+                return Some(TickRejection::Sigma);
+            }
+        }
+        None
+    }
+
+    /// Add a rejected price to the consecutive outliers of the instrument and,
+    /// once enough of them agree, restart the window from them. Returns whether it did.
+    fn reanchor(&self, state: &mut FilterState, instrument: &str, now: DateTime<Utc>, price: f64) -> bool {
+        if self.reanchor_ticks == 0 {
+            return false;
+        }
+
+        let outliers = state.outliers.entry(instrument.to_string()).or_default();
+        outliers.retain(|(received_at, _)| (now - *received_at).to_std().unwrap_or_default() <= self.window);
+        let mut prices: Vec<f64> = outliers.iter().map(|(_, price)| *price).collect();
+        prices.sort_by(f64::total_cmp);
+        // A price away from the previous outliers starts a new streak
+        let agreement = self.max_deviation_percentage.unwrap_or(DEFAULT_AGREEMENT_PERCENTAGE);
+        if !prices.is_empty() {
+            let median = Self::median(&prices);
+            if (price - median).abs() / median * 100.0 > agreement {
+                outliers.clear();
+            }
+        }
+        outliers.push_back((now, price));
+        if outliers.len() < self.reanchor_ticks {
+            return false;
+        }
+
+        let outliers = state.outliers.remove(instrument).unwrap_or_default();
+        state.windows.insert(instrument.to_string(), outliers);
+        state.reanchored.insert(instrument.to_string());
+        true
+    }
+
+    fn median(sorted: &[f64]) -> f64 {
+        let middle = sorted.len() / 2;
+        if sorted.len().is_multiple_of(2) {
+            (sorted[middle - 1] + sorted[middle]) / 2.0
+        } else {
+            sorted[middle]
+        }
+    }
+}
+
+impl Default for TickFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+
+    use super::*;
+    use crate::orderbook::Level;
+
+    fn tick(exchange: &str, price: f64, at: DateTime<Utc>) -> SymbolMessage {
+        let mut message = SymbolMessage::new(exchange, "BTC-USD".to_string(), price);
+        message.received_at = at;
+        message
+    }
+
+    /// A filter whose BTCUSD window holds ten prices around 100
+    fn seeded(filter: TickFilter, start: DateTime<Utc>) -> TickFilter {
+        for i in 0..10 {
+            let price = 99.8 + i as f64 * 0.05;
+            assert_eq!(filter.check(&tick("coinbase", price, start + TimeDelta::milliseconds(i))), Ok(()));
+        }
+        filter
+    }
+
+    fn book(bid: f64, ask: f64) -> OrderBook {
+        let mut book = OrderBook::new("gemini", "BTCUSD");
+        book.apply_snapshot(&[Level::new(bid, 1.0)], &[Level::new(ask, 1.0)], 1);
+        book
+    }
+
+    #[test]
+    fn rejects_non_positive_and_non_finite_prices() {
+        let filter = TickFilter::new();
+        let now = Utc::now();
+        assert_eq!(filter.check(&tick("binance", 0.0, now)), Err(TickRejection::NonPositive));
+        assert_eq!(filter.check(&tick("binance", -1.0, now)), Err(TickRejection::NonPositive));
+        assert_eq!(filter.check(&tick("binance", f64::NAN, now)), Err(TickRejection::NotFinite));
+        assert_eq!(filter.check(&tick("binance", f64::INFINITY, now)), Err(TickRejection::NotFinite));
+        assert_eq!(filter.rejection_counters()["binance"].total(), 4);
+    }
+
+    #[test]
+    fn rejects_an_isolated_bad_print_and_keeps_accepting_the_market() {
+        let start = Utc::now();
+        let filter = seeded(TickFilter::new(), start);
+        for i in 0..20 {
+            let at = start + TimeDelta::seconds(1 + i);
+            assert_eq!(filter.check(&tick("binance", 1.0, at)), Err(TickRejection::Deviation));
+            assert_eq!(filter.check(&tick("coinbase", 100.0, at)), Ok(()));
+        }
+        assert_eq!(filter.rejection_counters()["binance"].deviation, 20);
+    }
+
+    #[test]
+    fn re_anchors_after_consecutive_agreeing_outliers() {
+        let start = Utc::now();
+        let filter = seeded(TickFilter::new(), start);
+        for i in 0..4 {
+            let at = start + TimeDelta::seconds(1 + i);
+            let exchange = if i % 2 == 0 { "binance" } else { "gemini" };
+            assert_eq!(filter.check(&tick(exchange, 120.0 + i as f64 * 0.1, at)), Err(TickRejection::Deviation));
+        }
+        assert_eq!(filter.check(&tick("coinbase", 120.2, start + TimeDelta::seconds(5))), Ok(()));
+
+        // The window restarted at the new level and keeps checking against it while it refills
+        assert_eq!(filter.check(&tick("binance", 120.1, start + TimeDelta::seconds(6))), Ok(()));
+        assert_eq!(filter.check(&tick("binance", 100.0, start + TimeDelta::seconds(7))), Err(TickRejection::Deviation));
+        assert_eq!(filter.check_book(&book(99.9, 100.1)), Err(TickRejection::Deviation));
+        for i in 0..4 {
+            assert_eq!(filter.check(&tick("gemini", 120.0, start + TimeDelta::seconds(8 + i))), Ok(()));
+        }
+        assert_eq!(filter.check(&tick("binance", 100.0, start + TimeDelta::seconds(12))), Err(TickRejection::Deviation));
+    }
+
+    #[test]
+    fn outliers_away_from_each_other_do_not_re_anchor() {
+        let start = Utc::now();
+        let filter = seeded(TickFilter::new(), start);
+        for i in 0..10 {
+            let price = if i % 2 == 0 { 120.0 } else { 80.0 };
+            let at = start + TimeDelta::seconds(1 + i);
+            assert_eq!(filter.check(&tick("binance", price, at)), Err(TickRejection::Deviation));
+        }
+    }
+
+    #[test]
+    fn re_anchoring_can_be_disabled() {
+        let start = Utc::now();
+        let filter = seeded(TickFilter::new().with_reanchor_ticks(0), start);
+        for i in 0..10 {
+            let at = start + TimeDelta::seconds(1 + i);
+            assert_eq!(filter.check(&tick("binance", 120.0, at)), Err(TickRejection::Deviation));
+        }
+    }
+
+    #[test]
+    fn checks_the_top_of_book_against_the_window() {
+        let filter = TickFilter::new();
+        // Nothing to compare with yet
+        assert_eq!(filter.check_book(&book(1.0, 1.1)), Ok(()));
+
+        let filter = seeded(filter, Utc::now());
+        assert_eq!(filter.check_book(&book(99.9, 100.1)), Ok(()));
+        assert_eq!(filter.check_book(&book(1.0, 1.1)), Err(TickRejection::Deviation));
+        assert_eq!(filter.check_book(&OrderBook::new("gemini", "BTCUSD")), Ok(()));
+
+        let counters = filter.rejection_counters()["gemini"];
+        assert_eq!(counters.books, 1);
+        assert_eq!(counters.total(), 0);
+    }
+}
@@ -0,0 +1,207 @@
+pub mod opportunities;
+pub mod quotes;
+
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use rusqlite::Connection;
+
+use crate::log_info;
+
+/// Schema migrations, applied in order. The index of the last applied one is
+/// kept in SQLite's `user_version`, so a migration must never change once released.
+const MIGRATIONS: &[&str] = &[
+    // 1: opportunity lifecycle events
+    "CREATE TABLE opportunity_events (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        opportunity_id TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        symbol TEXT NOT NULL,
+        buy_exchange TEXT NOT NULL,
+        sell_exchange TEXT NOT NULL,
+        event_at INTEGER NOT NULL,
+        opened_at INTEGER NOT NULL,
+        closed_at INTEGER,
+        duration_ms INTEGER NOT NULL,
+        profit_percentage REAL NOT NULL,
+        peak_profit_percentage REAL NOT NULL,
+        expected_profit REAL NOT NULL,
+        opportunity TEXT NOT NULL
+    );
+    CREATE INDEX opportunity_events_event_at ON opportunity_events (event_at);
+    CREATE INDEX opportunity_events_symbol ON opportunity_events (symbol, event_at);
+    CREATE INDEX opportunity_events_opportunity ON opportunity_events (opportunity_id);",
+    // 2: tick and top of book history, `resolution_ms` is 0 for raw ticks
+    "CREATE TABLE quotes (
+        resolution_ms INTEGER NOT NULL,
+        exchange TEXT NOT NULL,
+        symbol TEXT NOT NULL,
+        at INTEGER NOT NULL,
+        price REAL NOT NULL,
+        bid REAL,
+        bid_quantity REAL,
+        ask REAL,
+        ask_quantity REAL,
+        samples INTEGER NOT NULL
+    );
+    CREATE INDEX quotes_market ON quotes (symbol, resolution_ms, at);
+    CREATE INDEX quotes_resolution ON quotes (resolution_ms, at);",
+    // 3: one downsampled quote per market and bucket, so buckets can be re-aggregated in place
+    "DELETE FROM quotes WHERE resolution_ms > 0 AND rowid NOT IN (
+        SELECT MAX(rowid) FROM quotes WHERE resolution_ms > 0 GROUP BY resolution_ms, exchange, symbol, at
+    );
+    CREATE UNIQUE INDEX quotes_bucket ON quotes (resolution_ms, exchange, symbol, at) WHERE resolution_ms > 0;",
+];
+
+/// Local SQLite database shared by the persistent stores
+#[derive(Clone)]
+pub struct Database {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl Database {
+    /// Open or create the database file and bring its schema up to date
+    pub fn open(path: impl AsRef<Path>) -> rusqlite::Result<Self> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        }
+
+        let connection = Connection::open(path)?;
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        connection.pragma_update(None, "synchronous", "NORMAL")?;
+        log_info!(path = %path.display(), "Opened database");
+        Self::from_connection(connection)
+    }
+
+    /// Database living in memory only, lost when dropped
+    pub fn in_memory() -> rusqlite::Result<Self> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(mut connection: Connection) -> rusqlite::Result<Self> {
+        Self::migrate(&mut connection)?;
+        Ok(Database {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    fn migrate(connection: &mut Connection) -> rusqlite::Result<()> {
+        let version: i64 = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+            let transaction = connection.transaction()?;
+            transaction.execute_batch(migration)?;
+            transaction.pragma_update(None, "user_version", index as i64 + 1)?;
+            transaction.commit()?;
+            log_info!(version = index + 1, "Applied migration");
+        }
+        Ok(())
+    }
+
+    /// Run `f` with exclusive access to the connection
+    pub fn with_connection<T>(&self, f: impl FnOnce(&mut Connection) -> rusqlite::Result<T>) -> rusqlite::Result<T> {
+        let mut connection = self.connection.lock().unwrap();
+        f(&mut connection)
+    }
+}
+
+impl std::fmt::Debug for Database {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let connection = self.connection.lock().unwrap();
+        f.debug_struct("Database").field("path", &connection.path()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(database: &Database) -> i64 {
+        database
+            .with_connection(|connection| connection.pragma_query_value(None, "user_version", |row| row.get(0)))
+            .unwrap()
+    }
+
+    fn count(connection: &Connection, table: &str) -> i64 {
+        connection.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn applies_every_migration_once() {
+        let database = Database::in_memory().unwrap();
+        assert_eq!(version(&database), MIGRATIONS.len() as i64);
+
+        database
+            .with_connection(|connection| {
+                assert_eq!(count(connection, "opportunity_events"), 0);
+                assert_eq!(count(connection, "quotes"), 0);
+                // Already up to date, a second run must not recreate the tables
+                Database::migrate(connection)
+            })
+            .unwrap();
+        assert_eq!(version(&database), MIGRATIONS.len() as i64);
+    }
+
+    #[test]
+    fn resumes_from_the_recorded_version() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection.execute_batch(MIGRATIONS[0]).unwrap();
+        connection.execute_batch(MIGRATIONS[1]).unwrap();
+        connection.pragma_update(None, "user_version", 2).unwrap();
+        // Duplicate downsampled quotes written before migration 3 made buckets unique
+        connection
+            .execute_batch(
+                "INSERT INTO quotes (resolution_ms, exchange, symbol, at, price, samples) VALUES
+                    (0, 'kraken', 'BTCUSD', 0, 100.0, 1),
+                    (0, 'kraken', 'BTCUSD', 0, 100.5, 1),
+                    (1000, 'kraken', 'BTCUSD', 0, 101.0, 1),
+                    (1000, 'kraken', 'BTCUSD', 0, 102.0, 2);",
+            )
+            .unwrap();
+
+        let database = Database::from_connection(connection).unwrap();
+
+        assert_eq!(version(&database), 3);
+        database
+            .with_connection(|connection| {
+                let prices: Vec<(i64, f64)> = connection
+                    .prepare("SELECT resolution_ms, price FROM quotes ORDER BY rowid")?
+                    .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                    .collect::<rusqlite::Result<_>>()?;
+                assert_eq!(prices, vec![(0, 100.0), (0, 100.5), (1000, 102.0)]);
+
+                let duplicate = connection.execute(
+                    "INSERT INTO quotes (resolution_ms, exchange, symbol, at, price, samples)
+                     VALUES (1000, 'kraken', 'BTCUSD', 0, 103.0, 1)",
+                    [],
+                );
+                assert!(duplicate.is_err());
+                Ok(())
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn creates_the_file_and_keeps_its_schema() {
+        let directory = std::env::temp_dir().join(format!("database-test-{}", std::process::id()));
+        let path = directory.join("nested").join("arbitrage.db");
+
+        let database = Database::open(&path).unwrap();
+        database
+            .with_connection(|connection| {
+                connection.execute_batch(
+                    "INSERT INTO quotes (resolution_ms, exchange, symbol, at, price, samples)
+                     VALUES (0, 'kraken', 'BTCUSD', 0, 100.0, 1)",
+                )
+            })
+            .unwrap();
+        drop(database);
+
+        let reopened = Database::open(&path).unwrap();
+        assert_eq!(version(&reopened), MIGRATIONS.len() as i64);
+        assert_eq!(reopened.with_connection(|connection| Ok(count(connection, "quotes"))).unwrap(), 1);
+
+        drop(reopened);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,449 @@
+use std::thread::{self, JoinHandle};
+
+use chrono::{DateTime, TimeZone, Utc};
+use rusqlite::{params, params_from_iter, types::Value};
+use serde::Deserialize;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::events::EventBus;
+use crate::models::{OpportunityEvent, OpportunityEventKind, OpportunityHistoryEntry, Page, StreamEvent};
+use crate::storage::Database;
+use crate::{log_error, log_info, log_warn};
+
+const DEFAULT_PAGE_SIZE: u32 = 100;
+const MAX_PAGE_SIZE: u32 = 1000;
+/// Events read per query of an export
+const EXPORT_BATCH_SIZE: usize = 10_000;
+
+/// Filters and pagination of an opportunity history query, all optional
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct OpportunityHistoryQuery {
+    /// Inclusive start of the event time range
+    pub from: Option<DateTime<Utc>>,
+    /// Exclusive end of the event time range
+    pub to: Option<DateTime<Utc>>,
+    pub symbol: Option<String>,
+    /// Either leg's venue
+    pub exchange: Option<String>,
+    pub buy_exchange: Option<String>,
+    pub sell_exchange: Option<String>,
+    pub kind: Option<OpportunityEventKind>,
+    /// Page size, 100 by default and at most 1000
+    pub limit: Option<u32>,
+    pub offset: Option<u64>,
+}
+
+/// Every opportunity lifecycle event, persisted to the database
+#[derive(Debug, Clone)]
+pub struct OpportunityHistory {
+    database: Database,
+}
+
+impl OpportunityHistory {
+    pub fn new(database: Database) -> Self {
+        OpportunityHistory { database }
+    }
+
+    /// Persist the opportunity events published on the bus from a background thread
+    pub fn record_events(&self, events: &EventBus) -> JoinHandle<()> {
+        let history = self.clone();
+        let mut receiver = events.subscribe();
+
+        thread::spawn(move || {
+            log_info!("Recording opportunity events");
+            loop {
+                match receiver.blocking_recv() {
+                    Ok(StreamEvent::Opportunity(event)) => {
+                        if let Err(e) = history.record(&event) {
+                            log_error!(opportunity_id = %event.opportunity.id, error = %e, "Cannot record opportunity event");
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(skipped)) => {
+                        log_warn!(skipped, "Opportunity writer lagging behind, events not recorded");
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        })
+    }
+
+    pub fn record(&self, event: &OpportunityEvent) -> rusqlite::Result<()> {
+        let opportunity = &event.opportunity;
+        let json = serde_json::to_string(opportunity).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        let event_at = opportunity.closed_at.unwrap_or(opportunity.updated_at);
+
+        self.database.with_connection(|connection| {
+            connection.execute(
+                "INSERT INTO opportunity_events (
+                    opportunity_id, kind, symbol, buy_exchange, sell_exchange, event_at, opened_at, closed_at,
+                    duration_ms, profit_percentage, peak_profit_percentage, expected_profit, opportunity
+                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+                params![
+                    opportunity.id,
+                    event.kind.name(),
+                    opportunity.symbol,
+                    opportunity.buy_exchange,
+                    opportunity.sell_exchange,
+                    event_at.timestamp_millis(),
+                    opportunity.opened_at.timestamp_millis(),
+                    opportunity.closed_at.map(|closed_at| closed_at.timestamp_millis()),
+                    opportunity.duration_ms,
+                    opportunity.current_profit_percentage,
+                    opportunity.peak_profit_percentage,
+                    opportunity.latest.expected_profit,
+                    json,
+                ],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Events matching the query, most recent first
+    pub fn query(&self, query: &OpportunityHistoryQuery) -> rusqlite::Result<Page<OpportunityHistoryEntry>> {
+        let (filter, values) = filter(query);
+        let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+        let offset = query.offset.unwrap_or(0);
+
+        self.database.with_connection(|connection| {
+            let total: i64 = connection.query_row(
+                &format!("SELECT COUNT(*) FROM opportunity_events {}", filter),
+                params_from_iter(values.iter()),
+                |row| row.get(0),
+            )?;
+
+            let mut statement = connection.prepare(&format!(
+                "SELECT id, kind, event_at, opportunity FROM opportunity_events {} ORDER BY event_at DESC, id DESC LIMIT {} OFFSET {}",
+                filter, limit, offset
+            ))?;
+            let items = statement
+                .query_map(params_from_iter(values.iter()), read_entry)?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            Ok(Page { items, total: total as u64, limit, offset })
+        })
+    }
+
+    /// Hand every event matching the query filters to `on_batch`, oldest first
+    /// and ignoring the pagination, and return how many there were. Events are
+    /// read a batch at a time and the database is not held while `on_batch` runs.
+    pub fn export(
+        &self,
+        query: &OpportunityHistoryQuery,
+        mut on_batch: impl FnMut(&[OpportunityHistoryEntry]) -> Result<(), String>,
+    ) -> Result<usize, String> {
+        let (filter, values) = filter(query);
+        let filter = if filter.is_empty() { "WHERE".to_string() } else { format!("{} AND", filter) };
+        let sql = format!(
+            "SELECT id, kind, event_at, opportunity FROM opportunity_events
+             {} (event_at > ? OR (event_at = ? AND id > ?)) ORDER BY event_at, id LIMIT {}",
+            filter, EXPORT_BATCH_SIZE
+        );
+
+        let mut after = (i64::MIN, i64::MIN);
+        let mut rows = 0;
+        loop {
+            let mut parameters = values.clone();
+            parameters.extend([Value::Integer(after.0), Value::Integer(after.0), Value::Integer(after.1)]);
+            let batch = self
+                .database
+                .with_connection(|connection| {
+                    connection
+                        .prepare_cached(&sql)?
+                        .query_map(params_from_iter(parameters.iter()), read_entry)?
+                        .collect::<rusqlite::Result<Vec<_>>>()
+                })
+                .map_err(|e| format!("Cannot read opportunity history: {}", e))?;
+            let Some(last) = batch.last() else {
+                return Ok(rows);
+            };
+            after = (last.event_at.timestamp_millis(), last.id);
+            rows += batch.len();
+            on_batch(&batch)?;
+            if batch.len() < EXPORT_BATCH_SIZE {
+                return Ok(rows);
+            }
+        }
+    }
+}
+
+/// WHERE clause and its parameters for the query filters
+fn filter(query: &OpportunityHistoryQuery) -> (String, Vec<Value>) {
+    let mut conditions = Vec::new();
+    let mut values: Vec<Value> = Vec::new();
+    if let Some(from) = query.from {
+        conditions.push("event_at >= ?");
+        values.push(Value::Integer(from.timestamp_millis()));
+    }
+    if let Some(to) = query.to {
+        conditions.push("event_at < ?");
+        values.push(Value::Integer(to.timestamp_millis()));
+    }
+    if let Some(symbol) = &query.symbol {
+        conditions.push("symbol = ?");
+        values.push(Value::Text(symbol.replace("-", "").to_uppercase()));
+    }
+    if let Some(exchange) = &query.exchange {
+        conditions.push("(buy_exchange = ? OR sell_exchange = ?)");
+        values.push(Value::Text(exchange.to_lowercase()));
+        values.push(Value::Text(exchange.to_lowercase()));
+    }
+    if let Some(exchange) = &query.buy_exchange {
+        conditions.push("buy_exchange = ?");
+        values.push(Value::Text(exchange.to_lowercase()));
+    }
+    if let Some(exchange) = &query.sell_exchange {
+        conditions.push("sell_exchange = ?");
+        values.push(Value::Text(exchange.to_lowercase()));
+    }
+    if let Some(kind) = query.kind {
+        conditions.push("kind = ?");
+        values.push(Value::Text(kind.name().to_string()));
+    }
+
+    if conditions.is_empty() {
+        (String::new(), values)
+    } else {
+        (format!("WHERE {}", conditions.join(" AND ")), values)
+    }
+}
+
+fn read_entry(row: &rusqlite::Row) -> rusqlite::Result<OpportunityHistoryEntry> {
+    let kind: String = row.get(1)?;
+    let opportunity: String = row.get(3)?;
+    Ok(OpportunityHistoryEntry {
+        id: row.get(0)?,
+        kind: parse_kind(&kind).ok_or_else(|| rusqlite::Error::InvalidColumnType(1, kind, rusqlite::types::Type::Text))?,
+        event_at: Utc.timestamp_millis_opt(row.get(2)?).single().unwrap_or_default(),
+        opportunity: serde_json::from_str(&opportunity)
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(3, rusqlite::types::Type::Text, Box::new(e)))?,
+    })
+}
+
+fn parse_kind(name: &str) -> Option<OpportunityEventKind> {
+    match name {
+        "opened" => Some(OpportunityEventKind::Opened),
+        "updated" => Some(OpportunityEventKind::Updated),
+        "closed" => Some(OpportunityEventKind::Closed),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+
+    use super::*;
+    use crate::alerting::rules::tests::event;
+    use crate::models::{DepegAlert, DepegAlertKind, PegStatus};
+
+    fn start() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap()
+    }
+
+    fn history() -> OpportunityHistory {
+        OpportunityHistory::new(Database::in_memory().unwrap())
+    }
+
+    fn opportunity_event(
+        kind: OpportunityEventKind,
+        id: &str,
+        buy_exchange: &str,
+        sell_exchange: &str,
+        seconds: i64,
+    ) -> OpportunityEvent {
+        let mut event = event(kind, id);
+        let opportunity = &mut event.opportunity;
+        opportunity.buy_exchange = buy_exchange.to_string();
+        opportunity.sell_exchange = sell_exchange.to_string();
+        opportunity.updated_at = start() + TimeDelta::seconds(seconds);
+        if kind == OpportunityEventKind::Closed {
+            opportunity.closed_at = Some(opportunity.updated_at);
+        }
+        event
+    }
+
+    /// binance/coinbase opened at 0s and closed at 30s, kraken/binance opened at 10s, coinbase/kraken updated at 20s
+    fn recorded() -> OpportunityHistory {
+        let history = history();
+        for event in [
+            opportunity_event(OpportunityEventKind::Opened, "first", "binance", "coinbase", 0),
+            opportunity_event(OpportunityEventKind::Opened, "second", "kraken", "binance", 10),
+            opportunity_event(OpportunityEventKind::Updated, "third", "coinbase", "kraken", 20),
+            opportunity_event(OpportunityEventKind::Closed, "first", "binance", "coinbase", 30),
+        ] {
+            history.record(&event).unwrap();
+        }
+        history
+    }
+
+    /// (opportunity id, kind) of the matching events, most recent first
+    fn matching(history: &OpportunityHistory, query: OpportunityHistoryQuery) -> Vec<(String, OpportunityEventKind)> {
+        history
+            .query(&query)
+            .unwrap()
+            .items
+            .into_iter()
+            .map(|entry| (entry.opportunity.id, entry.kind))
+            .collect()
+    }
+
+    #[test]
+    fn round_trips_the_recorded_events() {
+        let history = recorded();
+
+        let page = history.query(&OpportunityHistoryQuery::default()).unwrap();
+
+        assert_eq!(page.total, 4);
+        assert_eq!((page.limit, page.offset), (DEFAULT_PAGE_SIZE, 0));
+        let closed = &page.items[0];
+        assert_eq!(closed.kind, OpportunityEventKind::Closed);
+        assert_eq!(closed.event_at, start() + TimeDelta::seconds(30));
+        assert_eq!(closed.opportunity.closed_at, Some(closed.event_at));
+        assert_eq!(closed.opportunity.latest.expected_profit, 0.8);
+    }
+
+    #[test]
+    fn filters_by_exchange_on_either_leg() {
+        let history = recorded();
+        let query = |exchange: &str| OpportunityHistoryQuery { exchange: Some(exchange.to_string()), ..Default::default() };
+
+        assert_eq!(matching(&history, query("KRAKEN")), vec![
+            ("third".to_string(), OpportunityEventKind::Updated),
+            ("second".to_string(), OpportunityEventKind::Opened),
+        ]);
+        assert_eq!(matching(&history, query("binance")).len(), 3);
+        assert!(matching(&history, query("gemini")).is_empty());
+
+        let buying_on_binance =
+            OpportunityHistoryQuery { buy_exchange: Some("binance".to_string()), ..Default::default() };
+        assert_eq!(matching(&history, buying_on_binance).len(), 2);
+        let selling_on_kraken = OpportunityHistoryQuery { sell_exchange: Some("kraken".to_string()), ..Default::default() };
+        assert_eq!(matching(&history, selling_on_kraken), vec![("third".to_string(), OpportunityEventKind::Updated)]);
+    }
+
+    #[test]
+    fn filters_by_kind_and_symbol() {
+        let history = recorded();
+
+        let opened = OpportunityHistoryQuery { kind: Some(OpportunityEventKind::Opened), ..Default::default() };
+        assert_eq!(matching(&history, opened), vec![
+            ("second".to_string(), OpportunityEventKind::Opened),
+            ("first".to_string(), OpportunityEventKind::Opened),
+        ]);
+
+        let dashed = OpportunityHistoryQuery { symbol: Some("btc-usd".to_string()), ..Default::default() };
+        assert_eq!(matching(&history, dashed).len(), 4);
+        let other = OpportunityHistoryQuery { symbol: Some("ETHUSD".to_string()), ..Default::default() };
+        assert!(matching(&history, other).is_empty());
+    }
+
+    #[test]
+    fn filters_by_a_half_open_time_range() {
+        let history = recorded();
+        let range = OpportunityHistoryQuery {
+            from: Some(start() + TimeDelta::seconds(10)),
+            to: Some(start() + TimeDelta::seconds(30)),
+            ..Default::default()
+        };
+
+        assert_eq!(matching(&history, range), vec![
+            ("third".to_string(), OpportunityEventKind::Updated),
+            ("second".to_string(), OpportunityEventKind::Opened),
+        ]);
+    }
+
+    #[test]
+    fn paginates_with_limit_and_offset() {
+        let history = recorded();
+
+        let page = history
+            .query(&OpportunityHistoryQuery { limit: Some(2), offset: Some(1), ..Default::default() })
+            .unwrap();
+        let ids: Vec<&str> = page.items.iter().map(|entry| entry.opportunity.id.as_str()).collect();
+        assert_eq!(ids, ["third", "second"]);
+        assert_eq!((page.total, page.limit, page.offset), (4, 2, 1));
+
+        let past_the_end = history.query(&OpportunityHistoryQuery { offset: Some(4), ..Default::default() }).unwrap();
+        assert!(past_the_end.items.is_empty());
+        assert_eq!(past_the_end.total, 4);
+
+        let clamped = history.query(&OpportunityHistoryQuery { limit: Some(5_000), ..Default::default() }).unwrap();
+        assert_eq!(clamped.limit, MAX_PAGE_SIZE);
+        let at_least_one = history.query(&OpportunityHistoryQuery { limit: Some(0), ..Default::default() }).unwrap();
+        assert_eq!(at_least_one.items.len(), 1);
+    }
+
+    #[test]
+    fn exports_across_batches_oldest_first() {
+        let history = history();
+        let count = 2 * EXPORT_BATCH_SIZE + 1;
+        for index in 0..count {
+            // Several thousand events share each timestamp, so batches end in the middle of a tie
+            let seconds = (index / 3_000) as i64;
+            let sell_exchange = if index % 2 == 0 { "coinbase" } else { "kraken" };
+            history
+                .record(&opportunity_event(OpportunityEventKind::Updated, "tie", "binance", sell_exchange, seconds))
+                .unwrap();
+        }
+
+        let mut batches = Vec::new();
+        let mut previous = (DateTime::<Utc>::MIN_UTC, 0);
+        let exported = history
+            .export(&OpportunityHistoryQuery { limit: Some(1), offset: Some(5), ..Default::default() }, |batch| {
+                for entry in batch {
+                    assert!((entry.event_at, entry.id) > previous);
+                    previous = (entry.event_at, entry.id);
+                }
+                batches.push(batch.len());
+                Ok(())
+            })
+            .unwrap();
+
+        assert_eq!(exported, count);
+        assert_eq!(batches, [EXPORT_BATCH_SIZE, EXPORT_BATCH_SIZE, 1]);
+
+        let on_kraken = OpportunityHistoryQuery { sell_exchange: Some("kraken".to_string()), ..Default::default() };
+        assert_eq!(history.export(&on_kraken, |_| Ok(())).unwrap(), EXPORT_BATCH_SIZE);
+        assert_eq!(
+            history.export(&on_kraken, |_| Err("disk full".to_string())),
+            Err("disk full".to_string())
+        );
+    }
+
+    #[test]
+    fn records_the_opportunity_events_published_on_the_bus() {
+        let history = history();
+        let events = EventBus::new();
+        let writer = history.record_events(&events);
+
+        events.publish(StreamEvent::Opportunity(Box::new(opportunity_event(
+            OpportunityEventKind::Opened,
+            "published",
+            "binance",
+            "coinbase",
+            0,
+        ))));
+        events.publish(StreamEvent::DepegAlert(DepegAlert {
+            kind: DepegAlertKind::Depegged,
+            threshold_percentage: 0.5,
+            peg: PegStatus {
+                exchange: "kraken".to_string(),
+                symbol: "USDTUSD".to_string(),
+                currency: "USDT".to_string(),
+                reference_currency: "USD".to_string(),
+                price: 0.99,
+                deviation_percentage: -1.0,
+                depegged: true,
+                updated_at: start(),
+            },
+        }));
+        drop(events);
+        writer.join().unwrap();
+
+        assert_eq!(matching(&history, OpportunityHistoryQuery::default()), vec![(
+            "published".to_string(),
+            OpportunityEventKind::Opened
+        )]);
+    }
+}
@@ -0,0 +1,490 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, TimeZone, Utc};
+use rusqlite::{params, params_from_iter, types::Value, Transaction};
+use serde::Deserialize;
+
+use crate::models::{Page, QuoteResolution, QuoteSnapshot, SymbolMessage};
+use crate::orderbook::OrderBookStore;
+use crate::storage::Database;
+use crate::{log_debug, log_error, log_info};
+
+const DEFAULT_PAGE_SIZE: u32 = 500;
+const MAX_PAGE_SIZE: u32 = 10_000;
+/// Quotes read per query of an export
+const EXPORT_BATCH_SIZE: usize = 10_000;
+/// Interval at which buffered ticks are written
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+/// Interval at which quotes are downsampled and expired
+const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(10);
+/// Buckets younger than this are not downsampled yet, so that late flushes still land in them
+const DOWNSAMPLE_GRACE: Duration = Duration::from_secs(5);
+
+/// Filters and pagination of a quote history query, all optional
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct QuoteHistoryQuery {
+    pub exchange: Option<String>,
+    /// Inclusive start of the time range
+    pub from: Option<DateTime<Utc>>,
+    /// Exclusive end of the time range
+    pub to: Option<DateTime<Utc>>,
+    /// `raw`, `1s` or `1m`; defaults to the finest resolution still retained at `from`
+    pub resolution: Option<QuoteResolution>,
+    /// Page size, 500 by default and at most 10000
+    pub limit: Option<u32>,
+    pub offset: Option<u64>,
+}
+
+/// Last price, best bid and best ask
+type Quote = (f64, Option<f64>, Option<f64>);
+
+#[derive(Debug, Default)]
+struct PendingQuotes {
+    quotes: Vec<QuoteSnapshot>,
+    /// Last quote buffered per (exchange, symbol), to skip ticks that change nothing
+    last: HashMap<(String, String), Quote>,
+}
+
+/// Append-only history of the ticks and top of book of every market.
+///
+/// Ticks are buffered and written every second. Completed buckets are then
+/// rolled up from raw ticks into 1s quotes, and from 1s into 1m quotes, each
+/// keeping the last quote of its bucket and the number of ticks it covers.
+/// A bucket is rolled up again whenever a quote lands in it later, e.g. a tick
+/// flushed late or a replayed one. Every resolution is deleted once older than
+/// its retention.
+#[derive(Debug, Clone)]
+pub struct QuoteHistory {
+    database: Database,
+    order_books: OrderBookStore,
+    pending: Arc<Mutex<PendingQuotes>>,
+    /// Target resolution => last source rowid whose bucket is rolled up
+    downsampled: Arc<Mutex<HashMap<i64, i64>>>,
+    raw_retention: Duration,
+    second_retention: Duration,
+    minute_retention: Duration,
+}
+
+impl QuoteHistory {
+    pub fn new(database: Database, order_books: OrderBookStore) -> Self {
+        QuoteHistory {
+            database,
+            order_books,
+            pending: Arc::new(Mutex::new(PendingQuotes::default())),
+            downsampled: Arc::new(Mutex::new(HashMap::new())),
+            raw_retention: Duration::from_secs(3600),
+            second_retention: Duration::from_secs(24 * 3600),
+            minute_retention: Duration::from_secs(30 * 24 * 3600),
+        }
+    }
+
+    /// How long quotes of the given resolution are kept
+    pub fn with_retention(mut self, resolution: QuoteResolution, retention: Duration) -> Self {
+        match resolution {
+            QuoteResolution::Raw => self.raw_retention = retention,
+            QuoteResolution::OneSecond => self.second_retention = retention,
+            QuoteResolution::OneMinute => self.minute_retention = retention,
+        }
+        self
+    }
+
+    pub fn retention(&self, resolution: QuoteResolution) -> Duration {
+        match resolution {
+            QuoteResolution::Raw => self.raw_retention,
+            QuoteResolution::OneSecond => self.second_retention,
+            QuoteResolution::OneMinute => self.minute_retention,
+        }
+    }
+
+    /// Buffer a tick together with the current top of book of its market
+    pub fn record(&self, message: &SymbolMessage) {
+        let top = self.order_books.top_of_book(&message.exchange, &message.symbol);
+        let (bid, ask) = (top.as_ref().map(|(bid, _)| bid), top.as_ref().map(|(_, ask)| ask));
+        let key = (message.exchange.clone(), message.symbol.clone());
+        let quote = (message.price, bid.map(|bid| bid.price), ask.map(|ask| ask.price));
+
+        let mut pending = self.pending.lock().unwrap();
+        if pending.last.get(&key) == Some(&quote) {
+            return;
+        }
+        pending.last.insert(key, quote);
+        pending.quotes.push(QuoteSnapshot {
+            exchange: message.exchange.clone(),
+            symbol: message.symbol.clone(),
+            resolution: QuoteResolution::Raw,
+            at: message.received_at,
+            price: message.price,
+            bid: bid.map(|bid| bid.price),
+            bid_quantity: bid.map(|bid| bid.quantity),
+            ask: ask.map(|ask| ask.price),
+            ask_quantity: ask.map(|ask| ask.quantity),
+            samples: 1,
+        });
+    }
+
+    /// Flush, downsample and expire quotes from a background thread
+    pub fn start(&self) -> JoinHandle<()> {
+        let history = self.clone();
+        thread::spawn(move || {
+            log_info!("Recording quotes");
+            let mut last_maintenance = Instant::now();
+            loop {
+                thread::sleep(FLUSH_INTERVAL);
+                if let Err(e) = history.flush() {
+                    log_error!(error = %e, "Cannot write quotes");
+                }
+                if last_maintenance.elapsed() >= MAINTENANCE_INTERVAL {
+                    last_maintenance = Instant::now();
+                    if let Err(e) = history.maintain(Utc::now()) {
+                        log_error!(error = %e, "Cannot downsample quotes");
+                    }
+                }
+            }
+        })
+    }
+
+    /// Write the buffered ticks, returns how many were written
+    pub fn flush(&self) -> rusqlite::Result<usize> {
+        let quotes = std::mem::take(&mut self.pending.lock().unwrap().quotes);
+        if quotes.is_empty() {
+            return Ok(0);
+        }
+
+        self.database.with_connection(|connection| {
+            let transaction = connection.transaction()?;
+            {
+                let mut statement = transaction.prepare_cached(
+                    "INSERT INTO quotes (resolution_ms, exchange, symbol, at, price, bid, bid_quantity, ask, ask_quantity, samples)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                )?;
+                for quote in &quotes {
+                    statement.execute(params![
+                        quote.resolution.millis(),
+                        quote.exchange,
+                        quote.symbol,
+                        quote.at.timestamp_millis(),
+                        quote.price,
+                        quote.bid,
+                        quote.bid_quantity,
+                        quote.ask,
+                        quote.ask_quantity,
+                        quote.samples as i64,
+                    ])?;
+                }
+            }
+            transaction.commit()?;
+            Ok(quotes.len())
+        })
+    }
+
+    /// Roll completed buckets up to the coarser resolutions, then drop expired quotes
+    pub fn maintain(&self, now: DateTime<Utc>) -> rusqlite::Result<()> {
+        let complete_before = now.timestamp_millis() - DOWNSAMPLE_GRACE.as_millis() as i64;
+
+        self.database.with_connection(|connection| {
+            let transaction = connection.transaction()?;
+            let seconds = self.downsample(&transaction, QuoteResolution::Raw, QuoteResolution::OneSecond, now, complete_before)?;
+            let minutes = self.downsample(&transaction, QuoteResolution::OneSecond, QuoteResolution::OneMinute, now, complete_before)?;
+
+            let mut expired = 0;
+            for resolution in [QuoteResolution::Raw, QuoteResolution::OneSecond, QuoteResolution::OneMinute] {
+                let cutoff = now.timestamp_millis() - self.retention(resolution).as_millis() as i64;
+                expired += transaction.execute(
+                    "DELETE FROM quotes WHERE resolution_ms = ?1 AND at < ?2",
+                    params![resolution.millis(), cutoff],
+                )?;
+            }
+            transaction.commit()?;
+
+            log_debug!(seconds, minutes, expired, "Rolled up quotes");
+            Ok(())
+        })
+    }
+
+    /// Summarize the `source` quotes of every complete bucket of `target` that
+    /// received a quote since the previous run into the last quote of the bucket,
+    /// replacing the summary written before
+    fn downsample(
+        &self,
+        transaction: &Transaction,
+        source: QuoteResolution,
+        target: QuoteResolution,
+        now: DateTime<Utc>,
+        complete_before: i64,
+    ) -> rusqlite::Result<usize> {
+        let width = target.millis();
+        let end = complete_before.div_euclid(width) * width;
+        // Buckets whose oldest source quotes are expired already would lose them
+        let cutoff = now.timestamp_millis() - self.retention(source).as_millis() as i64;
+        let start = (cutoff + width - 1).div_euclid(width) * width;
+
+        let mut downsampled = self.downsampled.lock().unwrap();
+        let last_rowid: Option<i64> = transaction.query_row("SELECT MAX(rowid) FROM quotes", [], |row| row.get(0))?;
+        // Rowids restart once the table was emptied
+        let after = downsampled.get(&width).copied().filter(|after| last_rowid.is_some_and(|last| *after <= last)).unwrap_or(0);
+
+        // SQLite takes the bare columns of an aggregate query from the row holding the MAX
+        let written = transaction.execute(
+            "INSERT OR REPLACE INTO quotes (resolution_ms, exchange, symbol, at, price, bid, bid_quantity, ask, ask_quantity, samples)
+             SELECT ?1, exchange, symbol, bucket, price, bid, bid_quantity, ask, ask_quantity, samples FROM (
+                 SELECT quotes.exchange, quotes.symbol, touched.bucket, price, bid, bid_quantity, ask, ask_quantity,
+                        SUM(samples) AS samples, MAX(at)
+                 FROM (
+                     SELECT DISTINCT exchange, symbol, (at / ?1) * ?1 AS bucket FROM quotes
+                     WHERE resolution_ms = ?2 AND rowid > ?5 AND at >= ?3 AND at < ?4
+                 ) AS touched
+                 JOIN quotes ON quotes.symbol = touched.symbol AND quotes.resolution_ms = ?2
+                     AND quotes.at >= touched.bucket AND quotes.at < touched.bucket + ?1
+                     AND quotes.exchange = touched.exchange
+                 GROUP BY quotes.exchange, quotes.symbol, touched.bucket
+             )",
+            params![width, source.millis(), start, end, after],
+        )?;
+
+        // Quotes of incomplete buckets are looked at again on the next run
+        let pending: Option<i64> = transaction.query_row(
+            "SELECT MIN(rowid) FROM quotes WHERE resolution_ms = ?1 AND rowid > ?2 AND at >= ?3",
+            params![source.millis(), after, end],
+            |row| row.get(0),
+        )?;
+        let after = match pending {
+            Some(pending) => pending - 1,
+            None => last_rowid.unwrap_or(0),
+        };
+        downsampled.insert(width, after);
+        Ok(written)
+    }
+
+    /// Finest resolution still retained at `from`
+    pub fn resolution_for(&self, from: Option<DateTime<Utc>>) -> QuoteResolution {
+        let Some(from) = from else {
+            return QuoteResolution::Raw;
+        };
+        let age = (Utc::now() - from).to_std().unwrap_or_default();
+        [QuoteResolution::Raw, QuoteResolution::OneSecond]
+            .into_iter()
+            .find(|resolution| age <= self.retention(*resolution))
+            .unwrap_or(QuoteResolution::OneMinute)
+    }
+
+    /// Quotes of a symbol matching the query, oldest first
+    pub fn query(&self, symbol: &str, query: &QuoteHistoryQuery) -> rusqlite::Result<Page<QuoteSnapshot>> {
+        let resolution = query.resolution.unwrap_or_else(|| self.resolution_for(query.from));
+        let (filter, values) = filter(Some(symbol), resolution, query);
+        let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+        let offset = query.offset.unwrap_or(0);
+
+        self.database.with_connection(|connection| {
+            let total: i64 = connection.query_row(
+                &format!("SELECT COUNT(*) FROM quotes WHERE {}", filter),
+                params_from_iter(values.iter()),
+                |row| row.get(0),
+            )?;
+
+            let mut statement = connection.prepare(&format!(
+                "SELECT exchange, symbol, at, price, bid, bid_quantity, ask, ask_quantity, samples
+                 FROM quotes WHERE {} ORDER BY at, exchange LIMIT {} OFFSET {}",
+                filter, limit, offset
+            ))?;
+            let items = statement
+                .query_map(params_from_iter(values.iter()), |row| read_quote(row, resolution))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            Ok(Page { items, total: total as u64, limit, offset })
+        })
+    }
+
+    /// Hand every quote matching the query filters, of one symbol or all of
+    /// them, to `on_batch`, oldest first and ignoring the pagination, and return
+    /// how many there were. Quotes are read a batch at a time and the database
+    /// is not held while `on_batch` runs.
+    pub fn export(
+        &self,
+        symbol: Option<&str>,
+        query: &QuoteHistoryQuery,
+        mut on_batch: impl FnMut(&[QuoteSnapshot]) -> Result<(), String>,
+    ) -> Result<usize, String> {
+        let resolution = query.resolution.unwrap_or_else(|| self.resolution_for(query.from));
+        let (filter, values) = filter(symbol, resolution, query);
+        let sql = format!(
+            "SELECT exchange, symbol, at, price, bid, bid_quantity, ask, ask_quantity, samples, rowid
+             FROM quotes WHERE {} AND (at > ? OR (at = ? AND rowid > ?)) ORDER BY at, rowid LIMIT {}",
+            filter, EXPORT_BATCH_SIZE
+        );
+
+        let mut after = (i64::MIN, i64::MIN);
+        let mut rows = 0;
+        loop {
+            let mut parameters = values.clone();
+            parameters.extend([Value::Integer(after.0), Value::Integer(after.0), Value::Integer(after.1)]);
+            let batch = self
+                .database
+                .with_connection(|connection| {
+                    connection
+                        .prepare_cached(&sql)?
+                        .query_map(params_from_iter(parameters.iter()), |row| {
+                            Ok((row.get::<_, i64>(2)?, row.get::<_, i64>(9)?, read_quote(row, resolution)?))
+                        })?
+                        .collect::<rusqlite::Result<Vec<_>>>()
+                })
+                .map_err(|e| format!("Cannot read quote history: {}", e))?;
+            let Some((at, rowid, _)) = batch.last() else {
+                return Ok(rows);
+            };
+            after = (*at, *rowid);
+            let quotes: Vec<QuoteSnapshot> = batch.into_iter().map(|(_, _, quote)| quote).collect();
+            rows += quotes.len();
+            on_batch(&quotes)?;
+            if quotes.len() < EXPORT_BATCH_SIZE {
+                return Ok(rows);
+            }
+        }
+    }
+}
+
+/// WHERE conditions and their parameters for the query filters
+fn filter(symbol: Option<&str>, resolution: QuoteResolution, query: &QuoteHistoryQuery) -> (String, Vec<Value>) {
+    let mut conditions = vec!["resolution_ms = ?"];
+    let mut values = vec![Value::Integer(resolution.millis())];
+    if let Some(symbol) = symbol {
+        conditions.push("symbol = ?");
+        values.push(Value::Text(symbol.replace("-", "").to_uppercase()));
+    }
+    if let Some(exchange) = &query.exchange {
+        conditions.push("exchange = ?");
+        values.push(Value::Text(exchange.to_lowercase()));
+    }
+    if let Some(from) = query.from {
+        conditions.push("at >= ?");
+        values.push(Value::Integer(from.timestamp_millis()));
+    }
+    if let Some(to) = query.to {
+        conditions.push("at < ?");
+        values.push(Value::Integer(to.timestamp_millis()));
+    }
+    (conditions.join(" AND "), values)
+}
+
+fn read_quote(row: &rusqlite::Row, resolution: QuoteResolution) -> rusqlite::Result<QuoteSnapshot> {
+    Ok(QuoteSnapshot {
+        exchange: row.get(0)?,
+        symbol: row.get(1)?,
+        resolution,
+        at: Utc.timestamp_millis_opt(row.get(2)?).single().unwrap_or_default(),
+        price: row.get(3)?,
+        bid: row.get(4)?,
+        bid_quantity: row.get(5)?,
+        ask: row.get(6)?,
+        ask_quantity: row.get(7)?,
+        samples: row.get::<_, i64>(8)? as u64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+
+    use super::*;
+
+    fn start() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap()
+    }
+
+    fn record(history: &QuoteHistory, exchange: &str, price: f64, millis: i64) {
+        let mut message = SymbolMessage::new(exchange, "BTCUSD".to_string(), price);
+        message.received_at = start() + TimeDelta::milliseconds(millis);
+        history.record(&message);
+        history.flush().unwrap();
+    }
+
+    /// (exchange, price, samples) of every quote of the resolution
+    fn quotes(history: &QuoteHistory, resolution: QuoteResolution) -> Vec<(String, f64, u64)> {
+        let query = QuoteHistoryQuery { resolution: Some(resolution), ..Default::default() };
+        history
+            .query("BTCUSD", &query)
+            .unwrap()
+            .items
+            .into_iter()
+            .map(|quote| (quote.exchange, quote.price, quote.samples))
+            .collect()
+    }
+
+    #[test]
+    fn rolls_up_late_ticks_into_their_buckets() {
+        let history = QuoteHistory::new(Database::in_memory().unwrap(), OrderBookStore::new());
+        record(&history, "coinbase", 100.0, 100);
+        record(&history, "coinbase", 101.0, 200);
+        record(&history, "gemini", 102.0, 1_500);
+        history.maintain(start() + TimeDelta::seconds(10)).unwrap();
+        assert_eq!(
+            quotes(&history, QuoteResolution::OneSecond),
+            vec![("coinbase".to_string(), 101.0, 2), ("gemini".to_string(), 102.0, 1)]
+        );
+
+        // Landing after the buckets of both markets were rolled up
+        record(&history, "coinbase", 103.0, 300);
+        record(&history, "coinbase", 104.0, 8_000);
+        history.maintain(start() + TimeDelta::seconds(20)).unwrap();
+        assert_eq!(
+            quotes(&history, QuoteResolution::OneSecond),
+            vec![
+                ("coinbase".to_string(), 103.0, 3),
+                ("gemini".to_string(), 102.0, 1),
+                ("coinbase".to_string(), 104.0, 1),
+            ]
+        );
+
+        history.maintain(start() + TimeDelta::seconds(70)).unwrap();
+        record(&history, "gemini", 105.0, 2_000);
+        history.maintain(start() + TimeDelta::seconds(80)).unwrap();
+        assert_eq!(
+            quotes(&history, QuoteResolution::OneMinute),
+            vec![("coinbase".to_string(), 104.0, 4), ("gemini".to_string(), 105.0, 2)]
+        );
+    }
+
+    #[test]
+    fn exports_in_batches_oldest_first() {
+        let history = QuoteHistory::new(Database::in_memory().unwrap(), OrderBookStore::new());
+        let total = 2 * EXPORT_BATCH_SIZE + 1;
+        for i in 0..total {
+            // Several ticks share each millisecond
+            let mut message = SymbolMessage::new("coinbase", "BTCUSD".to_string(), 100.0 + i as f64);
+            message.received_at = start() + TimeDelta::milliseconds(i as i64 / 3);
+            history.record(&message);
+        }
+        history.flush().unwrap();
+
+        let query = QuoteHistoryQuery { resolution: Some(QuoteResolution::Raw), ..Default::default() };
+        let mut batches = Vec::new();
+        let mut prices = Vec::new();
+        let exported = history
+            .export(Some("BTCUSD"), &query, |batch| {
+                batches.push(batch.len());
+                prices.extend(batch.iter().map(|quote| quote.price));
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(exported, total);
+        assert_eq!(batches, vec![EXPORT_BATCH_SIZE, EXPORT_BATCH_SIZE, 1]);
+        assert!(prices.windows(2).all(|pair| pair[0] < pair[1]));
+
+        let failed = history.export(None, &query, |_| Err("disk full".to_string()));
+        assert_eq!(failed, Err("disk full".to_string()));
+    }
+
+    #[test]
+    fn leaves_incomplete_buckets_for_a_later_run() {
+        let history = QuoteHistory::new(Database::in_memory().unwrap(), OrderBookStore::new());
+        record(&history, "coinbase", 100.0, 9_000);
+        history.maintain(start() + TimeDelta::seconds(12)).unwrap();
+        assert!(quotes(&history, QuoteResolution::OneSecond).is_empty());
+
+        record(&history, "coinbase", 101.0, 9_500);
+        history.maintain(start() + TimeDelta::seconds(15)).unwrap();
+        assert_eq!(quotes(&history, QuoteResolution::OneSecond), vec![("coinbase".to_string(), 101.0, 2)]);
+    }
+}